@@ -0,0 +1,1044 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Command-line argument parsing.
+//_____________________________________________________________________________
+
+// External imports.
+use genetic_ode::data::{Cleaning, Column, Duplicates, Format, Missing};
+use genetic_ode::data::Unordered;
+use genetic_ode::integrate::{BackwardEuler, DormandPrince, Euler, Midpoint};
+use genetic_ode::integrate::{Rk4, TrBdf2};
+use genetic_ode::metric::{Area, Huber, Mae, MaxError, Mse, Relative, Rmse};
+use genetic_ode::render::Target;
+use genetic_ode::operator::BUILTIN_TOKENS;
+use genetic_ode::population::{Evaluation, Limits, Overflow, TIME_STEP};
+use genetic_ode::preprocess::{MovingAverage, Resample, SavitzkyGolay};
+use genetic_ode::preprocess::{SmoothingSpline, TotalVariation};
+use genetic_ode::selection::{Exponential, Lexicase, Rank, Roulette};
+use genetic_ode::selection::{DoubleTournament, Tournament};
+use genetic_ode::{Aggregate, Crossover, FitnessMetric, Integrator};
+use genetic_ode::{InitialCondition, InitialVelocity, Mutation, Order};
+use genetic_ode::{Differentiation, Pipeline, Preprocessor};
+use genetic_ode::{Initialization, Initializer};
+use genetic_ode::{Sampling, Selection, State, Tolerance};
+
+// Operators enabled when none are specified on the command line.
+const DEFAULT_OPERATORS: [&str; 10] = [
+    "ADD", "SUB", "MUL", "DIV", "SQUARE", "SQRT", "EXP", "LN", "ONE", "PI",
+];
+
+pub const USAGE: &str = "\
+Usage: genetic_ode <COMMAND> [OPTIONS]
+
+Commands:
+    fit         Evolve a population and print the leaderboard per generation.
+    eval        Fit, then print the best individual's residuals at each data
+                point. With --expr, the given expressions are evaluated
+                instead.
+    simulate    Fit, then print the trajectory of the best individual. With
+                --expr, the given expressions are simulated instead.
+    help        Print this message.
+
+Options:
+    --data <FILE>          CSV, TSV or whitespace separated data file. Lines
+                           starting with # are ignored and a header row is
+                           detected automatically. May be repeated to fit
+                           several trajectories of the same system, each
+                           simulated from its own initial state. Defaults to
+                           a sampled logistic curve.
+    --missing <MODE>       Data points with missing (empty, NA, N/A, null, ?)
+                           or non-finite values, one of skip, interpolate
+                           (linearly in time) or error [default: error].
+    --unordered <MODE>     Data points out of time order, one of sort or
+                           error [default: sort].
+    --duplicates <MODE>    Data points at the same time, one of mean, first,
+                           last or error [default: mean].
+    --time-column <COL>    Name or zero-based index of the time column
+                           [default: 0].
+    --position-column <COL>
+                           Name or zero-based index of the position column
+                           [default: 1]. Several comma separated columns fit a
+                           system of coupled ODEs, where the state variables
+                           after the first have tokens POS1, POS2, ...
+    --delimiter <CHAR>     Field delimiter, one of , tab ; or space
+                           [default: detected].
+    --size <N>             Population size [default: 300].
+    --generations <N>      Number of generations [default: 15].
+    --step <DT>            Time step used in simulation, or the initial step
+                           of the adaptive rk45 integrator [default: 0.01].
+    --integrator <NAME>    Integration scheme, one of euler, midpoint, rk4,
+                           rk45 (adaptive Dormand-Prince), backward-euler or
+                           tr-bdf2, the last two being implicit for stiff
+                           candidates [default: rk4, or rk45 given --rtol or
+                           --atol].
+    --rtol <TOL>           Relative error tolerance of rk45 [default: 1e-6].
+    --atol <TOL>           Absolute error tolerance of rk45 [default: 1e-9].
+    --metric <NAME>        Fitness metric, one of area (between the simulated
+                           and observed trajectories), mse, rmse, mae, huber,
+                           max or relative [default: area].
+    --huber-delta <D>      Residual at which the huber metric turns from
+                           quadratic to linear [default: 1].
+    --sampling <MODE>      Where trajectories are compared against the data,
+                           one of steps (every integration step, against the
+                           interpolated data) or data (exactly at each data
+                           time) [default: steps].
+    --evaluation <MODE>    How individuals are tested, one of integrate
+                           (simulate and compare with the data), gradient
+                           (compare the expressions with derivatives of the
+                           data, much faster) or screen (gradient match each
+                           generation, then simulate the best
+                           --screen-fraction) [default: integrate].
+    --screen-fraction <F>  Fraction of each generation simulated by screen
+                           [default: 0.2].
+    --differentiation <NAME>
+                           How derivatives of the data are estimated for
+                           gradient matching, one of finite-difference,
+                           savitzky-golay (a least squares polynomial of
+                           --degree through --window points, smoothing
+                           noise) or total-variation (regularized by
+                           --tv-alpha, for derivatives with jumps)
+                           [default: finite-difference].
+    --window <N>           Data points fit by savitzky-golay [default: 7].
+    --degree <N>           Degree of the savitzky-golay polynomial
+                           [default: 2].
+    --tv-alpha <A>         Weight of total-variation regularization
+                           [default: 0.01].
+    --preprocess <STAGE>   Preprocess the data before fitting, one of
+                           moving-average:<W>, savitzky-golay:<W>:<D>,
+                           total-variation:<A>, spline:<LAMBDA> (a cubic
+                           smoothing spline) or resample:<N> (onto N evenly
+                           spaced times). May be repeated to apply stages in
+                           turn.
+    --init <METHOD>        How the expressions of the initial population are
+                           generated, one of grow (leaves and operators drawn
+                           at random), full (every leaf at the greatest
+                           depth) or ramped (half by each, the depth of each
+                           drawn evenly between the least and greatest)
+                           [default: ramped].
+    --init-min-depth <N>   Least depth of initial expressions, above which
+                           only operators are drawn [default: 1].
+    --init-max-depth <N>   Greatest depth of initial expressions
+                           [default: 4].
+    --node-weights <T,V,C,O>
+                           Relative probability of drawing time, a state
+                           variable, a constant or an operator when growing
+                           expressions [default: 1,1,1,2].
+    --selection <NAME>     How parents are chosen, one of exponential (closest
+                           to the best fitness plus an exponential offset),
+                           tournament (best of --tournament-size at random),
+                           roulette (proportional to 1 / (1 + fitness - best
+                           fitness)), rank (linear ranking with
+                           --rank-pressure), lexicase (epsilon lexicase
+                           over the error at each data point) or
+                           double-tournament (a tournament whose entrants
+                           win tournaments on size, see --size-tournament)
+                           [default: exponential].
+    --tournament-size <N>  Individuals in each tournament [default: 7].
+    --rank-pressure <P>    Times the best individual is chosen as often as
+                           the median, in [1, 2] [default: 1.5].
+    --size-tournament <D>  Twice the probability the smaller individual wins
+                           each size tournament of double-tournament, in
+                           [1, 2] [default: 1.4].
+    --max-depth <N>        Depth of new expressions allowed, from zero for a
+                           single leaf [default: unlimited].
+    --max-size <N>         Operators in new expressions allowed [default:
+                           unlimited].
+    --retries <N>          Times crossover and mutation are retried for
+                           offspring exceeding the limits, before the first
+                           parent is copied instead [default: 0].
+    --parsimony <C>        Fitness added per operator of each individual,
+                           favouring smaller ones [default: 0].
+    --crossover <NAME>     How offspring are formed, one of subtree (uniformly
+                           random subtrees), koza (subtrees rooted at
+                           internal nodes with probability --koza-internal),
+                           one-point (homologous, at the same position in
+                           the region where the parents have the same
+                           shape), size-fair (the subtree inserted at most
+                           1 + twice the size of that replaced) or
+                           depth-limited (retried until offspring are at
+                           most --crossover-depth deep) [default: subtree].
+    --koza-internal <P>    Probability koza crossover roots subtrees at
+                           internal nodes [default: 0.9].
+    --crossover-depth <N>  Depth of offspring allowed by depth-limited
+                           crossover [default: 17].
+    --mutation <KIND>:<P>  Probability of mutating offspring by a kind of
+                           mutation, one of point (swap an operator for one
+                           of equal arity), subtree (replace a subtree with
+                           a new expression), hoist (keep only a subtree),
+                           shrink (replace a subtree with a leaf), constant
+                           (perturb a constant by Gaussian noise), insert
+                           (wrap a subtree in a unary operator) or delete
+                           (remove a unary operator). May be repeated; kinds
+                           not given are not applied [default: shrink:1].
+    --mutation-sigma <S>   Standard deviation of constant mutations relative
+                           to the magnitude of the constant [default: 0.1].
+    --aggregate <MODE>     How the fitness of several trajectories is
+                           combined, one of sum, mean or worst
+                           [default: sum].
+    --operators <LIST>     Comma separated operator tokens to enable
+                           [default: ADD,SUB,MUL,DIV,SQUARE,SQRT,EXP,LN,ONE,PI].
+                           Available: ADD,SUB,MUL,DIV,SQUARE,SQRT,SIN,COS,TAN,
+                           EXP,LN,ONE,PI.
+    --expr <EXPR>          Expression in prefix (e.g. MUL 2 TIME) or infix
+                           (e.g. 2 * TIME) notation. For systems, give one
+                           expression per state variable separated by ;.
+                           May be repeated. Seeds the initial population of
+                           fit.
+    --seed <N>             Seed of the random number generator. Runs with the
+                           same seed and options are identical [default:
+                           random, printed at the start of the run].
+    --top <N>              Individuals printed per generation [default: 10].
+    --notation <NOTATION>  Notation expressions are printed in, one of prefix,
+                           infix, latex or mathml [default: prefix].
+    --precision <N>        Significant digits of constants printed in infix,
+                           latex or mathml notation [default: all].
+    --order <N>            Order of the ODE, 1 for x' = f(x, t) or 2 for
+                           x'' = f(x, x', t) [default: 1]. Velocities of
+                           second-order ODEs have tokens VEL, VEL1, ...
+    --initial-velocity <MODE>
+                           Initial velocity of second-order ODEs, one of
+                           estimate (from the data) or evolve (as part of
+                           each individual) [default: estimate].
+    --initial-condition <MODE>
+                           Where simulations start, one of first (the first
+                           data point), smoothed (a quadratic fit of the
+                           first --initial-points data points) or optimize
+                           (fit for each individual by line search)
+                           [default: first].
+    --initial-points <N>   Data points fit by smoothed [default: 5].
+    --simplify             Simplify expressions as they are created, folding
+                           constants and removing redundant arithmetic.
+    --dense                Also print internal steps when simulating.";
+
+//_____________________________________________________________________________
+//                                                         Command & Options
+
+// Integration schemes selectable with --integrator.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Method {
+    Euler,
+    Midpoint,
+    Rk4,
+    Rk45,
+    BackwardEuler,
+    TrBdf2,
+}
+
+// Fitness metrics selectable with --metric.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Metric {
+    Area,
+    Mse,
+    Rmse,
+    Mae,
+    Huber,
+    Max,
+    Relative,
+}
+
+// Evaluation modes selectable with --evaluation.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Mode {
+    Integrate,
+    Gradient,
+    Screen,
+}
+
+// Parent selections selectable with --selection.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Scheme {
+    Exponential,
+    Tournament,
+    Roulette,
+    Rank,
+    Lexicase,
+    DoubleTournament,
+}
+
+// Crossovers selectable with --crossover.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Recombination {
+    Subtree,
+    Koza,
+    OnePoint,
+    SizeFair,
+    DepthLimited,
+}
+
+// Derivative estimates selectable with --differentiation.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Derivative {
+    FiniteDifference,
+    SavitzkyGolay,
+    TotalVariation,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Command {
+    Fit,
+    Eval,
+    Simulate,
+    Help,
+}
+
+pub struct Options {
+    pub data: Vec<String>,
+    pub format: Format,
+    pub cleaning: Cleaning,
+    pub size: usize,
+    pub generations: u64,
+    pub step: f64,
+    // Integration scheme, None to choose by whether a tolerance is given.
+    pub integrator: Option<Method>,
+    pub tolerance: Option<Tolerance>,
+    pub metric: Metric,
+    pub huber_delta: f64,
+    pub sampling: Sampling,
+    pub aggregate: Aggregate,
+    pub evaluation: Mode,
+    pub screen_fraction: f64,
+    pub differentiation: Derivative,
+    pub window: usize,
+    pub degree: usize,
+    pub tv_alpha: f64,
+    pub preprocess: Pipeline,
+    pub selection: Scheme,
+    pub tournament_size: usize,
+    pub rank_pressure: f64,
+    pub size_tournament: f64,
+    pub initializer: Initializer,
+    pub limits: Limits,
+    pub parsimony: f64,
+    pub crossover: Recombination,
+    pub koza_internal: f64,
+    pub crossover_depth: usize,
+    pub mutation: Mutation,
+    pub operators: Vec<&'static str>,
+    pub exprs: Vec<String>,
+    pub seed: Option<u64>,
+    pub top: usize,
+    // Notation expressions are printed in, None for prefix.
+    pub notation: Option<Target>,
+    pub precision: Option<usize>,
+    pub order: Order,
+    pub initial_velocity: InitialVelocity,
+    pub initial_condition: InitialCondition,
+    pub simplify: bool,
+    pub dense: bool,
+}
+
+impl Options {
+    /* integrator
+    * Create the integrator selected by the options.
+    */
+    pub fn integrator(&self) -> Box<dyn Integrator> {
+        let method = match (self.integrator, self.tolerance) {
+            (Some(method), _) => method,
+            (None, Some(_)) => Method::Rk45,
+            (None, None) => Method::Rk4,
+        };
+        return match method {
+            Method::Euler => Box::new(Euler),
+            Method::Midpoint => Box::new(Midpoint),
+            Method::Rk4 => Box::new(Rk4),
+            Method::Rk45 => Box::new(
+                DormandPrince::new(self.tolerance.unwrap_or_default())),
+            Method::BackwardEuler => Box::new(BackwardEuler),
+            Method::TrBdf2 => Box::new(TrBdf2),
+        };
+    }
+
+    /* metric
+    * Create the fitness metric selected by the options.
+    */
+    pub fn metric(&self) -> Box<dyn FitnessMetric> {
+        return match self.metric {
+            Metric::Area => Box::new(Area),
+            Metric::Mse => Box::new(Mse),
+            Metric::Rmse => Box::new(Rmse),
+            Metric::Mae => Box::new(Mae),
+            Metric::Huber => Box::new(Huber::new(self.huber_delta)),
+            Metric::Max => Box::new(MaxError),
+            Metric::Relative => Box::new(Relative),
+        };
+    }
+
+    /* evaluation
+    * Get how individuals are tested, as selected by the options.
+    */
+    pub fn evaluation(&self) -> Evaluation {
+        return match self.evaluation {
+            Mode::Integrate => Evaluation::Integrate,
+            Mode::Gradient => Evaluation::Gradient,
+            Mode::Screen => Evaluation::Screen(self.screen_fraction),
+        };
+    }
+
+    /* differentiation
+    * Get how derivatives of the data are estimated, as selected by the 
+    * options.
+    */
+    pub fn differentiation(&self) -> Differentiation {
+        return match self.differentiation {
+            Derivative::FiniteDifference => Differentiation::FiniteDifference,
+            Derivative::SavitzkyGolay => Differentiation::SavitzkyGolay {
+                window: self.window,
+                degree: self.degree,
+            },
+            Derivative::TotalVariation => 
+                Differentiation::TotalVariation {alpha: self.tv_alpha},
+        };
+    }
+
+    /* selection
+    * Create the parent selection selected by the options.
+    */
+    pub fn selection(&self) -> Box<dyn Selection> {
+        return match self.selection {
+            Scheme::Exponential => Box::new(Exponential::default()),
+            Scheme::Tournament => 
+                Box::new(Tournament::new(self.tournament_size)),
+            Scheme::Roulette => Box::new(Roulette),
+            Scheme::Rank => Box::new(Rank::new(self.rank_pressure)),
+            Scheme::Lexicase => Box::new(Lexicase),
+            Scheme::DoubleTournament => Box::new(DoubleTournament::new(
+                self.tournament_size, self.size_tournament)),
+        };
+    }
+
+    /* crossover
+    * Get how offspring are formed, as selected by the options.
+    */
+    pub fn crossover(&self) -> Crossover {
+        return match self.crossover {
+            Recombination::Subtree => Crossover::Subtree,
+            Recombination::Koza => Crossover::Koza(self.koza_internal),
+            Recombination::OnePoint => Crossover::OnePoint,
+            Recombination::SizeFair => Crossover::SizeFair,
+            Recombination::DepthLimited => 
+                Crossover::DepthLimited(self.crossover_depth),
+        };
+    }
+
+    fn new() -> Options {
+        return Options {
+            data: Vec::new(),
+            format: Format::new(),
+            cleaning: Cleaning::new(),
+            size: 300,
+            generations: 15,
+            step: TIME_STEP,
+            integrator: None,
+            tolerance: None,
+            metric: Metric::Area,
+            huber_delta: 1.0,
+            sampling: Sampling::Steps,
+            aggregate: Aggregate::Sum,
+            evaluation: Mode::Integrate,
+            screen_fraction: 0.2,
+            differentiation: Derivative::FiniteDifference,
+            window: 7,
+            degree: 2,
+            tv_alpha: 0.01,
+            preprocess: Pipeline::new(),
+            selection: Scheme::Exponential,
+            tournament_size: 7,
+            rank_pressure: 1.5,
+            size_tournament: 1.4,
+            initializer: Initializer::new(),
+            limits: Limits::new(),
+            parsimony: 0.0,
+            crossover: Recombination::Subtree,
+            koza_internal: 0.9,
+            crossover_depth: 17,
+            mutation: Mutation::new(),
+            operators: DEFAULT_OPERATORS.to_vec(),
+            exprs: Vec::new(),
+            seed: None,
+            top: 10,
+            notation: None,
+            precision: None,
+            order: Order::First,
+            initial_velocity: InitialVelocity::Estimate,
+            initial_condition: InitialCondition::First,
+            simplify: false,
+            dense: false,
+        };
+    }
+}
+
+/* parse
+* Parse the command and options from the given arguments.
+* Input:
+*     args - Command-line arguments, excluding the program name.
+* Output:
+*     The command and options, or a message describing the invalid argument.
+*/
+pub fn parse<I>(args: I) -> Result<(Command, Options), String>
+    where I: IntoIterator<Item = String> {
+    let mut args = args.into_iter();
+
+    let command = match args.next().as_deref() {
+        Some("fit") => Command::Fit,
+        Some("eval") => Command::Eval,
+        Some("simulate") => Command::Simulate,
+        Some("help") | Some("-h") | Some("--help") | None => Command::Help,
+        Some(other) => return Err(format!("Unknown command {}.", other)),
+    };
+
+    let mut options = Options::new();
+    let mut initial_points = 5;
+    let mut mutation_given = false;
+
+    while let Some(flag) = args.next() {
+        // All flags other than --dense and --simplify take a value.
+        if flag == "--dense" {
+            options.dense = true;
+            continue;
+        }
+        if flag == "--simplify" {
+            options.simplify = true;
+            continue;
+        }
+        if flag == "-h" || flag == "--help" {
+            return Ok((Command::Help, options));
+        }
+
+        let value = match args.next() {
+            Some(value) => value,
+            None => return Err(format!("Missing value for {}.", flag)),
+        };
+
+        match flag.as_str() {
+            "--data" => options.data.push(value),
+            "--missing" => options.cleaning.missing = parse_missing(&value)?,
+            "--unordered" => 
+                options.cleaning.unordered = parse_unordered(&value)?,
+            "--duplicates" => 
+                options.cleaning.duplicates = parse_duplicates(&value)?,
+            "--time-column" => options.format.time = Column::parse(&value),
+            "--position-column" => options.format.positions =
+                value.split(',').map(Column::parse).collect(),
+            "--delimiter" =>
+                options.format.delimiter = Some(parse_delimiter(&value)?),
+            "--size" => options.size = parse_value(&flag, &value)?,
+            "--generations" =>
+                options.generations = parse_value(&flag, &value)?,
+            "--step" => options.step = parse_value(&flag, &value)?,
+            "--integrator" => options.integrator = Some(parse_method(&value)?),
+            "--metric" => options.metric = parse_metric(&value)?,
+            "--sampling" => options.sampling = parse_sampling(&value)?,
+            "--aggregate" => options.aggregate = parse_aggregate(&value)?,
+            "--evaluation" => options.evaluation = parse_evaluation(&value)?,
+            "--screen-fraction" => 
+                options.screen_fraction = parse_value(&flag, &value)?,
+            "--differentiation" => 
+                options.differentiation = parse_differentiation(&value)?,
+            "--window" => options.window = parse_value(&flag, &value)?,
+            "--degree" => options.degree = parse_value(&flag, &value)?,
+            "--tv-alpha" => options.tv_alpha = parse_value(&flag, &value)?,
+            "--preprocess" => options.preprocess.push(parse_stage(&value)?),
+            "--selection" => options.selection = parse_selection(&value)?,
+            "--tournament-size" => 
+                options.tournament_size = parse_value(&flag, &value)?,
+            "--rank-pressure" => 
+                options.rank_pressure = parse_value(&flag, &value)?,
+            "--init" => 
+                options.initializer.method = parse_initialization(&value)?,
+            "--init-min-depth" => 
+                options.initializer.min_depth = parse_value(&flag, &value)?,
+            "--init-max-depth" => 
+                options.initializer.max_depth = parse_value(&flag, &value)?,
+            "--node-weights" => {
+                let weights = parse_weights(&value)?;
+                options.initializer.time = weights[0];
+                options.initializer.variable = weights[1];
+                options.initializer.constant = weights[2];
+                options.initializer.operator = weights[3];
+            },
+            "--size-tournament" => 
+                options.size_tournament = parse_value(&flag, &value)?,
+            "--max-depth" => 
+                options.limits.depth = Some(parse_value(&flag, &value)?),
+            "--max-size" => 
+                options.limits.size = Some(parse_value(&flag, &value)?),
+            "--retries" => options.limits.overflow = 
+                match parse_value(&flag, &value)? {
+                    0 => Overflow::Reject,
+                    retries => Overflow::Retry(retries),
+                },
+            "--parsimony" => options.parsimony = parse_value(&flag, &value)?,
+            "--crossover" => options.crossover = parse_crossover(&value)?,
+            "--koza-internal" => 
+                options.koza_internal = parse_value(&flag, &value)?,
+            "--crossover-depth" => 
+                options.crossover_depth = parse_value(&flag, &value)?,
+            "--mutation" => {
+                // Giving any kind replaces the default.
+                if !mutation_given {
+                    options.mutation = Mutation {
+                        sigma: options.mutation.sigma, 
+                        ..Mutation::none()
+                    };
+                    mutation_given = true;
+                }
+                parse_mutation(&value, &mut options.mutation)?;
+            },
+            "--mutation-sigma" => 
+                options.mutation.sigma = parse_value(&flag, &value)?,
+            "--huber-delta" => 
+                options.huber_delta = parse_value(&flag, &value)?,
+            "--rtol" => options.tolerance.get_or_insert_with(Tolerance::default)
+                .relative = parse_value(&flag, &value)?,
+            "--atol" => options.tolerance.get_or_insert_with(Tolerance::default)
+                .absolute = parse_value(&flag, &value)?,
+            "--operators" => options.operators = parse_operators(&value)?,
+            "--expr" => options.exprs.push(value),
+            "--seed" => options.seed = Some(parse_value(&flag, &value)?),
+            "--top" => options.top = parse_value(&flag, &value)?,
+            "--notation" => options.notation = parse_notation(&value)?,
+            "--precision" =>
+                options.precision = Some(parse_value(&flag, &value)?),
+            "--order" => options.order = parse_order(&value)?,
+            "--initial-velocity" => 
+                options.initial_velocity = parse_initial_velocity(&value)?,
+            "--initial-condition" => options.initial_condition = 
+                parse_initial_condition(&value, initial_points)?,
+            "--initial-points" => {
+                initial_points = parse_value(&flag, &value)?;
+                // Points may be given before or after the mode.
+                if let InitialCondition::Smoothed(points) = 
+                    &mut options.initial_condition {
+                    *points = initial_points;
+                }
+            },
+            _ => return Err(format!("Unknown option {}.", flag)),
+        }
+    }
+
+    if options.size == 0 {
+        return Err(String::from("Population size must be positive."));
+    }
+    if !options.step.is_finite() || options.step <= 0.0 {
+        return Err(String::from("Time step must be positive and finite."));
+    }
+    if options.huber_delta.is_nan() || options.huber_delta <= 0.0 {
+        return Err(String::from("Huber delta must be positive."));
+    }
+    if !(options.screen_fraction > 0.0 && options.screen_fraction <= 1.0) {
+        return Err(String::from("Screen fraction must be in (0, 1]."));
+    }
+    if options.degree == 0 || options.window <= options.degree {
+        return Err(String::from(
+            "Degree must be positive and less than the window."));
+    }
+    if options.order == Order::Second && options.degree < 2 
+        && options.differentiation == Derivative::SavitzkyGolay {
+        return Err(String::from(
+            "Degree must be at least 2 to estimate accelerations."));
+    }
+    if options.tournament_size == 0 {
+        return Err(String::from("Tournament size must be positive."));
+    }
+    if !(1.0..=2.0).contains(&options.rank_pressure) {
+        return Err(String::from("Rank pressure must be in [1, 2]."));
+    }
+    if !(1.0..=2.0).contains(&options.size_tournament) {
+        return Err(String::from("Size tournament must be in [1, 2]."));
+    }
+    if options.initializer.min_depth > options.initializer.max_depth {
+        return Err(String::from(
+            "Initial minimum depth must be at most the maximum depth."));
+    }
+    if options.limits.size == Some(0) {
+        return Err(String::from("Maximum size must be positive."));
+    }
+    if options.parsimony.is_nan() || options.parsimony < 0.0 {
+        return Err(String::from("Parsimony must be non-negative."));
+    }
+    if !(0.0..=1.0).contains(&options.koza_internal) {
+        return Err(String::from(
+            "Koza internal probability must be in [0, 1]."));
+    }
+    let mutation = options.mutation;
+    let total = mutation.point + mutation.subtree + mutation.hoist 
+        + mutation.shrink + mutation.constant + mutation.insert 
+        + mutation.delete;
+    if total > 1.0 {
+        return Err(String::from(
+            "Mutation probabilities must sum to at most 1."));
+    }
+    if mutation.sigma.is_nan() || mutation.sigma <= 0.0 {
+        return Err(String::from("Mutation sigma must be positive."));
+    }
+    if options.tv_alpha.is_nan() || options.tv_alpha < 0.0 {
+        return Err(String::from("Total variation alpha must be non-negative."));
+    }
+    if let Some(tolerance) = options.tolerance {
+        if !(tolerance.relative >= 0.0 && tolerance.absolute >= 0.0)
+            || tolerance.relative + tolerance.absolute == 0.0 {
+            return Err(String::from(
+                "Tolerances must be non-negative and not both zero."));
+        }
+    }
+
+    return Ok((command, options));
+}
+
+/* parse_value
+* Parse the value of a flag, reporting the flag on failure.
+*/
+fn parse_value<T>(flag: &str, value: &str) -> Result<T, String>
+    where T: std::str::FromStr {
+    return value.parse()
+        .map_err(|_| format!("Invalid value {} for {}.", value, flag));
+}
+
+/* parse_notation
+* Parse the notation expressions are printed in.
+*/
+fn parse_notation(value: &str) -> Result<Option<Target>, String> {
+    return match value {
+        "prefix" => Ok(None),
+        "infix" => Ok(Some(Target::Infix)),
+        "latex" => Ok(Some(Target::Latex)),
+        "mathml" => Ok(Some(Target::MathMl)),
+        _ => Err(format!("Unknown notation {}.", value)),
+    };
+}
+
+/* parse_method
+* Parse the name of an integration scheme.
+*/
+fn parse_method(value: &str) -> Result<Method, String> {
+    return match value {
+        "euler" => Ok(Method::Euler),
+        "midpoint" => Ok(Method::Midpoint),
+        "rk4" => Ok(Method::Rk4),
+        "rk45" => Ok(Method::Rk45),
+        "backward-euler" => Ok(Method::BackwardEuler),
+        "tr-bdf2" => Ok(Method::TrBdf2),
+        _ => Err(format!("Unknown integrator {}.", value)),
+    };
+}
+
+/* parse_metric
+* Parse the name of a fitness metric.
+*/
+fn parse_metric(value: &str) -> Result<Metric, String> {
+    return match value {
+        "area" => Ok(Metric::Area),
+        "mse" => Ok(Metric::Mse),
+        "rmse" => Ok(Metric::Rmse),
+        "mae" => Ok(Metric::Mae),
+        "huber" => Ok(Metric::Huber),
+        "max" => Ok(Metric::Max),
+        "relative" => Ok(Metric::Relative),
+        _ => Err(format!("Unknown metric {}.", value)),
+    };
+}
+
+/* parse_sampling
+* Parse where trajectories are compared against the data.
+*/
+fn parse_sampling(value: &str) -> Result<Sampling, String> {
+    return match value {
+        "steps" => Ok(Sampling::Steps),
+        "data" => Ok(Sampling::DataTimes),
+        _ => Err(format!("Unknown sampling {}.", value)),
+    };
+}
+
+/* parse_evaluation
+* Parse how individuals are tested.
+*/
+fn parse_evaluation(value: &str) -> Result<Mode, String> {
+    return match value {
+        "integrate" => Ok(Mode::Integrate),
+        "gradient" => Ok(Mode::Gradient),
+        "screen" => Ok(Mode::Screen),
+        _ => Err(format!("Unknown evaluation {}.", value)),
+    };
+}
+
+/* parse_differentiation
+* Parse how derivatives of the data are estimated.
+*/
+fn parse_differentiation(value: &str) -> Result<Derivative, String> {
+    return match value {
+        "finite-difference" => Ok(Derivative::FiniteDifference),
+        "savitzky-golay" => Ok(Derivative::SavitzkyGolay),
+        "total-variation" => Ok(Derivative::TotalVariation),
+        _ => Err(format!("Unknown differentiation {}.", value)),
+    };
+}
+
+/* parse_selection
+* Parse how parents are chosen.
+*/
+fn parse_selection(value: &str) -> Result<Scheme, String> {
+    return match value {
+        "exponential" => Ok(Scheme::Exponential),
+        "tournament" => Ok(Scheme::Tournament),
+        "roulette" => Ok(Scheme::Roulette),
+        "rank" => Ok(Scheme::Rank),
+        "lexicase" => Ok(Scheme::Lexicase),
+        "double-tournament" => Ok(Scheme::DoubleTournament),
+        _ => Err(format!("Unknown selection {}.", value)),
+    };
+}
+
+/* parse_initialization
+* Parse how the expressions of the initial population are generated.
+*/
+fn parse_initialization(value: &str) -> Result<Initialization, String> {
+    return match value {
+        "grow" => Ok(Initialization::Grow),
+        "full" => Ok(Initialization::Full),
+        "ramped" => Ok(Initialization::RampedHalfAndHalf),
+        _ => Err(format!("Unknown initialization {}.", value)),
+    };
+}
+
+/* parse_weights
+* Parse the weights of drawing time, a state variable, a constant and an 
+* operator, separated by commas. At least one of the leaves must have a 
+* positive weight.
+*/
+fn parse_weights(value: &str) -> Result<Vec<f64>, String> {
+    let weights: Vec<f64> = value.split(',')
+        .map(|weight| match weight.trim().parse::<f64>() {
+            Ok(weight) if weight >= 0.0 => Ok(weight),
+            _ => Err(format!("Invalid node weight {}.", weight)),
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    if weights.len() != 4 {
+        return Err(format!("Expected four node weights, found {}.", 
+            weights.len()));
+    }
+    if weights[..3].iter().all(|weight| *weight == 0.0) {
+        return Err(String::from("A leaf node weight must be positive."));
+    }
+    return Ok(weights);
+}
+
+/* parse_crossover
+* Parse how offspring are formed.
+*/
+fn parse_crossover(value: &str) -> Result<Recombination, String> {
+    return match value {
+        "subtree" => Ok(Recombination::Subtree),
+        "koza" => Ok(Recombination::Koza),
+        "one-point" => Ok(Recombination::OnePoint),
+        "size-fair" => Ok(Recombination::SizeFair),
+        "depth-limited" => Ok(Recombination::DepthLimited),
+        _ => Err(format!("Unknown crossover {}.", value)),
+    };
+}
+
+/* parse_mutation
+* Parse the probability of a kind of mutation, its name and probability
+* separated by a colon, setting it in the given mutation.
+*/
+fn parse_mutation(value: &str, mutation: &mut Mutation) -> Result<(), String> {
+    let (kind, probability) = value.split_once(':')
+        .ok_or_else(|| format!("Missing probability of mutation {}.", value))?;
+    let probability = match probability.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => p,
+        _ => return Err(format!("Invalid probability of mutation {}.", value)),
+    };
+
+    let rate = match kind {
+        "point" => &mut mutation.point,
+        "subtree" => &mut mutation.subtree,
+        "hoist" => &mut mutation.hoist,
+        "shrink" => &mut mutation.shrink,
+        "constant" => &mut mutation.constant,
+        "insert" => &mut mutation.insert,
+        "delete" => &mut mutation.delete,
+        _ => return Err(format!("Unknown mutation {}.", value)),
+    };
+    *rate = probability;
+    return Ok(());
+}
+
+/* parse_stage
+* Parse a preprocessing stage, its name followed by its parameters separated
+* by colons.
+*/
+fn parse_stage(value: &str) -> Result<Box<dyn Preprocessor>, String> {
+    let fields: Vec<&str> = value.split(':').collect();
+    let param = |idx: usize| fields.get(idx)
+        .ok_or_else(|| format!("Missing parameter of stage {}.", value));
+    let positive = |idx: usize| -> Result<usize, String> {
+        return match param(idx)?.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("Invalid parameter of stage {}.", value)),
+        };
+    };
+    let non_negative = |idx: usize| -> Result<f64, String> {
+        return match param(idx)?.parse::<f64>() {
+            Ok(x) if x >= 0.0 => Ok(x),
+            _ => Err(format!("Invalid parameter of stage {}.", value)),
+        };
+    };
+
+    let (stage, arity): (Box<dyn Preprocessor>, usize) = match fields[0] {
+        "moving-average" => (Box::new(MovingAverage::new(positive(1)?)), 2),
+        "savitzky-golay" => (Box::new(
+            SavitzkyGolay::new(positive(1)?, positive(2)?)), 3),
+        "total-variation" => 
+            (Box::new(TotalVariation::new(non_negative(1)?)), 2),
+        "spline" => (Box::new(SmoothingSpline::new(non_negative(1)?)), 2),
+        "resample" => (Box::new(Resample::new(positive(1)?)), 2),
+        _ => return Err(format!("Unknown preprocessing stage {}.", value)),
+    };
+    if fields.len() != arity {
+        return Err(format!("Too many parameters of stage {}.", value));
+    }
+    return Ok(stage);
+}
+
+/* parse_aggregate
+* Parse how the fitness of several trajectories is combined.
+*/
+fn parse_aggregate(value: &str) -> Result<Aggregate, String> {
+    return match value {
+        "sum" => Ok(Aggregate::Sum),
+        "mean" => Ok(Aggregate::Mean),
+        "worst" => Ok(Aggregate::Worst),
+        _ => Err(format!("Unknown aggregate {}.", value)),
+    };
+}
+
+/* parse_order
+* Parse the order of the ODE.
+*/
+fn parse_order(value: &str) -> Result<Order, String> {
+    return match value {
+        "1" => Ok(Order::First),
+        "2" => Ok(Order::Second),
+        _ => Err(format!("Unsupported order {}, expected 1 or 2.", value)),
+    };
+}
+
+/* parse_initial_velocity
+* Parse how the initial velocity of second-order ODEs is found.
+*/
+fn parse_initial_velocity(value: &str) -> Result<InitialVelocity, String> {
+    return match value {
+        "estimate" => Ok(InitialVelocity::Estimate),
+        "evolve" => Ok(InitialVelocity::Evolve),
+        _ => Err(format!("Unknown initial velocity {}.", value)),
+    };
+}
+
+/* parse_initial_condition
+* Parse where simulations start, smoothing over the given number of points.
+*/
+fn parse_initial_condition(value: &str, points: usize) 
+    -> Result<InitialCondition, String> {
+    return match value {
+        "first" => Ok(InitialCondition::First),
+        "smoothed" => Ok(InitialCondition::Smoothed(points)),
+        "optimize" => Ok(InitialCondition::Optimize),
+        _ => Err(format!("Unknown initial condition {}.", value)),
+    };
+}
+
+/* parse_missing
+* Parse how data points with missing values are handled.
+*/
+fn parse_missing(value: &str) -> Result<Missing, String> {
+    return match value {
+        "skip" => Ok(Missing::Skip),
+        "interpolate" => Ok(Missing::Interpolate),
+        "error" => Ok(Missing::Error),
+        _ => Err(format!("Unknown missing value handling {}.", value)),
+    };
+}
+
+/* parse_unordered
+* Parse how data points out of time order are handled.
+*/
+fn parse_unordered(value: &str) -> Result<Unordered, String> {
+    return match value {
+        "sort" => Ok(Unordered::Sort),
+        "error" => Ok(Unordered::Error),
+        _ => Err(format!("Unknown unordered data handling {}.", value)),
+    };
+}
+
+/* parse_duplicates
+* Parse how data points at the same time are handled.
+*/
+fn parse_duplicates(value: &str) -> Result<Duplicates, String> {
+    return match value {
+        "mean" => Ok(Duplicates::Mean),
+        "first" => Ok(Duplicates::First),
+        "last" => Ok(Duplicates::Last),
+        "error" => Ok(Duplicates::Error),
+        _ => Err(format!("Unknown duplicate handling {}.", value)),
+    };
+}
+
+/* parse_delimiter
+* Parse a delimiter, allowing tab and space to be given by name.
+*/
+fn parse_delimiter(value: &str) -> Result<char, String> {
+    return match value {
+        "tab" | "\\t" => Ok('\t'),
+        "space" => Ok(' '),
+        _ if value.chars().count() == 1 => Ok(value.chars().next().unwrap()),
+        _ => Err(format!("Invalid delimiter {}.", value)),
+    };
+}
+
+/* parse_operators
+* Parse a comma separated list of builtin operator tokens.
+*/
+fn parse_operators(value: &str) -> Result<Vec<&'static str>, String> {
+    let mut operators = Vec::new();
+    for token in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        match BUILTIN_TOKENS.iter().find(|builtin| **builtin == token) {
+            Some(builtin) => operators.push(*builtin),
+            None => return Err(format!("Unknown operator {}.", token)),
+        }
+    }
+    return Ok(operators);
+}
+
+//_____________________________________________________________________________
+//                                                                 Default Data
+
+/* default_data
+* Sampled logistic curve used when no data file is given.
+*/
+pub fn default_data() -> Vec<State> {
+    return (0..=10)
+        .map(|t| t as f64)
+        .map(|t| State::new(t, t.exp() / (1.0 + t.exp())))
+        .collect();
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        return text.split_whitespace().map(String::from).collect();
+    }
+
+    #[test]
+    fn step_must_be_positive_and_finite() {
+        for step in ["0", "-0.1", "NaN", "inf", "-inf"].iter() {
+            let args = args(&format!("fit --step {}", step));
+            assert!(parse(args).is_err(), "{}", step);
+        }
+        let (_, options) = parse(args("fit --step 0.5")).unwrap();
+        assert_eq!(options.step, 0.5);
+    }
+}
@@ -0,0 +1,299 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Crossover of the expressions of parents.
+//_____________________________________________________________________________
+
+// External imports.
+use rand::Rng;
+use std::ops::RangeInclusive;
+
+// Internal imports.
+use crate::ode::Expr;
+use crate::tree::Node;
+
+// Number of crossovers tried by depth-limited crossover before giving up and
+// keeping the first parent.
+const CROSSOVER_ATTEMPTS: usize = 10;
+
+//_____________________________________________________________________________
+//                                                        Crossover Type & Impl
+
+// How offspring are formed from a subtree of each parent, replacing the
+// subtree of the first parent with that of the second.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Crossover {
+    // Uniformly random subtrees of each parent. Most nodes are leaves, so
+    // this mostly swaps leaves or grafts whole subtrees onto them.
+    Subtree,
+    // Subtrees rooted at internal nodes with the given probability, and at
+    // leaves otherwise, 0.9 as proposed by Koza.
+    Koza(f64),
+    // One-point (homologous) crossover (Poli & Langdon, 1998). Both parents
+    // are swapped at the same position, chosen within the region where they
+    // have the same shape, so that offspring resemble their parents.
+    OnePoint,
+    // Size-fair crossover (Langdon, 2000). The subtree of the second parent
+    // is at most one more than twice the size of that it replaces, limiting
+    // the growth of offspring.
+    SizeFair,
+    // Subtree crossover retried until the offspring is no deeper than the
+    // given depth, see Expr::depth, keeping the first parent if none is.
+    DepthLimited(usize),
+}
+
+impl Crossover {
+    /* apply
+    * Crossover two expressions.
+    * Input:
+    *     expr - The first parent, the subtree of which is replaced.
+    *     other - The second parent, the subtree of which replaces it.
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     The offspring.
+    */
+    pub fn apply<R>(&self, expr: &Expr, other: &Expr, rng: &mut R) -> Expr
+        where R: Rng + ?Sized {
+        return match *self {
+            Crossover::Subtree => {
+                let sub_self = expr.sub_expr(rng);
+                let sub_other = other.sub_expr(rng);
+                splice(expr, sub_self, other, sub_other)
+            },
+            Crossover::Koza(internal) => {
+                let sub_self = biased(expr, internal, rng);
+                let sub_other = biased(other, internal, rng);
+                splice(expr, sub_self, other, sub_other)
+            },
+            Crossover::OnePoint => one_point(expr, other, rng),
+            Crossover::SizeFair => {
+                let sub_self = expr.sub_expr(rng);
+                let limit = 1 + 2 * sub_self.clone().count();
+                let candidates: Vec<RangeInclusive<usize>> =
+                    (0..other.operators().len())
+                    .map(|start| other.subtree(start))
+                    .filter(|range| range.clone().count() <= limit)
+                    .collect();
+
+                // Note that leaves are always candidates.
+                let sub_other =
+                    candidates[rng.gen_range(0, candidates.len())].clone();
+                splice(expr, sub_self, other, sub_other)
+            },
+            Crossover::DepthLimited(depth) => {
+                for _ in 0..CROSSOVER_ATTEMPTS {
+                    let offspring = Crossover::Subtree.apply(expr, other, rng);
+                    if offspring.depth() <= depth {
+                        return offspring;
+                    }
+                }
+                expr.clone()
+            },
+        };
+    }
+}
+
+impl Default for Crossover {
+    fn default() -> Crossover {
+        return Crossover::Subtree;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                            Crossover Helpers
+
+/* splice
+* Replace a subtree of one expression with a subtree of another.
+*/
+fn splice(
+    expr: &Expr,
+    range: RangeInclusive<usize>,
+    other: &Expr,
+    other_range: RangeInclusive<usize>) -> Expr {
+    let operators = expr.splice(range, &other.operators()[other_range]);
+    return Expr::from_operators(operators).unwrap();
+}
+
+/* biased
+* Get a random subtree of an expression, rooted at an internal node with the
+* given probability if there are any and at a leaf otherwise.
+*/
+fn biased<R>(expr: &Expr, internal: f64, rng: &mut R)
+    -> RangeInclusive<usize>
+    where R: Rng + ?Sized {
+    let wants_internal = rng.gen_range(0.0, 1.0) < internal;
+    let starts: Vec<usize> = expr.operators().iter().enumerate()
+        .filter(|(_, operator)| (operator.arity() > 0) == wants_internal)
+        .map(|(idx, _)| idx)
+        .collect();
+    return match starts.is_empty() {
+        true => expr.sub_expr(rng),
+        false => expr.subtree(starts[rng.gen_range(0, starts.len())]),
+    };
+}
+
+/* one_point
+* Swap the subtrees of two expressions at a random position within their
+* common region, the positions reached from the root through nodes of equal
+* arity in both.
+*/
+fn one_point<R>(expr: &Expr, other: &Expr, rng: &mut R) -> Expr
+    where R: Rng + ?Sized {
+    let mut tree = Node::from_expr(expr);
+    let other_tree = Node::from_expr(other);
+
+    let mut region = Vec::new();
+    common_region(&tree, &other_tree, &mut Vec::new(), &mut region);
+    let path = &region[rng.gen_range(0, region.len())];
+
+    let mut node = &mut tree;
+    let mut other_node = &other_tree;
+    for idx in path.iter() {
+        node = &mut node.args[*idx];
+        other_node = &other_node.args[*idx];
+    }
+    *node = other_node.clone();
+    return tree.to_expr();
+}
+
+/* common_region
+* Collect the paths, as the argument taken at each node from the root, of
+* the positions common to two trees.
+*/
+fn common_region(
+    node: &Node,
+    other: &Node,
+    path: &mut Vec<usize>,
+    region: &mut Vec<Vec<usize>>) {
+    region.push(path.clone());
+    if node.args.len() != other.args.len() {
+        return;
+    }
+    for (idx, (arg, other_arg)) in
+        node.args.iter().zip(other.args.iter()).enumerate() {
+        path.push(idx);
+        common_region(arg, other_arg, path, region);
+        path.pop();
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::operator::builtin_map;
+
+    const VARIANTS: [Crossover; 5] = [
+        Crossover::Subtree,
+        Crossover::Koza(0.9),
+        Crossover::OnePoint,
+        Crossover::SizeFair,
+        Crossover::DepthLimited(3),
+    ];
+
+    fn arities(expr: &Expr) -> Vec<usize> {
+        return expr.operators().iter()
+            .map(|operator| operator.arity())
+            .collect();
+    }
+
+    #[test]
+    fn offspring_are_valid() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for crossover in VARIANTS.iter() {
+            for _ in 0..200 {
+                let expr = Expr::generate(&map, &mut rng);
+                let other = Expr::generate(&map, &mut rng);
+                let offspring = crossover.apply(&expr, &other, &mut rng);
+                let operators = offspring.operators().to_vec();
+                assert!(Expr::from_operators(operators).is_ok(),
+                    "{:?}", crossover);
+            }
+        }
+    }
+
+    #[test]
+    fn depth_limited_offspring_are_shallow_or_the_first_parent() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        for depth in 0..4 {
+            for _ in 0..200 {
+                let expr = Expr::generate(&map, &mut rng);
+                let other = Expr::generate(&map, &mut rng);
+                let offspring = Crossover::DepthLimited(depth)
+                    .apply(&expr, &other, &mut rng);
+                assert!(offspring.depth() <= depth
+                    || offspring.operators() == expr.operators());
+            }
+        }
+    }
+
+    #[test]
+    fn size_fair_limits_the_inserted_subtree() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let deep = Expr::parse("ADD MUL SIN POS COS TIME SUB EXP POS LN TIME",
+            &map).unwrap();
+
+        // Only a leaf can be replaced, so at most three nodes are inserted.
+        let leaf = Expr::parse("POS", &map).unwrap();
+        for _ in 0..200 {
+            let offspring = Crossover::SizeFair.apply(&leaf, &deep, &mut rng);
+            assert!(offspring.size() <= 3);
+        }
+
+        // Replacing n of s nodes gives at most s - n + 1 + 2n nodes.
+        for _ in 0..200 {
+            let expr = Expr::generate(&map, &mut rng);
+            let offspring = Crossover::SizeFair.apply(&expr, &deep, &mut rng);
+            assert!(offspring.size() <= 2 * expr.size() + 1);
+        }
+    }
+
+    #[test]
+    fn one_point_keeps_the_shape_of_identical_shapes() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let expr = Expr::parse("ADD SIN POS TIME", &map).unwrap();
+        let other = Expr::parse("MUL COS TIME POS", &map).unwrap();
+        for _ in 0..100 {
+            let offspring = Crossover::OnePoint.apply(&expr, &other, &mut rng);
+            assert_eq!(arities(&offspring), arities(&expr));
+            for (idx, operator) in offspring.operators().iter().enumerate() {
+                assert!(*operator == expr.operators()[idx]
+                    || *operator == other.operators()[idx]);
+            }
+        }
+    }
+
+    #[test]
+    fn one_point_swaps_within_the_common_region() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let expr = Expr::parse("ADD SIN POS TIME", &map).unwrap();
+        let other = Expr::parse("MUL POS COS TIME", &map).unwrap();
+
+        // The root and its arguments, whose arities differ.
+        let expected: Vec<Expr> = [
+            "MUL POS COS TIME",
+            "ADD POS TIME",
+            "ADD SIN POS COS TIME",
+        ].iter()
+            .map(|text| Expr::parse(text, &map).unwrap())
+            .collect();
+        let mut seen = vec![false; expected.len()];
+        for _ in 0..100 {
+            let offspring = Crossover::OnePoint.apply(&expr, &other, &mut rng);
+            let idx = expected.iter()
+                .position(|swapped|
+                    swapped.operators() == offspring.operators())
+                .unwrap();
+            seen[idx] = true;
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
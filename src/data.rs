@@ -0,0 +1,856 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Loading of time and position data from delimited text files.
+//_____________________________________________________________________________
+
+// External imports.
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Internal imports.
+use crate::error::Result;
+use crate::ode::State;
+
+// Fields read as missing values, in addition to those parsing as NaN.
+const MISSING_TOKENS: [&str; 5] = ["", "NA", "N/A", "null", "?"];
+
+//_____________________________________________________________________________
+//                                                                   Error Type
+
+#[derive(Debug)]
+pub enum DataError {
+    Io(String),
+    // No data rows were found.
+    Empty,
+    // No position columns were selected.
+    NoPositionColumns,
+    // A named column does not appear in the header (or there is no header).
+    MissingColumn(String),
+    // A column index beyond the width of the rows.
+    ColumnOutOfRange(usize),
+    Ragged {line: usize, expected: usize, found: usize},
+    NonNumeric {line: usize, column: usize, value: String},
+    // A missing or non-finite value at a data point, counted from one in the
+    // data as given (the data rows of a file).
+    Missing {point: usize},
+    // A data point earlier than the one before it, counted as for Missing.
+    Unordered {point: usize},
+    // Several data points at the same time.
+    DuplicateTime(f64),
+}
+
+impl std::error::Error for DataError { }
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            DataError::Io(message) => write!(f, "{}", message),
+            DataError::Empty => write!(f, "No data rows found."),
+            DataError::NoPositionColumns =>
+                write!(f, "No position columns selected."),
+            DataError::MissingColumn(name) =>
+                write!(f, "Column {} not found in header.", name),
+            DataError::ColumnOutOfRange(index) =>
+                write!(f, "Column index {} is out of range.", index),
+            DataError::Ragged {line, expected, found} => write!(f,
+                "Line {} has {} fields, expected {}.", line, found, expected),
+            DataError::NonNumeric {line, column, value} => write!(f,
+                "Line {} has non-numeric value {:?} in column {}.",
+                line, value, column),
+            DataError::Missing {point} => write!(f,
+                "Data point {} has a missing or non-finite value.", point),
+            DataError::Unordered {point} => write!(f,
+                "Data point {} is earlier than the one before it.", point),
+            DataError::DuplicateTime(time) =>
+                write!(f, "Several data points at time {}.", time),
+        };
+    }
+}
+
+//_____________________________________________________________________________
+//                                                         Column & Format Types
+
+// A column selected either by its header name or its zero-based index.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Name(String),
+    Index(usize),
+}
+
+impl Column {
+    /* parse
+    * Interpret a command-line style column specifier, treating integers as
+    * indices and anything else as a name.
+    */
+    pub fn parse(spec: &str) -> Column {
+        return match spec.parse::<usize>() {
+            Ok(index) => Column::Index(index),
+            Err(_) => Column::Name(String::from(spec)),
+        };
+    }
+}
+
+pub struct Format {
+    // Field delimiter. When None, it is detected from the file extension or
+    // the first data line. A space splits on any run of whitespace.
+    pub delimiter: Option<char>,
+    // Lines beginning with this character are skipped.
+    pub comment: Option<char>,
+    // Whether the first row is a header. When None, the first row is treated
+    // as a header if any of its fields are non-numeric.
+    pub header: Option<bool>,
+    pub time: Column,
+    // Columns of each state variable, the first being the position.
+    pub positions: Vec<Column>,
+}
+
+impl Format {
+    /* new
+    * Default format reading time from the first column and position from the
+    * second, with # comments and detected delimiter and header.
+    */
+    pub fn new() -> Format {
+        return Format {
+            delimiter: None,
+            comment: Some('#'),
+            header: None,
+            time: Column::Index(0),
+            positions: vec![Column::Index(1)],
+        };
+    }
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        return Format::new();
+    }
+}
+
+//_____________________________________________________________________________
+//                                                              Trajectory Type
+
+// One run of an experiment, such as the contents of a data file. Several 
+// trajectories of the same system from different initial conditions can be
+// fit together.
+#[derive(Clone, Debug)]
+pub struct Trajectory {
+    pub name: String,
+    pub states: Vec<State>,
+}
+
+impl Trajectory {
+    /* new
+    * Create a trajectory from its name and states ordered by time.
+    */
+    pub fn new<S>(name: S, states: Vec<State>) -> Trajectory
+        where S: Into<String> {
+        return Trajectory {name: name.into(), states};
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                      Reading
+
+/* read
+* Read states from the delimited file at the given path.
+* Input:
+*     path - Path of a CSV, TSV or whitespace separated file.
+*     format - Description of the file layout.
+* Output:
+*     Vector of states in file order, with missing values read as NaN.
+*/
+pub fn read<P>(path: P, format: &Format) -> Result<Vec<State>>
+    where P: AsRef<Path> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .map_err(|e| DataError::Io(e.to_string()))?;
+
+    // Prefer the delimiter implied by the extension over detection.
+    let extension = path.extension().and_then(|e| e.to_str());
+    let delimiter = format.delimiter.or(match extension {
+        Some(e) if e.eq_ignore_ascii_case("csv") => Some(','),
+        Some(e) if e.eq_ignore_ascii_case("tsv") => Some('\t'),
+        _ => None,
+    });
+
+    let format = Format {
+        delimiter,
+        comment: format.comment,
+        header: format.header,
+        time: format.time.clone(),
+        positions: format.positions.clone(),
+    };
+    return parse(&contents, &format);
+}
+
+/* read_trajectory
+* Read a trajectory from the delimited file at the given path, named by the
+* path.
+*/
+pub fn read_trajectory<P>(path: P, format: &Format) -> Result<Trajectory>
+    where P: AsRef<Path> {
+    let states = read(&path, format)?;
+    return Ok(Trajectory::new(path.as_ref().to_string_lossy(), states));
+}
+
+/* parse
+* Parse states from delimited text.
+* Input:
+*     contents - Text of the file.
+*     format - Description of the text layout.
+* Output:
+*     Vector of states in file order, with missing values read as NaN.
+*/
+pub fn parse(contents: &str, format: &Format) -> Result<Vec<State>> {
+    if format.positions.is_empty() {
+        return Err(DataError::NoPositionColumns.into());
+    }
+
+    // Keep line numbers (one-based) for error messages.
+    let mut lines = contents.lines().enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .filter(|(_, line)| match format.comment {
+            Some(c) => !line.starts_with(c),
+            None => true,
+        })
+        .peekable();
+
+    let first = match lines.peek() {
+        Some((_, line)) => *line,
+        None => return Err(DataError::Empty.into()),
+    };
+
+    let delimiter = format.delimiter.unwrap_or_else(|| detect_delimiter(first));
+    let first_fields = split(first, delimiter);
+
+    let has_header = format.header.unwrap_or_else(||
+        first_fields.iter().any(|field| field.parse::<f64>().is_err()
+            && !MISSING_TOKENS.contains(field)));
+    let header = if has_header {
+        lines.next();
+        Some(first_fields.clone())
+    } else {
+        None
+    };
+
+    let width = first_fields.len();
+    let time_idx = resolve(&format.time, &header, width)?;
+    let position_idxs = format.positions.iter()
+        .map(|column| resolve(column, &header, width))
+        .collect::<Result<Vec<usize>>>()?;
+
+    let mut states = Vec::new();
+    for (line_num, line) in lines {
+        let fields = split(line, delimiter);
+        if fields.len() != width {
+            return Err(DataError::Ragged {
+                line: line_num,
+                expected: width,
+                found: fields.len(),
+            }.into());
+        }
+
+        // Missing values are read as NaN, see clean.
+        let value = |idx: usize| match MISSING_TOKENS.contains(&fields[idx]) {
+            true => Ok(f64::NAN),
+            false => fields[idx].parse::<f64>()
+                .map_err(|_| DataError::NonNumeric {
+                    line: line_num,
+                    column: idx,
+                    value: String::from(fields[idx]),
+                }),
+        };
+        let values = position_idxs.iter()
+            .map(|idx| value(*idx))
+            .collect::<std::result::Result<Vec<f64>, DataError>>()?;
+        states.push(State::from_values(value(time_idx)?, values));
+    }
+
+    if states.is_empty() {
+        return Err(DataError::Empty.into());
+    }
+    return Ok(states);
+}
+
+/* detect_delimiter
+* Guess the delimiter of a line, preferring tabs, then commas, then
+* semicolons, and falling back to whitespace.
+*/
+fn detect_delimiter(line: &str) -> char {
+    return ['\t', ',', ';'].iter()
+        .find(|c| line.contains(**c))
+        .copied()
+        .unwrap_or(' ');
+}
+
+/* split
+* Split a line into trimmed fields, removing quotes around fields.
+*/
+fn split(line: &str, delimiter: char) -> Vec<&str> {
+    return match delimiter {
+        ' ' => line.split_whitespace().map(unquote).collect(),
+        _ => line.split(delimiter).map(unquote).collect(),
+    };
+}
+
+/* unquote
+* Trim a field and remove any surrounding double quotes.
+*/
+fn unquote(field: &str) -> &str {
+    let field = field.trim();
+    if field.len() >= 2 && field.starts_with('"') && field.ends_with('"') {
+        return &field[1..(field.len() - 1)];
+    }
+    return field;
+}
+
+/* resolve
+* Find the index of a column, checking it against the row width.
+*/
+fn resolve(column: &Column, header: &Option<Vec<&str>>, width: usize)
+    -> Result<usize> {
+    let idx = match (column, header) {
+        (Column::Index(idx), _) => *idx,
+        (Column::Name(name), Some(header)) =>
+            match header.iter().position(|field| field == name) {
+                Some(idx) => idx,
+                None => return Err(DataError::MissingColumn(name.clone()).into()),
+            },
+        (Column::Name(name), None) =>
+            return Err(DataError::MissingColumn(name.clone()).into()),
+    };
+
+    if idx >= width {
+        return Err(DataError::ColumnOutOfRange(idx).into());
+    }
+    return Ok(idx);
+}
+
+//_____________________________________________________________________________
+//                                                                     Cleaning
+
+// How data points with missing or non-finite values are handled.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Missing {
+    // Drop data points with any missing value.
+    Skip,
+    // Linearly interpolate missing values in time between the neighbouring 
+    // values of the same state variable, dropping data points with missing
+    // values before the first or after the last value.
+    Interpolate,
+    Error,
+}
+
+// How data points out of time order are handled.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Unordered {
+    Sort,
+    Error,
+}
+
+// How several data points at the same time are handled.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Duplicates {
+    // Average the values of the data points, ignoring missing values.
+    Mean,
+    First,
+    Last,
+    Error,
+}
+
+// Handling of problems in data, see clean.
+#[derive(Copy, Clone, Debug)]
+pub struct Cleaning {
+    pub missing: Missing,
+    pub unordered: Unordered,
+    pub duplicates: Duplicates,
+}
+
+impl Cleaning {
+    /* new
+    * Cleaning that rejects missing values, sorts data points by time and 
+    * averages those at the same time.
+    */
+    pub fn new() -> Cleaning {
+        return Cleaning {
+            missing: Missing::Error,
+            unordered: Unordered::Sort,
+            duplicates: Duplicates::Mean,
+        };
+    }
+}
+
+impl Default for Cleaning {
+    fn default() -> Cleaning {
+        return Cleaning::new();
+    }
+}
+
+// What was changed when cleaning data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    // Data points dropped for missing or non-finite times.
+    pub invalid_times: usize,
+    // Whether the data points were sorted by time.
+    pub sorted: bool,
+    // Data points merged into another at the same time.
+    pub merged: usize,
+    // Data points dropped for missing values.
+    pub skipped: usize,
+    // Missing values filled in by interpolation.
+    pub interpolated: usize,
+}
+
+impl Report {
+    /* is_clean
+    * Whether the data was left unchanged.
+    */
+    pub fn is_clean(&self) -> bool {
+        return *self == Report::default();
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut changes = Vec::new();
+        if self.invalid_times > 0 {
+            changes.push(format!("dropped {} data points with missing times",
+                self.invalid_times));
+        }
+        if self.sorted {
+            changes.push(String::from("sorted data points by time"));
+        }
+        if self.merged > 0 {
+            changes.push(format!(
+                "merged {} data points at duplicate times", self.merged));
+        }
+        if self.skipped > 0 {
+            changes.push(format!(
+                "skipped {} data points with missing values", self.skipped));
+        }
+        if self.interpolated > 0 {
+            changes.push(format!(
+                "interpolated {} missing values", self.interpolated));
+        }
+
+        return match changes.is_empty() {
+            true => write!(f, "No changes to data."),
+            false => write!(f, "Data cleaned: {}.", changes.join(", ")),
+        };
+    }
+}
+
+/* clean
+* Resolve missing values, time order and duplicate times in data, such as 
+* that read by this module, so that it can be fit. Data points are first 
+* dropped for missing times, then sorted and merged, and finally missing 
+* values are handled.
+* Input:
+*     states - Data, possibly with NaN for missing values.
+*     cleaning - How each problem is handled.
+* Output:
+*     The cleaned data and a report of the changes made, or the first 
+*     problem handled by Error. Note that the data may be left empty.
+*/
+pub fn clean(states: Vec<State>, cleaning: &Cleaning) 
+    -> Result<(Vec<State>, Report)> {
+    let mut report = Report::default();
+    let is_missing = |value: &f64| !value.is_finite();
+
+    // Times cannot be interpolated, so are only ever dropped.
+    if let Some(point) = states.iter().position(|s| is_missing(&s.time)) {
+        if cleaning.missing == Missing::Error {
+            return Err(DataError::Missing {point: point + 1}.into());
+        }
+    }
+
+    // Pair each data point with its index in the given data, so that errors
+    // name the data point as given however it has since been moved.
+    let count = states.len();
+    let mut points: Vec<(usize, State)> = states.into_iter()
+        .enumerate()
+        .filter(|(_, state)| !is_missing(&state.time))
+        .collect();
+    report.invalid_times = count - points.len();
+
+    if let Some(idx) = points.windows(2)
+        .position(|w| w[1].1.time < w[0].1.time) {
+        if cleaning.unordered == Unordered::Error {
+            let point = points[idx + 1].0 + 1;
+            return Err(DataError::Unordered {point}.into());
+        }
+        // Note that the sort is stable, keeping duplicates in file order.
+        points.sort_by(|a, b| a.1.time.total_cmp(&b.1.time));
+        report.sorted = true;
+    }
+
+    let mut merged: Vec<(usize, State)> = Vec::with_capacity(points.len());
+    let mut idx = 0;
+    while idx < points.len() {
+        let end = idx + points[idx..].iter()
+            .take_while(|(_, state)| state.time == points[idx].1.time)
+            .count();
+        let group = &points[idx..end];
+        let point = match (group, cleaning.duplicates) {
+            ([point], _) | ([point, ..], Duplicates::First) => point.clone(),
+            ([.., point], Duplicates::Last) => point.clone(),
+            (_, Duplicates::Mean) => (group[0].0, State::from_values(
+                group[0].1.time,
+                (0..group[0].1.dimension())
+                    .map(|i| {
+                        let values: Vec<f64> = group.iter()
+                            .map(|(_, state)| state.values[i])
+                            .filter(|value| !is_missing(value))
+                            .collect();
+                        match values.is_empty() {
+                            true => f64::NAN,
+                            false => values.iter().sum::<f64>() 
+                                / values.len() as f64,
+                        }
+                    })
+                    .collect())),
+            _ => return Err(DataError::DuplicateTime(group[0].1.time).into()),
+        };
+        report.merged += group.len() - 1;
+        merged.push(point);
+        idx = end;
+    }
+    let (rows, mut states): (Vec<usize>, Vec<State>) = 
+        merged.into_iter().unzip();
+
+    let complete = |state: &State| !state.values.iter().any(is_missing);
+    match cleaning.missing {
+        Missing::Error => {
+            if let Some(idx) = states.iter().position(|s| !complete(s)) {
+                return Err(DataError::Missing {point: rows[idx] + 1}.into());
+            }
+        },
+        Missing::Skip => (),
+        Missing::Interpolate => {
+            let dimension = states.first().map_or(0, State::dimension);
+            for i in 0..dimension {
+                let known: Vec<usize> = (0..states.len())
+                    .filter(|idx| !is_missing(&states[*idx].values[i]))
+                    .collect();
+                for pair in known.windows(2) {
+                    let (a, b) = (&states[pair[0]], &states[pair[1]]);
+                    let (ta, xa) = (a.time, a.values[i]);
+                    let (tb, xb) = (b.time, b.values[i]);
+                    for state in states[(pair[0] + 1)..pair[1]].iter_mut() {
+                        let fraction = (state.time - ta) / (tb - ta);
+                        state.values[i] = xa + fraction * (xb - xa);
+                        report.interpolated += 1;
+                    }
+                }
+            }
+        },
+    }
+
+    // Whatever could not be filled in is skipped.
+    let count = states.len();
+    states.retain(complete);
+    report.skipped = count - states.len();
+    return Ok((states, report));
+}
+
+/* validate
+* Check that data can be fit, see clean.
+* Output:
+*     A Missing error for missing or non-finite times or values, or an 
+*     Unordered or DuplicateTime error if the times are not increasing.
+*/
+pub fn validate(states: &[State]) -> Result<()> {
+    let is_finite = |state: &State| state.time.is_finite() 
+        && state.values.iter().all(|value| value.is_finite());
+    if let Some(point) = states.iter().position(|state| !is_finite(state)) {
+        return Err(DataError::Missing {point: point + 1}.into());
+    }
+
+    for (idx, pair) in states.windows(2).enumerate() {
+        if pair[1].time < pair[0].time {
+            return Err(DataError::Unordered {point: idx + 2}.into());
+        } else if pair[1].time == pair[0].time {
+            return Err(DataError::DuplicateTime(pair[0].time).into());
+        }
+    }
+    return Ok(());
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    fn columns(time: Column, positions: Vec<Column>) -> Format {
+        return Format {time, positions, ..Format::new()};
+    }
+
+    fn parse_error(contents: &str, format: &Format) -> DataError {
+        return match parse(contents, format) {
+            Err(Error::Data(error)) => error,
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("expected an error"),
+        };
+    }
+
+    #[test]
+    fn parse_detects_delimiters() {
+        let expected = vec![
+            State::from_values(0.0, vec![1.0, 2.0]),
+            State::from_values(0.5, vec![3.0, 4.0]),
+        ];
+        let format = columns(
+            Column::Index(0), vec![Column::Index(1), Column::Index(2)]);
+        let contents = [
+            "0,1,2\n0.5,3,4\n",
+            "0\t1\t2\n0.5\t3\t4\n",
+            "0;1;2\n0.5;3;4\n",
+            "0  1 2\n  0.5 3\t 4\n",
+        ];
+        for text in contents.iter() {
+            assert_eq!(parse(text, &format).unwrap(), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn read_prefers_the_delimiter_of_the_extension() {
+        // Detection alone would split on the tab.
+        let text = "note\tx,t,x\nrun\t1,0,1\n";
+        let format = columns(Column::Index(1), vec![Column::Index(2)]);
+        assert!(parse(text, &format).is_err());
+
+        let path = std::env::temp_dir()
+            .join(format!("genetic_ode_{}.csv", std::process::id()));
+        fs::write(&path, text).unwrap();
+        let trajectory = read_trajectory(&path, &format);
+        fs::remove_file(&path).unwrap();
+
+        let trajectory = trajectory.unwrap();
+        assert_eq!(trajectory.name, path.to_string_lossy());
+        assert_eq!(trajectory.states, vec![State::new(0.0, 1.0)]);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let text = "# time, position\n\n0, 1\n  # note\n1, 2\n\n";
+        let states = parse(text, &Format::new()).unwrap();
+        assert_eq!(states, vec![State::new(0.0, 1.0), State::new(1.0, 2.0)]);
+    }
+
+    #[test]
+    fn parse_detects_headers() {
+        let text = "t,x\n0,1\n1,2\n";
+        assert_eq!(parse(text, &Format::new()).unwrap().len(), 2);
+
+        // Missing values are not taken for a header.
+        let text = "0,NA\n1,2\n";
+        let states = parse(text, &Format::new()).unwrap();
+        assert_eq!(states.len(), 2);
+        assert!(states[0].position().is_nan());
+
+        // A header given explicitly.
+        let text = "0,1\n1,2\n";
+        let format = Format {header: Some(true), ..Format::new()};
+        assert_eq!(parse(text, &format).unwrap(), vec![State::new(1.0, 2.0)]);
+    }
+
+    #[test]
+    fn parse_selects_columns_by_name_and_index() {
+        let text = "\"x\",\"y\",\"time\"\n1,2,0\n3,4,1\n";
+        let format = columns(Column::Name(String::from("time")), 
+            vec![Column::Name(String::from("y")), Column::Index(0)]);
+        assert_eq!(parse(text, &format).unwrap(), vec![
+            State::from_values(0.0, vec![2.0, 1.0]),
+            State::from_values(1.0, vec![4.0, 3.0]),
+        ]);
+
+        let format = columns(Column::Name(String::from("z")), 
+            vec![Column::Index(1)]);
+        assert!(matches!(parse_error(text, &format),
+            DataError::MissingColumn(name) if name == "z"));
+
+        // Names cannot be resolved without a header.
+        let format = columns(Column::Name(String::from("x")), 
+            vec![Column::Index(1)]);
+        assert!(matches!(parse_error("0,1\n", &format),
+            DataError::MissingColumn(_)));
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range_columns() {
+        let format = columns(Column::Index(0), vec![Column::Index(2)]);
+        assert!(matches!(parse_error("0,1\n1,2\n", &format),
+            DataError::ColumnOutOfRange(2)));
+
+        let format = columns(Column::Index(0), Vec::new());
+        assert!(matches!(parse_error("0,1\n", &format),
+            DataError::NoPositionColumns));
+    }
+
+    #[test]
+    fn parse_rejects_ragged_rows() {
+        let text = "t,x\n0,1\n\n1,2,3\n";
+        assert!(matches!(parse_error(text, &Format::new()),
+            DataError::Ragged {line: 4, expected: 2, found: 3}));
+    }
+
+    #[test]
+    fn parse_rejects_non_numeric_fields() {
+        let text = "0,1\n1,abc\n";
+        assert!(matches!(parse_error(text, &Format::new()),
+            DataError::NonNumeric {line: 2, column: 1, value} 
+                if value == "abc"));
+    }
+
+    #[test]
+    fn parse_rejects_empty_data() {
+        for text in ["", "# only comments\n", "t,x\n"].iter() {
+            assert!(matches!(parse_error(text, &Format::new()),
+                DataError::Empty));
+        }
+    }
+
+    fn states(points: &[(f64, f64)]) -> Vec<State> {
+        return points.iter()
+            .map(|(time, position)| State::new(*time, *position))
+            .collect();
+    }
+
+    fn cleaning(missing: Missing, unordered: Unordered, duplicates: Duplicates)
+        -> Cleaning {
+        return Cleaning {missing, unordered, duplicates};
+    }
+
+    fn cleaned(points: &[(f64, f64)], cleaning: &Cleaning) 
+        -> (Vec<(f64, f64)>, Report) {
+        let (states, report) = clean(states(points), cleaning).unwrap();
+        let points = states.iter()
+            .map(|state| (state.time, state.position()))
+            .collect();
+        return (points, report);
+    }
+
+    fn error(points: &[(f64, f64)], cleaning: &Cleaning) -> DataError {
+        return match clean(states(points), cleaning) {
+            Err(Error::Data(error)) => error,
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("expected an error"),
+        };
+    }
+
+    #[test]
+    fn clean_leaves_clean_data_unchanged() {
+        let points = [(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)];
+        let (result, report) = cleaned(&points, &Cleaning::new());
+        assert_eq!(result, points.to_vec());
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn clean_missing_skip() {
+        let points = [(0.0, 1.0), (f64::NAN, 2.0), (1.0, f64::NAN), (2.0, 3.0)];
+        let cleaning = cleaning(
+            Missing::Skip, Unordered::Sort, Duplicates::Mean);
+        let (result, report) = cleaned(&points, &cleaning);
+        assert_eq!(result, vec![(0.0, 1.0), (2.0, 3.0)]);
+        assert_eq!(report, Report {
+            invalid_times: 1, skipped: 1, ..Report::default()});
+    }
+
+    #[test]
+    fn clean_missing_interpolate() {
+        let points = [
+            (f64::NAN, 0.0), (0.0, f64::NAN), (1.0, 1.0), (2.0, f64::NAN),
+            (4.0, 4.0), (5.0, f64::INFINITY),
+        ];
+        let cleaning = cleaning(
+            Missing::Interpolate, Unordered::Sort, Duplicates::Mean);
+        let (result, report) = cleaned(&points, &cleaning);
+        assert_eq!(result, vec![(1.0, 1.0), (2.0, 2.0), (4.0, 4.0)]);
+        assert_eq!(report, Report {
+            invalid_times: 1, interpolated: 1, skipped: 2, 
+            ..Report::default()});
+    }
+
+    #[test]
+    fn clean_missing_error_names_the_data_point_as_given() {
+        let cleaning = cleaning(
+            Missing::Error, Unordered::Sort, Duplicates::Mean);
+
+        let points = [(0.0, 1.0), (1.0, f64::NAN), (2.0, 3.0)];
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::Missing {point: 2}));
+
+        let points = [(0.0, 1.0), (f64::NAN, 2.0)];
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::Missing {point: 2}));
+
+        // Sorting moves the third data point to second.
+        let points = [(2.0, 1.0), (0.0, 1.0), (1.0, f64::NAN)];
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::Missing {point: 3}));
+
+        // Merging moves the third data point to second.
+        let points = [(0.0, 1.0), (0.0, 2.0), (1.0, f64::NAN)];
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::Missing {point: 3}));
+    }
+
+    #[test]
+    fn clean_unordered_sort() {
+        let points = [(2.0, 3.0), (0.0, 1.0), (1.0, 2.0)];
+        let (result, report) = cleaned(&points, &Cleaning::new());
+        assert_eq!(result, vec![(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)]);
+        assert_eq!(report, Report {sorted: true, ..Report::default()});
+    }
+
+    #[test]
+    fn clean_unordered_error_names_the_data_point_as_given() {
+        let cleaning = cleaning(
+            Missing::Skip, Unordered::Error, Duplicates::Mean);
+        let points = [(0.0, 1.0), (2.0, 1.0), (1.0, 1.0)];
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::Unordered {point: 3}));
+
+        // Dropping the first data point for its missing time.
+        let points = [(f64::NAN, 1.0), (1.0, 1.0), (0.0, 1.0)];
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::Unordered {point: 3}));
+    }
+
+    #[test]
+    fn clean_duplicates() {
+        let points = [(0.0, 1.0), (1.0, 2.0), (1.0, f64::NAN), (1.0, 4.0)];
+        let modes = [
+            (Duplicates::Mean, 3.0),
+            (Duplicates::First, 2.0),
+            (Duplicates::Last, 4.0),
+        ];
+        for (duplicates, position) in modes.iter() {
+            let cleaning = cleaning(
+                Missing::Skip, Unordered::Sort, *duplicates);
+            let (result, report) = cleaned(&points, &cleaning);
+            assert_eq!(result, vec![(0.0, 1.0), (1.0, *position)]);
+            assert_eq!(report, Report {merged: 2, ..Report::default()});
+        }
+
+        let cleaning = cleaning(
+            Missing::Skip, Unordered::Sort, Duplicates::Error);
+        assert!(matches!(error(&points, &cleaning), 
+            DataError::DuplicateTime(time) if time == 1.0));
+    }
+
+    #[test]
+    fn clean_duplicates_after_sorting() {
+        let points = [(1.0, 2.0), (0.0, 1.0), (1.0, 4.0)];
+        let (result, report) = cleaned(&points, &Cleaning::new());
+        assert_eq!(result, vec![(0.0, 1.0), (1.0, 3.0)]);
+        assert_eq!(report, Report {
+            sorted: true, merged: 1, ..Report::default()});
+    }
+}
@@ -0,0 +1,84 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Crate-wide error type.
+//_____________________________________________________________________________
+
+// External imports.
+use std::fmt;
+
+// Internal imports.
+use crate::data::DataError;
+
+//_____________________________________________________________________________
+//                                                          Error Type & Result
+
+#[derive(Debug)]
+pub enum Error {
+    // An operator token that does not meet the token specifications.
+    InvalidToken {token: String, reason: &'static str},
+    // Time and position data of different lengths.
+    MismatchedData {times: usize, positions: usize},
+    EmptyData,
+    // States, or a system and states, with different numbers of variables.
+    DimensionMismatch {expected: usize, found: usize},
+    EmptyPopulation,
+    // A time step that is not positive and finite.
+    InvalidStep(f64),
+    // An expression whose operators do not form a single valid expression.
+    MalformedExpr(&'static str),
+    // An operator that is neither in the map nor an anonymous constant.
+    UnmappedOperator,
+    // A state variable index beyond the variables of a state.
+    UnknownVariable(usize),
+    // A velocity index beyond the velocities of a state.
+    UnknownVelocity(usize),
+    // A token in expression text that is neither in the map nor a number.
+    UnknownToken(String),
+    // Expression text that could not be parsed.
+    Parse(String),
+    // A failure reading or parsing a data file.
+    Data(DataError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::InvalidToken {token, reason} =>
+                write!(f, "Token {} invalid, {}.", token, reason),
+            Error::MismatchedData {times, positions} => write!(f,
+                "Time and position data must be of equal lengths, \
+                got {} times and {} positions.", times, positions),
+            Error::EmptyData =>
+                write!(f, "Time and position data cannot be empty."),
+            Error::DimensionMismatch {expected, found} => write!(f,
+                "Expected {} state variables, found {}.", expected, found),
+            Error::EmptyPopulation =>
+                write!(f, "Cannot evolve population with no individuals."),
+            Error::InvalidStep(step) => write!(f, 
+                "Time step must be positive and finite, got {}.", step),
+            Error::MalformedExpr(reason) =>
+                write!(f, "Malformed expression, {}.", reason),
+            Error::UnmappedOperator =>
+                write!(f, "Encountered operator not in map."),
+            Error::UnknownVariable(idx) =>
+                write!(f, "State variable {} does not exist.", idx),
+            Error::UnknownVelocity(idx) => write!(f, 
+                "Velocity of state variable {} does not exist.", idx),
+            Error::UnknownToken(token) =>
+                write!(f, "Unknown token {}.", token),
+            Error::Parse(message) =>
+                write!(f, "Could not parse expression, {}.", message),
+            Error::Data(error) => write!(f, "{}", error),
+        };
+    }
+}
+
+impl std::error::Error for Error { }
+
+impl From<DataError> for Error {
+    fn from(error: DataError) -> Error {
+        return Error::Data(error);
+    }
+}
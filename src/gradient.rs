@@ -0,0 +1,160 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Gradient matching, scoring expressions against derivatives estimated from
+// the data instead of simulating them.
+//_____________________________________________________________________________
+
+// Internal imports.
+use crate::metric::Sample;
+use crate::ode::{Order, State};
+use crate::preprocess::Differentiation;
+
+//_____________________________________________________________________________
+//                                                            Gradient Matching
+
+// A data point along with the derivative the expressions should give there.
+#[derive(Clone, Debug)]
+pub struct Target {
+    // The data point, with velocities estimated for second-order systems.
+    pub state: State,
+    // The estimated first derivative, or second for second-order systems.
+    pub derivative: Vec<f64>,
+}
+
+/* targets
+* Estimate the derivatives expressions of the given order should give at
+* each data point.
+* Input:
+*     states - Data ordered by time.
+*     order - Order of the ODEs being fit.
+*     differentiation - How the derivatives are estimated.
+*/
+pub fn targets(
+    states: &[State],
+    order: Order,
+    differentiation: Differentiation) -> Vec<Target> {
+    let velocities = differentiation.differentiate(states, 1);
+    return match order {
+        Order::First => states.iter().zip(velocities)
+            .map(|(state, derivative)|
+                Target {state: state.clone(), derivative})
+            .collect(),
+        Order::Second => {
+            let accelerations = differentiation.differentiate(states, 2);
+            states.iter().zip(velocities).zip(accelerations)
+                .map(|((state, velocities), derivative)| Target {
+                    state: State::with_velocities(
+                        state.time, state.values.clone(), velocities),
+                    derivative,
+                })
+                .collect()
+        },
+    };
+}
+
+/* samples
+* Compare the expressions evaluated at each target against its derivative.
+* Each sample spans the data points on either side of it, as when sampling
+* a simulated trajectory at the data times.
+* Input:
+*     rhs - Values of the expressions at a state.
+*     targets - Derivatives estimated from the data, see targets.
+*/
+pub fn samples<F>(rhs: &F, targets: &[Target]) -> Vec<Sample>
+    where F: Fn(&State) -> Vec<f64> {
+    let last = targets.len().saturating_sub(1);
+    return targets.iter().enumerate()
+        .map(|(idx, target)| {
+            let before = &targets[idx.saturating_sub(1)];
+            let after = &targets[(idx + 1).min(last)];
+            Sample {
+                time: target.state.time,
+                residuals: rhs(&target.state).iter()
+                    .zip(target.derivative.iter())
+                    .map(|(value, derivative)| value - derivative)
+                    .collect(),
+                observed: target.derivative.clone(),
+                weight: 1.0,
+                span: after.state.time - before.state.time,
+            }
+        })
+        .collect();
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ode::Expr;
+    use crate::operator::builtin_map;
+
+    const TOLERANCE: f64 = 1e-9;
+
+    // Unevenly sampled data of x = t^2, on which finite differences are
+    // exact.
+    fn quadratic() -> Vec<State> {
+        return [0.0, 0.1, 0.3, 0.4, 0.7, 1.0, 1.2].iter()
+            .map(|time| State::new(*time, time * time))
+            .collect();
+    }
+
+    #[test]
+    fn first_order_targets_are_first_derivatives() {
+        let states = quadratic();
+        let targets = targets(&states, Order::First, 
+            Differentiation::FiniteDifference);
+        for (target, state) in targets.iter().zip(states.iter()) {
+            assert_eq!(target.state, *state);
+            assert!((target.derivative[0] - 2.0 * state.time).abs() 
+                < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn second_order_targets_carry_velocities() {
+        let states = quadratic();
+        let targets = targets(&states, Order::Second, 
+            Differentiation::FiniteDifference);
+        for (target, state) in targets.iter().zip(states.iter()) {
+            assert!((target.state.velocities[0] - 2.0 * state.time).abs() 
+                < TOLERANCE);
+            assert!((target.derivative[0] - 2.0).abs() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn true_model_has_no_residual() {
+        let map = builtin_map();
+        let expr = Expr::parse("MUL 2 TIME", &map).unwrap();
+        let rhs = |state: &State| vec![expr.eval(state)];
+        let states = quadratic();
+        let samples = samples(&rhs, &targets(&states, Order::First, 
+            Differentiation::FiniteDifference));
+
+        assert_eq!(samples.len(), states.len());
+        for sample in samples.iter() {
+            assert!(sample.residuals[0].abs() < TOLERANCE);
+            assert_eq!(sample.weight, 1.0);
+        }
+        // Each sample spans the data points on either side.
+        assert_eq!(samples[0].span, 0.1);
+        assert!((samples[3].span - 0.4).abs() < TOLERANCE);
+        assert!((samples[6].span - 0.2).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn residuals_are_model_less_derivative() {
+        let rhs = |_: &State| vec![1.0];
+        let states = quadratic();
+        let targets = targets(&states, Order::First, 
+            Differentiation::FiniteDifference);
+        for (sample, target) in samples(&rhs, &targets).iter()
+            .zip(targets.iter()) {
+            assert_eq!(sample.residuals[0], 1.0 - target.derivative[0]);
+            assert_eq!(sample.observed, target.derivative);
+        }
+        assert!(samples(&rhs, &[]).is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Generation of random expressions, such as those of the initial population.
+//_____________________________________________________________________________
+
+// External imports.
+use rand::Rng;
+
+// Internal imports.
+use crate::ode::Expr;
+use crate::operator::{Operator, OperatorMap, ToOperator};
+
+// Anonymous constants are drawn uniformly from -CONSTANT_RANGE to
+// CONSTANT_RANGE.
+const CONSTANT_RANGE: f64 = 10.0;
+
+//_____________________________________________________________________________
+//                                                          Initialization Type
+
+// Shapes of the trees generated (Koza, 1992).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Initialization {
+    // Leaves and operators are drawn anywhere above the maximum depth, so
+    // that trees vary in shape and size.
+    Grow,
+    // Only operators are drawn above the maximum depth, so that every leaf
+    // is at the maximum depth.
+    Full,
+    // Half of the trees by grow and half by full, with the maximum depth of
+    // each drawn evenly between the minimum and maximum depths.
+    RampedHalfAndHalf,
+}
+
+//_____________________________________________________________________________
+//                                                      Initializer Type & Impl
+
+// How random expressions are generated.
+#[derive(Copy, Clone, Debug)]
+pub struct Initializer {
+    pub method: Initialization,
+    // Depths of the trees generated, see Expr::depth. Only operators are
+    // drawn above the minimum depth.
+    pub min_depth: usize,
+    pub max_depth: usize,
+
+    // Relative probability of each kind of node being drawn where both
+    // leaves and operators may be: time, a state variable, a constant (named
+    // in the map or anonymous) and an operator taking arguments. Where only
+    // leaves may be, they are drawn in the same proportions.
+    pub time: f64,
+    pub variable: f64,
+    pub constant: f64,
+    pub operator: f64,
+}
+
+impl Initializer {
+    /* new
+    * Create the default initializer, ramped half-and-half from depth one to
+    * four, drawing operators twice as often as each kind of leaf.
+    */
+    pub fn new() -> Initializer {
+        return Initializer {
+            method: Initialization::RampedHalfAndHalf,
+            min_depth: 1,
+            max_depth: 4,
+            time: 1.0,
+            variable: 1.0,
+            constant: 1.0,
+            operator: 2.0,
+        };
+    }
+
+    /* generate
+    * Generate a random expression.
+    * Input:
+    *     map - Operators to draw from.
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     The expression. Note that it is shallower than the minimum depth
+    *     if the map has no operators taking arguments.
+    */
+    pub fn generate<R>(&self, map: &OperatorMap, rng: &mut R) -> Expr
+        where R: Rng + ?Sized {
+        let max_depth = self.max_depth.max(self.min_depth);
+        let (full, depth) = match self.method {
+            Initialization::Grow => (false, max_depth),
+            Initialization::Full => (true, max_depth),
+            Initialization::RampedHalfAndHalf => (rng.gen::<bool>(), 
+                rng.gen_range(self.min_depth, max_depth + 1)),
+        };
+
+        // Full trees are forced to draw operators down to the maximum depth.
+        let forced = match full {
+            true => depth,
+            false => self.min_depth,
+        };
+        let mut operators = Vec::new();
+        self.node(0, depth, forced, map, rng, &mut operators);
+        return Expr::from_operators(operators).unwrap();
+    }
+
+    /* node
+    * Draw the operator at the given level of the tree, then its arguments.
+    * Only operators taking arguments are drawn above the forced level, and
+    * only leaves at the given depth.
+    */
+    fn node<R>(
+        &self,
+        level: usize,
+        depth: usize,
+        forced: usize,
+        map: &OperatorMap,
+        rng: &mut R,
+        operators: &mut Vec<Operator>)
+        where R: Rng + ?Sized {
+        let functions: Vec<&Operator> = map.operators().iter()
+            .filter(|operator| operator.arity() > 0)
+            .collect();
+        let operator = if level >= depth || functions.is_empty() {
+            self.leaf(map, rng)
+        } else if level < forced {
+            *functions[rng.gen_range(0, functions.len())]
+        } else {
+            let total = self.time + self.variable + self.constant
+                + self.operator;
+            match total > 0.0 && rng.gen_range(0.0, total) < self.operator {
+                true => *functions[rng.gen_range(0, functions.len())],
+                false => self.leaf(map, rng),
+            }
+        };
+
+        operators.push(operator);
+        for _ in 0..operator.arity() {
+            self.node(level + 1, depth, forced, map, rng, operators);
+        }
+    }
+
+    /* leaf
+    * Draw a time, state variable or constant leaf, a state variable if none
+    * have positive probability.
+    */
+    fn leaf<R>(&self, map: &OperatorMap, rng: &mut R) -> Operator
+        where R: Rng + ?Sized {
+        let total = self.time + self.variable + self.constant;
+        if total <= 0.0 {
+            return *map.rand_variable(rng);
+        }
+
+        let draw = rng.gen_range(0.0, total);
+        if draw < self.time {
+            return Operator::Time;
+        } else if draw < self.time + self.variable {
+            return *map.rand_variable(rng);
+        }
+
+        // Named constants are drawn as often as an anonymous one.
+        let named: Vec<&Operator> = map.operators().iter()
+            .filter(|operator| matches!(operator, Operator::Constant(_)))
+            .collect();
+        let idx = rng.gen_range(0, named.len() + 1);
+        return match named.get(idx) {
+            Some(constant) => **constant,
+            None => rng.gen_range(-CONSTANT_RANGE, CONSTANT_RANGE)
+                .to_operator(),
+        };
+    }
+}
+
+impl Default for Initializer {
+    fn default() -> Initializer {
+        return Initializer::new();
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::operator::builtin_map;
+
+    const METHODS: [Initialization; 3] = [
+        Initialization::Grow,
+        Initialization::Full,
+        Initialization::RampedHalfAndHalf,
+    ];
+
+    fn initializer(method: Initialization, min_depth: usize, 
+        max_depth: usize) -> Initializer {
+        return Initializer {method, min_depth, max_depth, ..Initializer::new()};
+    }
+
+    // Depth of each leaf of an expression, in prefix order.
+    fn leaf_depths(expr: &Expr) -> Vec<usize> {
+        let mut depths = Vec::new();
+        // Levels of the arguments still to be read.
+        let mut pending = vec![0];
+        for operator in expr.operators().iter() {
+            let level = pending.pop().unwrap();
+            match operator.arity() {
+                0 => depths.push(level),
+                arity => pending.extend(vec![level + 1; arity]),
+            }
+        }
+        return depths;
+    }
+
+    #[test]
+    fn full_trees_have_every_leaf_at_the_maximum_depth() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for max_depth in 0..5 {
+            let initializer = initializer(Initialization::Full, 0, max_depth);
+            for _ in 0..50 {
+                let expr = initializer.generate(&map, &mut rng);
+                assert!(leaf_depths(&expr).iter().all(|d| *d == max_depth));
+            }
+        }
+    }
+
+    #[test]
+    fn grown_trees_are_within_the_depths() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let initializer = initializer(Initialization::Grow, 2, 4);
+        let mut depths = [false; 5];
+        for _ in 0..500 {
+            let depth = initializer.generate(&map, &mut rng).depth();
+            assert!((2..=4).contains(&depth));
+            depths[depth] = true;
+        }
+        // Grown trees vary in depth.
+        assert!(depths[2] && depths[3] && depths[4]);
+    }
+
+    #[test]
+    fn ramped_trees_are_within_the_depths() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let initializer = 
+            initializer(Initialization::RampedHalfAndHalf, 1, 4);
+        let mut depths = [false; 5];
+        for _ in 0..500 {
+            let depth = initializer.generate(&map, &mut rng).depth();
+            assert!((1..=4).contains(&depth));
+            depths[depth] = true;
+        }
+        assert!(depths[1..].iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn minimum_depth_above_the_maximum_is_exact() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        for method in METHODS.iter() {
+            let initializer = initializer(*method, 3, 1);
+            for _ in 0..50 {
+                let expr = initializer.generate(&map, &mut rng);
+                assert!(leaf_depths(&expr).iter().all(|d| *d == 3), 
+                    "{:?}", method);
+            }
+        }
+    }
+
+    #[test]
+    fn maps_without_operators_give_leaves() {
+        // Only time and position, as documented on generate.
+        let map = OperatorMap::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        for method in METHODS.iter() {
+            let initializer = initializer(*method, 2, 4);
+            for _ in 0..50 {
+                let expr = initializer.generate(&map, &mut rng);
+                assert_eq!(expr.size(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_follow_their_weights() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(6);
+        let initializer = Initializer {
+            time: 1.0, 
+            variable: 0.0, 
+            constant: 0.0, 
+            ..initializer(Initialization::Full, 0, 2)
+        };
+        for _ in 0..50 {
+            let expr = initializer.generate(&map, &mut rng);
+            assert!(expr.operators().iter()
+                .filter(|operator| operator.arity() == 0)
+                .all(|operator| *operator == Operator::Time));
+        }
+    }
+}
@@ -0,0 +1,532 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Numerical integration schemes used to simulate candidate ODEs.
+//_____________________________________________________________________________
+
+// Internal imports.
+use crate::ode::State;
+
+// Step size control of adaptive integration: the safety factor applied to
+// the optimal step, the bounds on how much a step may shrink or grow, and
+// the shortest step relative to the current time.
+const SAFETY: f64 = 0.9;
+const MIN_FACTOR: f64 = 0.2;
+const MAX_FACTOR: f64 = 5.0;
+const MIN_STEP: f64 = 1e-12;
+
+// Newton iterations of implicit integrators stop after this many iterations
+// or once the update is this small relative to the variables.
+const NEWTON_ITERATIONS: usize = 10;
+const NEWTON_TOLERANCE: f64 = 1e-10;
+
+//_____________________________________________________________________________
+//                                                             Integrator Trait
+
+pub trait Integrator {
+    /* step
+    * Advance the integrated variables of a state, the state variables
+    * followed by any velocities, by a single step.
+    * Input:
+    *     rhs - Derivative of the integrated variables at a state. Variables
+    *         beyond those it gives are held constant.
+    *     state - The state to step from.
+    *     step - Length of the step, or for adaptive integrators the longest
+    *         step that may be taken.
+    * Output:
+    *     The new state, the length of the step taken and the suggested
+    *     length of the next step.
+    */
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64);
+
+    /* is_adaptive
+    * Whether the integrator chooses its own step lengths by error control,
+    * rather than taking steps of the given length.
+    */
+    fn is_adaptive(&self) -> bool {
+        return false;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                       Fixed-Step Integrators
+
+// Explicit (forward) Euler method, first order.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Euler;
+
+impl Integrator for Euler {
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64) {
+        let k1 = rhs(state);
+        return (offset(state, step, &[(step, &k1)]), step, step);
+    }
+}
+
+// Explicit midpoint method, second order.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Midpoint;
+
+impl Integrator for Midpoint {
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64) {
+        let h = step;
+        let k1 = rhs(state);
+        let k2 = rhs(&offset(state, h / 2.0, &[(h / 2.0, &k1)]));
+        return (offset(state, h, &[(h, &k2)]), h, h);
+    }
+}
+
+// Classic fourth order Runge-Kutta method.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Rk4;
+
+impl Integrator for Rk4 {
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64) {
+        let h = step;
+        let k1 = rhs(state);
+        let k2 = rhs(&offset(state, h / 2.0, &[(h / 2.0, &k1)]));
+        let k3 = rhs(&offset(state, h / 2.0, &[(h / 2.0, &k2)]));
+        let k4 = rhs(&offset(state, h, &[(h, &k3)]));
+
+        let new_state = offset(state, h, &[
+            (h / 6.0, &k1), (h / 3.0, &k2), (h / 3.0, &k3), (h / 6.0, &k4),
+        ]);
+        return (new_state, h, h);
+    }
+}
+
+// Implicit (backward) Euler method, first order. Unconditionally stable, so
+// stiff candidates decay rather than blow up at any step length.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BackwardEuler;
+
+impl Integrator for BackwardEuler {
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64) {
+        // Solve y1 = y0 + h f(t + h, y1), starting from the explicit step.
+        let k1 = rhs(state);
+        let y0 = integrated(state, k1.len());
+        let guess = offset(state, step, &[(step, &k1)]);
+        let y1 = newton(
+            rhs, state, state.time + step, &y0, step,
+            integrated(&guess, k1.len()));
+        return (at(state, state.time + step, &y1), step, step);
+    }
+}
+
+// TR-BDF2 method, second order. Each step is a trapezoidal stage followed
+// by a second order backward differentiation (BDF2) stage, which keeps the
+// stability of BDF2 without needing previous steps.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TrBdf2;
+
+impl Integrator for TrBdf2 {
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64) {
+        let h = step;
+        let gamma = 2.0 - 2.0_f64.sqrt();
+
+        // Trapezoidal stage to t + gamma h.
+        let k1 = rhs(state);
+        let n = k1.len();
+        let y0 = integrated(state, n);
+        let base: Vec<f64> = y0.iter().zip(k1.iter())
+            .map(|(y, k)| y + gamma * h / 2.0 * k)
+            .collect();
+        let guess = 
+            integrated(&offset(state, gamma * h, &[(gamma * h, &k1)]), n);
+        let y_gamma = newton(
+            rhs, state, state.time + gamma * h, &base, gamma * h / 2.0, guess);
+
+        // BDF2 stage through y0 and y_gamma to t + h.
+        let w_gamma = 1.0 / (gamma * (2.0 - gamma));
+        let w0 = (1.0 - gamma).powi(2) / (gamma * (2.0 - gamma));
+        let base: Vec<f64> = y_gamma.iter().zip(y0.iter())
+            .map(|(y_gamma, y0)| w_gamma * y_gamma - w0 * y0)
+            .collect();
+        let coefficient = (1.0 - gamma) / (2.0 - gamma) * h;
+        let y1 = newton(
+            rhs, state, state.time + h, &base, coefficient, y_gamma.clone());
+
+        return (at(state, state.time + h, &y1), h, h);
+    }
+}
+
+//_____________________________________________________________________________
+//                                              Adaptive Integrator & Tolerance
+
+// Error tolerance of adaptive integration. Each step keeps the estimated
+// error of every variable below absolute + relative * |value|.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Tolerance {
+    pub relative: f64,
+    pub absolute: f64,
+}
+
+impl Tolerance {
+    /* new
+    * Create a tolerance from its relative and absolute parts.
+    */
+    pub fn new(relative: f64, absolute: f64) -> Tolerance {
+        return Tolerance {relative, absolute};
+    }
+
+    /* error
+    * Get the root mean square of the estimated error of each integrated
+    * variable of a step, relative to the tolerance. Steps are accepted when
+    * this is at most one.
+    * Input:
+    *     state - The state the step was taken from.
+    *     new_state - The state the step was taken to.
+    *     difference - Estimated error of each variable.
+    */
+    fn error(&self, state: &State, new_state: &State, difference: &[f64])
+        -> f64 {
+        if difference.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f64 = state.variables().zip(new_state.variables())
+            .zip(difference.iter())
+            .map(|((value, new_value), difference)| {
+                let scale = self.absolute
+                    + self.relative * value.abs().max(new_value.abs());
+                (difference / scale).powi(2)
+            })
+            .sum();
+        return (sum / difference.len() as f64).sqrt();
+    }
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        return Tolerance::new(1e-6, 1e-9);
+    }
+}
+
+// Embedded fifth order Dormand-Prince method (RK45). The difference between
+// the fifth and fourth order solutions estimates the error of a step, which
+// is rejected and retried with a shorter step while it exceeds the
+// tolerance.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DormandPrince {
+    pub tolerance: Tolerance,
+}
+
+impl DormandPrince {
+    /* new
+    * Create the integrator with the given error tolerance.
+    */
+    pub fn new(tolerance: Tolerance) -> DormandPrince {
+        return DormandPrince {tolerance};
+    }
+}
+
+impl Integrator for DormandPrince {
+    fn step(&self, rhs: &dyn Fn(&State) -> Vec<f64>, state: &State, step: f64)
+        -> (State, f64, f64) {
+        if step <= 0.0 {
+            return (state.clone(), 0.0, 0.0);
+        }
+
+        // Once a candidate has diverged there is nothing left to control,
+        // so skip straight to the end of this and any following steps.
+        if !state.variables().all(|value| value.is_finite()) {
+            let mut new_state = state.clone();
+            new_state.time += step;
+            return (new_state, step, f64::INFINITY);
+        }
+
+        let mut h = step;
+        let min_step = MIN_STEP * state.time.abs().max(1.0);
+
+        let k1 = rhs(state);
+        loop {
+            let k2 = rhs(&offset(state, h / 5.0, &[(h / 5.0, &k1)]));
+            let k3 = rhs(&offset(state, 3.0 * h / 10.0, &[
+                (3.0 * h / 40.0, &k1), (9.0 * h / 40.0, &k2)]));
+            let k4 = rhs(&offset(state, 4.0 * h / 5.0, &[
+                (44.0 * h / 45.0, &k1), (-56.0 * h / 15.0, &k2),
+                (32.0 * h / 9.0, &k3)]));
+            let k5 = rhs(&offset(state, 8.0 * h / 9.0, &[
+                (19372.0 * h / 6561.0, &k1), (-25360.0 * h / 2187.0, &k2),
+                (64448.0 * h / 6561.0, &k3), (-212.0 * h / 729.0, &k4)]));
+            let k6 = rhs(&offset(state, h, &[
+                (9017.0 * h / 3168.0, &k1), (-355.0 * h / 33.0, &k2),
+                (46732.0 * h / 5247.0, &k3), (49.0 * h / 176.0, &k4),
+                (-5103.0 * h / 18656.0, &k5)]));
+            let new_state = offset(state, h, &[
+                (35.0 * h / 384.0, &k1), (500.0 * h / 1113.0, &k3),
+                (125.0 * h / 192.0, &k4), (-2187.0 * h / 6784.0, &k5),
+                (11.0 * h / 84.0, &k6)]);
+            let k7 = rhs(&new_state);
+
+            // Difference between the fifth and fourth order solutions.
+            let error_terms = [
+                (71.0 * h / 57600.0, &k1), (-71.0 * h / 16695.0, &k3),
+                (71.0 * h / 1920.0, &k4), (-17253.0 * h / 339200.0, &k5),
+                (22.0 * h / 525.0, &k6), (-h / 40.0, &k7)];
+            let mut difference = vec![0.0; k1.len()];
+            for (coefficient, k) in error_terms.iter() {
+                for (value, k) in difference.iter_mut().zip(k.iter()) {
+                    *value += coefficient * k;
+                }
+            }
+            let error = self.tolerance.error(state, &new_state, &difference);
+
+            // Standard step size controller, limiting how fast the step
+            // changes.
+            let factor = match error.is_nan() {
+                true => MIN_FACTOR,
+                false =>
+                    (SAFETY * error.powf(-0.2)).clamp(MIN_FACTOR, MAX_FACTOR),
+            };
+
+            // Accept the step, giving up on error control for candidates
+            // that have diverged or need steps too short to make progress.
+            if error <= 1.0 || error.is_nan() || h <= min_step {
+                return (new_state, h, (h * factor).max(min_step));
+            }
+
+            h = (h * factor).max(min_step);
+        }
+    }
+
+    fn is_adaptive(&self) -> bool {
+        return true;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                      Helpers
+
+/* offset
+* Get the state offset from the given one by a linear combination of
+* increments of the integrated variables, as used by Runge-Kutta methods.
+* Input:
+*     dt - Time offset.
+*     terms - Pairs of coefficients (including the step length) and
+*         increments.
+*/
+fn offset(state: &State, dt: f64, terms: &[(f64, &[f64])]) -> State {
+    let mut shifted = state.clone();
+    shifted.time += dt;
+    for (coefficient, k) in terms.iter() {
+        for (value, k) in shifted.variables_mut().zip(k.iter()) {
+            *value += coefficient * k;
+        }
+    }
+    return shifted;
+}
+
+/* integrated
+* Get the first n integrated variables of a state.
+*/
+fn integrated(state: &State, n: usize) -> Vec<f64> {
+    return state.variables().take(n).copied().collect();
+}
+
+/* at
+* Get the state at the given time with its first integrated variables
+* replaced by the given values.
+*/
+fn at(state: &State, time: f64, values: &[f64]) -> State {
+    let mut new_state = state.clone();
+    new_state.time = time;
+    for (value, new_value) in new_state.variables_mut().zip(values.iter()) {
+        *value = *new_value;
+    }
+    return new_state;
+}
+
+/* newton
+* Solve y = base + coefficient * f(time, y) for the integrated variables y by
+* Newton's method, with the Jacobian of f found by finite differences.
+* Input:
+*     state - State giving the variables held constant.
+*     guess - Initial value of y.
+* Output:
+*     The solution, or NaN values if the Jacobian is singular.
+*/
+fn newton(
+    rhs: &dyn Fn(&State) -> Vec<f64>,
+    state: &State,
+    time: f64,
+    base: &[f64],
+    coefficient: f64,
+    guess: Vec<f64>) -> Vec<f64> {
+    let n = base.len();
+    let mut y = guess;
+
+    for _ in 0..NEWTON_ITERATIONS {
+        let f = rhs(&at(state, time, &y));
+        let residual: Vec<f64> = (0..n)
+            .map(|i| y[i] - base[i] - coefficient * f[i])
+            .collect();
+
+        // Matrix I - coefficient * J of the residual, column by column.
+        let mut matrix = vec![vec![0.0; n]; n];
+        for j in 0..n {
+            let delta = f64::EPSILON.sqrt() * y[j].abs().max(1.0);
+            let mut shifted = y.clone();
+            shifted[j] += delta;
+            let f_shifted = rhs(&at(state, time, &shifted));
+            for i in 0..n {
+                let jacobian = (f_shifted[i] - f[i]) / delta;
+                matrix[i][j] = -coefficient * jacobian;
+            }
+            matrix[j][j] += 1.0;
+        }
+
+        let update = match solve(matrix, residual) {
+            Some(update) => update,
+            None => return vec![f64::NAN; n],
+        };
+
+        let mut largest_update: f64 = 0.0;
+        let mut largest_value: f64 = 0.0;
+        for (value, update) in y.iter_mut().zip(update.iter()) {
+            *value -= update;
+            largest_update = largest_update.max(update.abs());
+            largest_value = largest_value.max(value.abs());
+        }
+
+        // Note that NaN comparisons fail, so diverged solves run out the
+        // iterations and return NaN values.
+        if largest_update <= NEWTON_TOLERANCE * (1.0 + largest_value) {
+            break;
+        }
+    }
+
+    return y;
+}
+
+/* solve
+* Solve the linear system Ax = b by Gaussian elimination with partial
+* pivoting.
+* Output:
+*     The solution x, or None if A is singular.
+*/
+pub(crate) fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) 
+    -> Option<Vec<f64>> {
+    let n = b.len();
+
+    for col in 0..n {
+        // Swap the row with the largest pivot into place.
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col] == 0.0 || a[pivot][col].is_nan() {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in (col + 1)..n {
+            let factor = a[row][col] / a[col][col];
+            let (upper, lower) = a.split_at_mut(row);
+            for (value, pivot) in lower[0][col..].iter_mut()
+                .zip(upper[col][col..].iter()) {
+                *value -= factor * pivot;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    // Back substitution.
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = ((row + 1)..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    return Some(x);
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decay x' = -x from x(0) = 1, with the solution e^-t.
+    fn decay(state: &State) -> Vec<f64> {
+        return vec![-state.position()];
+    }
+
+    /* error
+    * Get the error at t = 1 of integrating the decay in n fixed steps.
+    */
+    fn error(integrator: &dyn Integrator, n: usize) -> f64 {
+        let h = 1.0 / n as f64;
+        let mut state = State::new(0.0, 1.0);
+        for _ in 0..n {
+            state = integrator.step(&decay, &state, h).0;
+        }
+        return (state.position() - (-1.0_f64).exp()).abs();
+    }
+
+    /* order
+    * Estimate the order of convergence from the errors as the number of 
+    * steps doubles.
+    */
+    fn order(integrator: &dyn Integrator) -> f64 {
+        return (error(integrator, 20) / error(integrator, 40)).log2();
+    }
+
+    fn assert_order(integrator: &dyn Integrator, expected: f64) {
+        let order = order(integrator);
+        assert!((order - expected).abs() < 0.1,
+            "expected order {}, found {}", expected, order);
+    }
+
+    #[test]
+    fn euler_is_first_order() {
+        assert_order(&Euler, 1.0);
+    }
+
+    #[test]
+    fn backward_euler_is_first_order() {
+        assert_order(&BackwardEuler, 1.0);
+    }
+
+    #[test]
+    fn midpoint_is_second_order() {
+        assert_order(&Midpoint, 2.0);
+    }
+
+    #[test]
+    fn tr_bdf2_is_second_order() {
+        assert_order(&TrBdf2, 2.0);
+    }
+
+    #[test]
+    fn rk4_is_fourth_order() {
+        assert_order(&Rk4, 4.0);
+    }
+
+    #[test]
+    fn dormand_prince_stays_within_tolerance() {
+        for tolerance in [1e-4, 1e-6, 1e-8].iter() {
+            let integrator = DormandPrince::new(
+                Tolerance::new(*tolerance, *tolerance));
+            let mut state = State::new(0.0, 1.0);
+            let mut suggested: f64 = 0.1;
+            let mut steps = 0;
+            while state.time < 1.0 {
+                let limit = suggested.min(1.0 - state.time);
+                let (new_state, taken, next) = 
+                    integrator.step(&decay, &state, limit);
+                assert!(taken > 0.0 && taken <= limit);
+                state = new_state;
+                suggested = next;
+                steps += 1;
+            }
+            let error = (state.position() - (-1.0_f64).exp()).abs();
+            assert!(error < *tolerance, 
+                "error {} for tolerance {}", error, tolerance);
+            assert!(steps < 100);
+        }
+    }
+}
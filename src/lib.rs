@@ -0,0 +1,42 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Library root. Genetic programming search for an ODE x' = f(x, t) fitting
+// given time and position data.
+//_____________________________________________________________________________
+// Explicit returns are used throughout as a matter of style.
+#![allow(clippy::needless_return)]
+
+pub mod crossover;
+pub mod data;
+pub mod error;
+pub mod gradient;
+pub mod initialize;
+pub mod integrate;
+pub mod metric;
+pub mod mutation;
+pub mod ode;
+pub mod operator;
+pub mod parse;
+pub mod render;
+pub mod simplify;
+pub mod population;
+pub mod preprocess;
+pub mod selection;
+pub mod tree;
+
+// Commonly used types, re-exported for convenience.
+pub use crossover::Crossover;
+pub use data::Trajectory;
+pub use error::{Error, Result};
+pub use initialize::{Initialization, Initializer};
+pub use integrate::{Integrator, Tolerance};
+pub use metric::{Aggregate, FitnessMetric, Sampling};
+pub use mutation::Mutation;
+pub use ode::{Expr, Order, State, System};
+pub use operator::{Operator, OperatorMap, ToOperator};
+pub use population::{InitialCondition, InitialVelocity, Individual};
+pub use population::{Evaluation, Limits, Overflow, Population};
+pub use preprocess::{Differentiation, Pipeline, Preprocessor};
+pub use render::Renderer;
+pub use selection::Selection;
+pub use simplify::Simplifier;
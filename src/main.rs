@@ -3,64 +3,304 @@
 // Entry point.
 //_____________________________________________________________________________
 // #![allow(dead_code)]
+// Explicit returns are used throughout as a matter of style.
+#![allow(clippy::needless_return)]
 
-mod operator;
-mod ode;
-mod population;
+mod cli;
 
-use operator::OperatorMap;
-use population::Population;
+use std::env;
+use std::process;
 
-fn main() {
-    let mut map = OperatorMap::new();
+use genetic_ode::render::Target;
+use genetic_ode::{data, operator, Individual, OperatorMap, Population};
+use genetic_ode::{Error, Operator, Order, State, System, Trajectory};
+use genetic_ode::{Renderer, Simplifier};
 
-    // Basic arithmetic operators.
-    map.insert((|x, y| x + y) as fn(f64, f64) -> f64, "ADD");
-    map.insert((|x, y| x - y) as fn(f64, f64) -> f64, "SUB");
-    map.insert((|x, y| x * y) as fn(f64, f64) -> f64, "MUL");
-    map.insert((|x, y| x / y) as fn(f64, f64) -> f64, "DIV");
+use cli::{Command, Options};
 
-    map.insert((|x| x * x) as fn(f64) -> f64, "SQUARE");
-    map.insert(f64::sqrt as fn(f64) -> f64, "SQRT");
-
-    // Trigonometric functions.
-    // map.insert(f64::cos as fn(f64) -> f64, "COS");
-    // map.insert(f64::sin as fn(f64) -> f64, "SIN");
-    // map.insert(f64::tan as fn(f64) -> f64, "TAN");
+fn main() {
+    let (command, options) = match cli::parse(env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, cli::USAGE);
+            process::exit(2);
+        },
+    };
 
-    // Logarithmic functions.
-    map.insert(f64::exp as fn(f64) -> f64, "EXP");
-    map.insert(f64::ln as fn(f64) -> f64, "LN");
+    if command == Command::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-    // We can use named constants too.
-    map.insert(1.0, "ONE");
-    map.insert(3.14159, "PI");
+    // Specify data, one trajectory per file, reporting any cleaning done.
+    let mut trajectories = Vec::new();
+    for path in options.data.iter() {
+        let cleaned = data::read_trajectory(path, &options.format)
+            .and_then(|trajectory| {
+                let (states, report) = 
+                    data::clean(trajectory.states, &options.cleaning)?;
+                if !report.is_clean() {
+                    eprintln!("{}: {}", path, report);
+                }
+                match states.is_empty() {
+                    true => Err(Error::EmptyData),
+                    false => Ok(Trajectory::new(trajectory.name, states)),
+                }
+            });
+        match cleaned {
+            Ok(trajectory) => trajectories.push(trajectory),
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                process::exit(1);
+            },
+        }
+    }
+    if trajectories.is_empty() {
+        trajectories.push(Trajectory::new("logistic", cli::default_data()));
+    }
 
-    // Specify data.
-    let times = vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+    // State variables after position are given the tokens POS1, POS2, ...
+    let dimension = trajectories[0].states[0].dimension();
+    let variable_tokens: Vec<String> = (1..dimension)
+        .map(|idx| format!("POS{}", idx))
+        .collect();
 
-    let positions = 
-        times.iter()
-        .map(|x: &f64| x.exp() / (1.0 + x.exp()))
+    // Velocities of second-order ODEs are given the tokens VEL, VEL1, ...
+    let velocity_tokens: Vec<String> = (0..dimension)
+        .map(|idx| match idx {
+            0 => String::from("VEL"),
+            _ => format!("VEL{}", idx),
+        })
         .collect();
 
+    // Enable the requested operators.
+    let mut map = OperatorMap::new();
+    for token in options.operators.iter() {
+        map.insert(operator::builtin(token).unwrap(), token);
+    }
+    for (idx, token) in variable_tokens.iter().enumerate() {
+        map.insert(Operator::Variable(idx + 1), token);
+    }
+    if options.order == Order::Second {
+        for (idx, token) in velocity_tokens.iter().enumerate() {
+            map.insert(Operator::Velocity(idx), token);
+        }
+    }
+
     // Construct population and simulate.
-    let size = 300;
-    let generations = 15;
-    let mut population = Population::new(times, positions);
-    population.grow(size, &map);
+    let mut population = match Population::try_from_trajectories(trajectories) {
+        Ok(population) => population,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        },
+    };
+    if let Err(error) = population.preprocess(&options.preprocess) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+    if let Err(error) = population.try_set_step(options.step) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+    population.set_integrator(options.integrator());
+    population.set_metric(options.metric());
+    population.set_sampling(options.sampling);
+    population.set_aggregate(options.aggregate);
+    population.set_evaluation(options.evaluation());
+    population.set_differentiation(options.differentiation());
+    population.set_selection(options.selection());
+    population.set_crossover(options.crossover());
+    population.set_mutation(options.mutation);
+    population.set_initializer(options.initializer);
+    population.set_limits(options.limits);
+    population.set_parsimony(options.parsimony);
+    population.set_order(options.order);
+    population.set_initial_velocity(options.initial_velocity);
+    population.set_initial_condition(options.initial_condition);
+
+    // Report the seed so that any run can be reproduced.
+    let seed = options.seed.unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    population.set_seed(seed);
 
-    for _ in 0..=generations {
+    if options.simplify {
+        population.set_simplifier(Some(Simplifier::new(&map)));
+    }
+
+    // Parse the expressions given on the command line.
+    for text in options.exprs.iter() {
+        let added = System::parse(text, &map)
+            .and_then(|system| population.try_add(system));
+        if let Err(error) = added {
+            eprintln!("{}: {}", text, error);
+            process::exit(1);
+        }
+    }
+
+    // Expressions given to eval and simulate are used in place of a fit, 
+    // otherwise they seed the initial population.
+    let individuals = if options.exprs.is_empty() || command == Command::Fit {
+        population.grow(options.size.saturating_sub(options.exprs.len()), &map);
+        fit(&mut population, &map, &options);
+        vec![population.best_fit().clone()]
+    } else {
+        population.population.clone()
+    };
+
+    for individual in individuals.iter() {
+        match command {
+            Command::Eval => eval(&population, &map, &options, individual),
+            Command::Simulate => 
+                simulate(&population, &map, &options, individual),
+            _ => (),
+        }
+    }
+}
+
+/* fit
+* Evolve the population, printing the best individuals of each generation.
+*/
+fn fit(population: &mut Population, map: &OperatorMap, options: &Options) {
+    for _ in 0..=options.generations {
         population.population.sort();
         println!("_________________________\n\
-        Generation {}:", 
+        Generation {}:",
         population.generation);
 
-        for individual in population.population.iter().take(10) {
-            println!("{}, fitness = {}", 
-                individual.expr.to_string(&map), 
+        for individual in population.population.iter().take(options.top) {
+            println!("{}, fitness = {}",
+                describe(&individual.system, map, options),
                 individual.fitness);
         }
-        population.evolve();
+        population.evolve(map);
+    }
+}
+
+/* eval
+* Print the residuals of an individual at each data point of each trajectory.
+*/
+fn eval(
+    population: &Population, 
+    map: &OperatorMap, 
+    options: &Options, 
+    best: &Individual) {
+    println!("_________________________\n\
+    Fit: {}, fitness = {}", 
+    describe(&best.system, map, options), 
+    best.fitness);
+
+    for (idx, trajectory) in population.trajectories().iter().enumerate() {
+        header(population, trajectory);
+        let fits = best.system.simulate(&trajectory.states, idx, 
+            population.step(), population.integrator(), false);
+        for (data, fit) in trajectory.states.iter().zip(fits.iter()) {
+            let residuals: Vec<f64> = data.values.iter()
+                .zip(fit.values.iter())
+                .map(|(data, fit)| data - fit)
+                .collect();
+            println!("t = {}, x = {}, fit = {}, residual = {}",
+                data.time,
+                values(data),
+                values(fit),
+                join(&residuals));
+        }
+    }
+}
+
+/* simulate
+* Print the trajectories predicted by an individual.
+*/
+fn simulate(
+    population: &Population, 
+    map: &OperatorMap, 
+    options: &Options, 
+    best: &Individual) {
+    println!("_________________________\n\
+    Fit: {}", describe(&best.system, map, options));
+
+    for (idx, trajectory) in population.trajectories().iter().enumerate() {
+        header(population, trajectory);
+        let fits = best.system.simulate(&trajectory.states, idx, 
+            population.step(), population.integrator(), options.dense);
+        for state in fits.iter() {
+            match state.velocities.len() {
+                0 => println!("t = {}, x = {}", state.time, values(state)),
+                1 => println!("t = {}, x = {}, v = {}", 
+                    state.time, values(state), state.velocities[0]),
+                _ => println!("t = {}, x = {}, v = ({})", 
+                    state.time, values(state), join(&state.velocities)),
+            }
+        }
+    }
+}
+
+/* header
+* Name the trajectory printed next, if there are several.
+*/
+fn header(population: &Population, trajectory: &Trajectory) {
+    if population.trajectories().len() > 1 {
+        println!("Trajectory: {}", trajectory.name);
+    }
+}
+
+/* describe
+* Print a system in the notation given by the options, separating the 
+* expressions of each state variable by semicolons and followed by any 
+* fitted initial values and evolved initial velocities.
+*/
+fn describe(system: &System, map: &OperatorMap, options: &Options) -> String {
+    let mut renderer = Renderer::new();
+    renderer.set_precision(options.precision);
+
+    let exprs = system.exprs().iter().enumerate().map(|(idx, expr)|
+        match options.notation {
+            None => expr.to_string(map),
+            Some(Target::Infix) => renderer.infix(expr, map).unwrap(),
+            Some(target) => 
+                renderer.equation(expr, map, idx, system.order(), target)
+                    .unwrap(),
+        });
+    let mut description = exprs.collect::<Vec<String>>().join("; ");
+
+    if let Some(initial_values) = system.initial_values() {
+        description.push_str(
+            &format!(", initial value = {}", conditions(initial_values)));
+    }
+    if let Some(velocities) = system.velocities() {
+        description.push_str(
+            &format!(", initial velocity = {}", conditions(velocities)));
     }
+    return description;
+}
+
+/* values
+* Format the state variables of a state, as a tuple if there are several.
+*/
+fn values(state: &State) -> String {
+    return match state.dimension() {
+        1 => state.position().to_string(),
+        _ => format!("({})", join(&state.values)),
+    };
+}
+
+/* conditions
+* Format the initial conditions of each trajectory, bracketing those of each
+* if there are several.
+*/
+fn conditions(trajectories: &[Vec<f64>]) -> String {
+    return match trajectories {
+        [values] => join(values),
+        _ => trajectories.iter()
+            .map(|values| format!("[{}]", join(values)))
+            .collect::<Vec<String>>()
+            .join(", "),
+    };
+}
+
+fn join(values: &[f64]) -> String {
+    return values.iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
 }
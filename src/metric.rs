@@ -0,0 +1,363 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Fitness metrics scoring how well a simulated trajectory fits the data.
+//_____________________________________________________________________________
+
+// Denominator below which relative errors are taken against this value
+// instead, so that data passing through zero does not give infinite errors.
+const RELATIVE_FLOOR: f64 = 1e-8;
+
+//_____________________________________________________________________________
+//                                                                  Sample Type
+
+// A point of a simulated trajectory compared against the data.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub time: f64,
+    // Simulated minus observed value of each compared state variable, where
+    // the observed value is linearly interpolated between data points.
+    pub residuals: Vec<f64>,
+    // Observed value of each compared state variable.
+    pub observed: Vec<f64>,
+    // Length of time the sample stands for relative to the time step, one
+    // for fixed-step integration and for samples at data times.
+    pub weight: f64,
+    // Time between the data points on either side of the sample.
+    pub span: f64,
+}
+
+// Where simulated trajectories are compared against the data.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Sampling {
+    // At every integration step, against the data interpolated between the
+    // data points on either side.
+    Steps,
+    // Only at the times of the data points, integrating exactly onto each so
+    // that unevenly sampled data is compared where it was observed.
+    DataTimes,
+}
+
+impl Default for Sampling {
+    fn default() -> Sampling {
+        return Sampling::Steps;
+    }
+}
+
+// How the fitness of each trajectory fit is combined into one.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Aggregate {
+    Sum,
+    Mean,
+    // The worst fit trajectory, so that no experiment is fit poorly.
+    Worst,
+}
+
+impl Aggregate {
+    /* combine
+    * Combine the fitness of each trajectory, NaN if any diverged.
+    */
+    pub fn combine(&self, fitnesses: &[f64]) -> f64 {
+        let sum = fitnesses.iter().sum::<f64>();
+        return match self {
+            Aggregate::Sum => sum,
+            Aggregate::Mean => sum / fitnesses.len() as f64,
+            Aggregate::Worst => fitnesses.iter()
+                .fold(f64::NEG_INFINITY, |max: f64, fitness|
+                    match max.is_nan() || fitness.is_nan() {
+                        true => f64::NAN,
+                        false => max.max(*fitness),
+                    }),
+        };
+    }
+}
+
+impl Default for Aggregate {
+    fn default() -> Aggregate {
+        return Aggregate::Sum;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                          FitnessMetric Trait
+
+pub trait FitnessMetric {
+    /* fitness
+    * Score the samples of a simulated trajectory, where lower is better.
+    * Input:
+    *     samples - Points of the trajectory compared against the data.
+    * Output:
+    *     The fitness, NaN if the trajectory diverged.
+    */
+    fn fitness(&self, samples: &[Sample]) -> f64;
+}
+
+/* mean
+* Get the weighted mean of a loss applied to every residual.
+*/
+fn mean<F>(samples: &[Sample], loss: F) -> f64
+    where F: Fn(f64, f64) -> f64 {
+    let mut total = 0.0;
+    let mut weights = 0.0;
+    for sample in samples.iter() {
+        for (residual, observed) in
+            sample.residuals.iter().zip(sample.observed.iter()) {
+            total += sample.weight * loss(*residual, *observed);
+            weights += sample.weight;
+        }
+    }
+
+    return match weights > 0.0 {
+        true => total / weights,
+        false => 0.0,
+    };
+}
+
+//_____________________________________________________________________________
+//                                                              Builtin Metrics
+
+// Sum over samples of the area of the triangle between the simulated point
+// and the data points on either side, the original metric of the crate.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Area;
+
+impl FitnessMetric for Area {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        // The triangle has the data points as its base and the residual as
+        // its height, measured along the position axis.
+        return samples.iter()
+            .map(|sample| sample.residuals.iter()
+                .map(|residual| sample.weight * sample.span / 2.0
+                    * residual.abs())
+                .sum::<f64>())
+            .sum();
+    }
+}
+
+// Mean squared error, for Gaussian measurement noise.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Mse;
+
+impl FitnessMetric for Mse {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        return mean(samples, |residual, _| residual * residual);
+    }
+}
+
+// Root mean squared error, the same ordering as MSE in the units of the data.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Rmse;
+
+impl FitnessMetric for Rmse {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        return Mse.fitness(samples).sqrt();
+    }
+}
+
+// Mean absolute error, for heavier tailed (Laplacian) noise.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Mae;
+
+impl FitnessMetric for Mae {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        return mean(samples, |residual, _| residual.abs());
+    }
+}
+
+// Mean Huber loss, quadratic for residuals up to delta and linear beyond, so
+// that outliers count less than under MSE.
+#[derive(Copy, Clone, Debug)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Huber {
+    /* new
+    * Create the metric with the given threshold between quadratic and linear
+    * loss.
+    */
+    pub fn new(delta: f64) -> Huber {
+        return Huber {delta};
+    }
+}
+
+impl Default for Huber {
+    fn default() -> Huber {
+        return Huber::new(1.0);
+    }
+}
+
+impl FitnessMetric for Huber {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        let delta = self.delta;
+        return mean(samples, |residual, _| match residual.abs() <= delta {
+            true => residual * residual / 2.0,
+            false => delta * (residual.abs() - delta / 2.0),
+        });
+    }
+}
+
+// Largest absolute error, for fits that must stay within a bound.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MaxError;
+
+impl FitnessMetric for MaxError {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        // Note that f64::max ignores NaN, so divergence is checked for.
+        return samples.iter()
+            .flat_map(|sample| sample.residuals.iter())
+            .fold(0.0, |max: f64, residual|
+                match max.is_nan() || residual.is_nan() {
+                    true => f64::NAN,
+                    false => max.max(residual.abs()),
+                });
+    }
+}
+
+// Mean absolute error relative to the observed value, for noise that scales
+// with the measurement.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Relative;
+
+impl FitnessMetric for Relative {
+    fn fitness(&self, samples: &[Sample]) -> f64 {
+        return mean(samples, |residual, observed|
+            residual.abs() / observed.abs().max(RELATIVE_FLOOR));
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrate::{Integrator, Rk4};
+    use crate::ode::{Expr, State};
+    use crate::operator::builtin_map;
+
+    const TOLERANCE: f64 = 1e-12;
+
+    // Samples of weight one with the given residuals and observed values.
+    fn samples(points: &[(f64, f64)]) -> Vec<Sample> {
+        return points.iter()
+            .map(|(residual, observed)| Sample {
+                time: 0.0,
+                residuals: vec![*residual],
+                observed: vec![*observed],
+                weight: 1.0,
+                span: 1.0,
+            })
+            .collect();
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < TOLERANCE,
+            "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn area_matches_the_original_shoelace_metric() {
+        let map = builtin_map();
+        let expr = Expr::parse("MUL 0.5 SIN ADD TIME POS", &map).unwrap();
+        let data = vec![
+            State::new(0.0, 1.0), State::new(0.25, 1.2),
+            State::new(0.6, 1.1), State::new(1.0, 1.5),
+        ];
+        let step = 0.1;
+
+        // The fitness as first computed by the crate, the area of the
+        // triangle between each simulated point and the data on either side
+        // by the shoelace formula.
+        let rhs = |state: &State| vec![expr.eval(state)];
+        let mut expected = 0.0;
+        let mut curr = data[0].clone();
+        let mut idx = 1;
+        while idx < data.len() {
+            let (prev, next) = (&data[idx - 1], &data[idx]);
+            expected += ((curr.time - next.time) 
+                * (prev.position() - curr.position())
+                - (curr.time - prev.time) 
+                * (next.position() - curr.position())).abs() / 2.0;
+            curr = Rk4.step(&rhs, &curr, step).0;
+            if curr.time >= next.time {
+                idx += 1;
+            }
+        }
+
+        let fitness = expr.fitness(&data, step, &Rk4, &Area, Sampling::Steps);
+        assert!(expected > 0.0);
+        assert_close(fitness, expected);
+    }
+
+    #[test]
+    fn area_weights_by_span_and_step() {
+        let mut samples = samples(&[(2.0, 0.0), (-1.0, 0.0)]);
+        samples[0].span = 0.5;
+        samples[1].weight = 3.0;
+        assert_close(Area.fitness(&samples), 0.5 * 2.0 / 2.0 + 3.0 / 2.0);
+    }
+
+    #[test]
+    fn mean_metrics() {
+        let samples = samples(&[(1.0, 2.0), (-2.0, 4.0), (3.0, -1.0)]);
+        assert_close(Mse.fitness(&samples), 14.0 / 3.0);
+        assert_close(Rmse.fitness(&samples), (14.0_f64 / 3.0).sqrt());
+        assert_close(Mae.fitness(&samples), 2.0);
+        assert_close(MaxError.fitness(&samples), 3.0);
+        assert_close(Relative.fitness(&samples), (0.5 + 0.5 + 3.0) / 3.0);
+    }
+
+    #[test]
+    fn mean_metrics_are_weighted() {
+        let mut samples = samples(&[(1.0, 1.0), (3.0, 1.0)]);
+        samples[1].weight = 3.0;
+        assert_close(Mae.fitness(&samples), (1.0 + 3.0 * 3.0) / 4.0);
+        assert_close(Mse.fitness(&samples), (1.0 + 3.0 * 9.0) / 4.0);
+        assert_eq!(Mse.fitness(&[]), 0.0);
+    }
+
+    #[test]
+    fn huber_switches_to_linear_past_delta() {
+        let huber = Huber::new(1.5);
+        // Quadratic up to and including delta.
+        assert_close(huber.fitness(&samples(&[(1.0, 0.0)])), 0.5);
+        assert_close(huber.fitness(&samples(&[(-1.5, 0.0)])), 1.125);
+        // Linear beyond, continuous at delta.
+        assert_close(huber.fitness(&samples(&[(1.5 + 1e-9, 0.0)])), 
+            1.125 + 1.5e-9);
+        assert_close(huber.fitness(&samples(&[(-4.0, 0.0)])), 
+            1.5 * (4.0 - 0.75));
+        assert_close(huber.fitness(&samples(&[(1.0, 0.0), (4.0, 0.0)])),
+            (0.5 + 4.875) / 2.0);
+    }
+
+    #[test]
+    fn relative_floors_zero_observations() {
+        let samples = samples(&[(1e-9, 0.0), (2.0, -4.0)]);
+        assert_close(Relative.fitness(&samples), (0.1 + 0.5) / 2.0);
+        assert!(Relative.fitness(&self::samples(&[(1.0, 0.0)])).is_finite());
+    }
+
+    #[test]
+    fn metrics_propagate_divergence() {
+        let samples = samples(&[(1.0, 1.0), (f64::NAN, 1.0), (2.0, 1.0)]);
+        let metrics: [&dyn FitnessMetric; 7] = [
+            &Area, &Mse, &Rmse, &Mae, &Huber::default(), &MaxError, &Relative,
+        ];
+        for metric in metrics.iter() {
+            assert!(metric.fitness(&samples).is_nan());
+        }
+    }
+
+    #[test]
+    fn aggregates() {
+        let fitnesses = [1.0, 4.0, 2.5];
+        assert_close(Aggregate::Sum.combine(&fitnesses), 7.5);
+        assert_close(Aggregate::Mean.combine(&fitnesses), 2.5);
+        assert_close(Aggregate::Worst.combine(&fitnesses), 4.0);
+        for aggregate in [Aggregate::Sum, Aggregate::Mean, Aggregate::Worst]
+            .iter() {
+            assert!(aggregate.combine(&[1.0, f64::NAN]).is_nan());
+        }
+    }
+}
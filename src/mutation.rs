@@ -0,0 +1,331 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Mutation of the expressions of offspring.
+//_____________________________________________________________________________
+
+// External imports.
+use rand::Rng;
+use rand_distr::Normal;
+
+// Internal imports.
+use crate::ode::Expr;
+use crate::operator::{Operator, OperatorMap, ToOperator};
+
+//_____________________________________________________________________________
+//                                                         Mutation Type & Impl
+
+// Probability of each kind of mutation being applied to an offspring's
+// expression. At most one is applied, none with the probability remaining;
+// probabilities summing to more than one are scaled to sum to one.
+#[derive(Copy, Clone, Debug)]
+pub struct Mutation {
+    // Swap an operator for a random one from the map of equal arity.
+    pub point: f64,
+    // Replace a random subtree with a freshly generated expression.
+    pub subtree: f64,
+    // Replace the expression with one of its random subtrees.
+    pub hoist: f64,
+    // Replace a random subtree with a time or state variable leaf.
+    pub shrink: f64,
+    // Perturb a random constant by Gaussian noise, see sigma.
+    pub constant: f64,
+    // Wrap a random subtree in a random unary operator from the map.
+    pub insert: f64,
+    // Remove a random unary operator, keeping its argument.
+    pub delete: f64,
+
+    // Standard deviation of constant perturbations relative to the magnitude
+    // of the constant, or absolute for constants smaller than one.
+    pub sigma: f64,
+}
+
+impl Mutation {
+    /* new
+    * Create the default mutation, which always shrinks, as expressions were
+    * mutated before the other kinds were added.
+    */
+    pub fn new() -> Mutation {
+        return Mutation {shrink: 1.0, ..Mutation::none()};
+    }
+
+    /* none
+    * Create a mutation applying nothing, to set the probability of only the
+    * kinds wanted.
+    */
+    pub fn none() -> Mutation {
+        return Mutation {
+            point: 0.0,
+            subtree: 0.0,
+            hoist: 0.0,
+            shrink: 0.0,
+            constant: 0.0,
+            insert: 0.0,
+            delete: 0.0,
+            sigma: 0.1,
+        };
+    }
+
+    /* apply
+    * Mutate an expression, choosing the kind of mutation at random.
+    * Input:
+    *     expr - The expression to mutate.
+    *     map - Operators new parts of the expression are drawn from.
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     The mutated expression, unchanged if no mutation was chosen or the
+    *     chosen kind does not apply (such as deleting from an expression
+    *     without unary operators).
+    */
+    pub fn apply<R>(&self, expr: &Expr, map: &OperatorMap, rng: &mut R)
+        -> Expr
+        where R: Rng + ?Sized {
+        let rates = [
+            self.point, self.subtree, self.hoist, self.shrink,
+            self.constant, self.insert, self.delete,
+        ];
+        let total: f64 = rates.iter().sum();
+        let mut draw = rng.gen_range(0.0, 1.0) * total.max(1.0);
+        let kind = rates.iter().position(|rate| {
+            draw -= rate;
+            draw < 0.0
+        });
+
+        let operators = expr.operators();
+        let mutated = match kind {
+            Some(0) => point(operators, map, rng),
+            Some(1) => {
+                let range = expr.sub_expr(rng);
+                let generated = Expr::generate(map, rng);
+                Some(expr.splice(range, generated.operators()))
+            },
+            Some(2) => Some(operators[expr.sub_expr(rng)].to_vec()),
+            Some(3) => {
+                let range = expr.sub_expr(rng);
+                let leaf = match rng.gen::<bool>() {
+                    true => Operator::Time,
+                    false => *map.rand_variable(rng),
+                };
+                Some(expr.splice(range, &[leaf]))
+            },
+            Some(4) => self.perturb(operators, rng),
+            Some(5) => insert(expr, map, rng),
+            Some(6) => delete(operators, rng),
+            _ => None,
+        };
+
+        // Each kind keeps the operators in valid prefix order.
+        return match mutated {
+            Some(operators) => Expr::from_operators(operators).unwrap(),
+            None => expr.clone(),
+        };
+    }
+
+    /* perturb
+    * Add Gaussian noise to a random constant, or None if there are none.
+    */
+    fn perturb<R>(&self, operators: &[Operator], rng: &mut R)
+        -> Option<Vec<Operator>>
+        where R: Rng + ?Sized {
+        let idx = choose(operators, rng, |operator|
+            matches!(operator, Operator::Constant(_)))?;
+        let value = match operators[idx] {
+            Operator::Constant(bits) => f64::from_bits(bits),
+            _ => unreachable!(),
+        };
+
+        let normal = Normal::new(0.0, self.sigma * value.abs().max(1.0))
+            .ok()?;
+        let mut operators = operators.to_vec();
+        operators[idx] = (value + rng.sample(normal)).to_operator();
+        return Some(operators);
+    }
+}
+
+impl Default for Mutation {
+    fn default() -> Mutation {
+        return Mutation::new();
+    }
+}
+
+//_____________________________________________________________________________
+//                                                             Mutation Helpers
+
+/* choose
+* Get the index of a random operator satisfying a predicate, or None if no
+* operator does.
+*/
+fn choose<R, P>(operators: &[Operator], rng: &mut R, predicate: P)
+    -> Option<usize>
+    where R: Rng + ?Sized, P: Fn(&Operator) -> bool {
+    let indices: Vec<usize> = (0..operators.len())
+        .filter(|idx| predicate(&operators[*idx]))
+        .collect();
+    if indices.is_empty() {
+        return None;
+    }
+    return Some(indices[rng.gen_range(0, indices.len())]);
+}
+
+/* point
+* Swap a random operator for one of equal arity from the map, or None if
+* there is no other.
+*/
+fn point<R>(operators: &[Operator], map: &OperatorMap, rng: &mut R)
+    -> Option<Vec<Operator>>
+    where R: Rng + ?Sized {
+    let idx = rng.gen_range(0, operators.len());
+    let replacement = map.rand_with_arity(operators[idx].arity(), rng)?;
+    let mut operators = operators.to_vec();
+    operators[idx] = *replacement;
+    return Some(operators);
+}
+
+/* insert
+* Wrap a random subtree in a random unary operator from the map, or None if
+* the map has none.
+*/
+fn insert<R>(expr: &Expr, map: &OperatorMap, rng: &mut R)
+    -> Option<Vec<Operator>>
+    where R: Rng + ?Sized {
+    let unary = *map.rand_with_arity(1, rng)?;
+    let range = expr.sub_expr(rng);
+    let mut operators = expr.operators().to_vec();
+    operators.insert(*range.start(), unary);
+    return Some(operators);
+}
+
+/* delete
+* Remove a random unary operator, so that its argument takes its place, or
+* None if there are none.
+*/
+fn delete<R>(operators: &[Operator], rng: &mut R) -> Option<Vec<Operator>>
+    where R: Rng + ?Sized {
+    let idx = choose(operators, rng, |operator| operator.arity() == 1)?;
+    let mut operators = operators.to_vec();
+    operators.remove(idx);
+    return Some(operators);
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::operator::{self, builtin_map};
+
+    // Mutation applying only the kind with the given index, in the order of
+    // Mutation::apply.
+    fn only(kind: usize) -> Mutation {
+        let mut mutation = Mutation::none();
+        let rate = match kind {
+            0 => &mut mutation.point,
+            1 => &mut mutation.subtree,
+            2 => &mut mutation.hoist,
+            3 => &mut mutation.shrink,
+            4 => &mut mutation.constant,
+            5 => &mut mutation.insert,
+            _ => &mut mutation.delete,
+        };
+        *rate = 1.0;
+        return mutation;
+    }
+
+    fn is_valid(expr: &Expr) -> bool {
+        return Expr::from_operators(expr.operators().to_vec()).is_ok();
+    }
+
+    #[test]
+    fn every_kind_keeps_expressions_valid() {
+        let mut map = builtin_map();
+        map.insert(2.5.to_operator(), "CONST");
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+
+        for kind in 0..7 {
+            let mutation = only(kind);
+            for _ in 0..200 {
+                let mut expr = Expr::generate(&map, &mut rng);
+                // Mutate repeatedly, so that mutants are mutated in turn.
+                for _ in 0..5 {
+                    let size = expr.size();
+                    expr = mutation.apply(&expr, &map, &mut rng);
+                    assert!(is_valid(&expr), "kind {}", kind);
+                    if kind == 2 {
+                        assert!(expr.size() <= size);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_mutation_leaves_expressions_unchanged() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let expr = Expr::parse("ADD SIN POS 2", &map).unwrap();
+        for _ in 0..100 {
+            let mutated = Mutation::none().apply(&expr, &map, &mut rng);
+            assert!(mutated.operators() == expr.operators());
+        }
+    }
+
+    #[test]
+    fn kinds_that_do_not_apply_leave_expressions_unchanged() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+
+        // Deleting without unary operators and perturbing without constants.
+        let expr = Expr::parse("ADD POS TIME", &map).unwrap();
+        for kind in [4, 6].iter() {
+            for _ in 0..100 {
+                let mutated = only(*kind).apply(&expr, &map, &mut rng);
+                assert!(mutated.operators() == expr.operators());
+            }
+        }
+
+        // Inserting without unary operators in the map.
+        let mut binary = OperatorMap::new();
+        binary.insert(operator::builtin("ADD").unwrap(), "ADD");
+        for _ in 0..100 {
+            let mutated = only(5).apply(&expr, &binary, &mut rng);
+            assert!(mutated.operators() == expr.operators());
+        }
+    }
+
+    #[test]
+    fn point_needs_an_operator_of_equal_arity() {
+        // Only the leaves can be swapped when the map has no binary
+        // operators.
+        let expr = Expr::parse("ADD TIME TIME", &builtin_map()).unwrap();
+        let map = OperatorMap::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(6);
+        let mut changed = false;
+        for _ in 0..100 {
+            let mutated = only(0).apply(&expr, &map, &mut rng);
+            assert!(is_valid(&mutated));
+            assert!(mutated.operators()[0] == expr.operators()[0]);
+            changed |= mutated.operators() != expr.operators();
+        }
+        assert!(changed);
+    }
+
+    #[test]
+    fn perturb_scales_with_the_constant() {
+        let map = builtin_map();
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let expr = Expr::parse("MUL POS 1000", &map).unwrap();
+        let mutation = Mutation {sigma: 0.01, ..only(4)};
+        for _ in 0..100 {
+            let mutated = mutation.apply(&expr, &map, &mut rng);
+            let value = match mutated.operators()[2] {
+                Operator::Constant(bits) => f64::from_bits(bits),
+                _ => panic!("constant replaced"),
+            };
+            // Within six standard deviations of ten.
+            assert!((value - 1000.0).abs() < 60.0);
+            assert_ne!(value, 1000.0);
+        }
+    }
+}
@@ -4,32 +4,115 @@
 //_____________________________________________________________________________
 
 // External imports.
-use rand;
 use rand::Rng;
+use rand_distr::Normal;
 use std::ops::RangeInclusive; // Used for sub expressions.
 
 // Internal imports.
-use crate::operator::{Operator, ToOperator, OperatorMap};
+use crate::crossover::Crossover;
+use crate::error::{Error, Result};
+use crate::gradient::{self, Target};
+use crate::initialize::Initializer;
+use crate::integrate::{self, Integrator};
+use crate::metric::{FitnessMetric, Sample, Sampling};
+use crate::mutation::Mutation;
+use crate::operator::{Operator, OperatorMap};
+use crate::parse;
+use crate::tree::Node;
 
 // Seperating character for printing. Note that we only allow alphanumeric 
 // characters for operator tokens.
 const SEP_CHAR: char = ' ';
 
+// Seperating character of the expressions of a system.
+const SYSTEM_SEP_CHAR: char = ';';
+
+// Number of adaptive steps, or fixed steps beyond those needed to cover the
+// data, after which a simulation is abandoned as diverged, so that stiff 
+// candidates or unusable steps cannot stall evolution, see step_budget.
+const MAX_STEPS: usize = 10_000;
+
+// Description of an operator applied with too few arguments.
+const MISSING_OPERAND: &str = "operator is missing an operand";
+
 //_____________________________________________________________________________
 //                                                                   State Type
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct State {
-    time: f64,
-    position: f64,
+    pub time: f64,
+    // Value of each state variable, the first of which is the position.
+    pub values: Vec<f64>,
+    // Velocity of each state variable when simulating a second-order system,
+    // otherwise empty.
+    pub velocities: Vec<f64>,
 }
 
 impl State {
+    /* new
+    * Create a one-dimensional state.
+    */
     pub fn new(time: f64, position: f64) -> State {
-        return State {time, position};
+        return State {time, values: vec![position], velocities: Vec::new()};
+    }
+
+    /* from_values
+    * Create a state of any dimension.
+    */
+    pub fn from_values(time: f64, values: Vec<f64>) -> State {
+        return State {time, values, velocities: Vec::new()};
+    }
+
+    /* with_velocities
+    * Create a state of a second-order system, given the velocity of each 
+    * state variable.
+    */
+    pub fn with_velocities(time: f64, values: Vec<f64>, velocities: Vec<f64>)
+        -> State {
+        return State {time, values, velocities};
+    }
+
+    /* position
+    * Get the value of the first state variable.
+    */
+    pub fn position(&self) -> f64 {
+        return self.values[0];
+    }
+
+    /* dimension
+    * Get the number of state variables.
+    */
+    pub fn dimension(&self) -> usize {
+        return self.values.len();
+    }
+
+    /* variables
+    * Iterate over the variables that are integrated, the state variables 
+    * followed by their velocities.
+    */
+    pub(crate) fn variables(&self) -> impl Iterator<Item = &f64> {
+        return self.values.iter().chain(self.velocities.iter());
+    }
+
+    /* variables_mut
+    * Mutably iterate over the variables that are integrated.
+    */
+    pub(crate) fn variables_mut(&mut self) -> impl Iterator<Item = &mut f64> {
+        return self.values.iter_mut().chain(self.velocities.iter_mut());
     }
 }
 
+//_____________________________________________________________________________
+//                                                                   Order Type
+
+// Order of the derivative an ODE gives. Second-order systems x'' = f(x, x', t)
+// are integrated as the first-order system of positions and velocities.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Order {
+    First,
+    Second,
+}
+
 //_____________________________________________________________________________
 //                                                             Expr Type & Impl
 
@@ -40,63 +123,91 @@ pub struct Expr {
 
 impl<'a> Expr {
     /* generate
-    * Generate a random expression using operators from our given map.
+    * Generate a random expression using operators from our given map, by 
+    * the default initializer (see Initializer).
+    * Input:
+    *     map - Operators to draw from.
+    *     rng - Random number generator to draw from.
     * Output:
     *     Randomly generated Expr struct.
     */
-    pub fn generate(map: &'a OperatorMap) -> Expr {
-        // Initialize data scructures to form Expr struct.
-        let mut operators = Vec::new();
-
-        // Initialize random number generator.
-        let mut rng = rand::thread_rng();
+    pub fn generate<R>(map: &'a OperatorMap, rng: &mut R) -> Expr
+        where R: Rng + ?Sized {
+        return Initializer::new().generate(map, rng);
+    }
 
-        // Require expression to be not empty.
+    /* from_operators
+    * Create an expression from operators in prefix order.
+    * Output:
+    *     The expression, or a MalformedExpr error if the operators do not 
+    *     form exactly one expression.
+    */
+    pub fn from_operators(operators: Vec<Operator>) -> Result<Expr> {
         let mut args_needed: i32 = 1;
-        loop {
-            // TODO: rework randomness to be more easily changed.
-            // Choose a random class of Operator. Note that we put more weight
-            // to choosing the time and position variables even though they 
-            // can be found in the map.
-            let rand: u32 = rng.gen_range(0, 5);
-            match rand {
-                0 => { // Time
-                    operators.push(Operator::Time);
-                    args_needed -= 1;
-                },
-                1 => { // Position
-                    operators.push(Operator::Position);
-                    args_needed -= 1;
-                },
-                2 => { // Anonymous Constant
-                    let c: f64 = rng.gen_range(-10.0, 10.0);
-                    operators.push(c.to_operator());
-                    args_needed -= 1;
-                },
-                _ => { // Operator
-                    let operator = map.rand_operator();
-                    operators.push(*operator);
-
-                    // Note that here, there is an argument already required.
-                    args_needed += match operator {
-                        Operator::Unary(_) => 0,
-                        Operator::Binary(_) => 1,
-                        _ => -1,
-                    }
-                },
-            }
-            // An expression is valid if there are no more arguments needed by 
-            // any of the operators.
+        for operator in operators.iter() {
             if args_needed == 0 {
-                break;
+                return Err(Error::MalformedExpr(
+                    "more than one operand remaining in the stack"));
             }
+            args_needed += operator.arity() as i32 - 1;
         }
-        return Expr {operators};
+
+        if args_needed != 0 {
+            return Err(Error::MalformedExpr(MISSING_OPERAND));
+        }
+        return Ok(Expr {operators});
+    }
+
+    /* parse
+    * Parse an expression from text, either in the prefix notation produced by
+    * to_string or in ordinary infix notation (see the parse module).
+    * Input:
+    *     text - Text of the expression.
+    *     map - Operators the tokens of the text refer to.
+    * Output:
+    *     The parsed expression.
+    */
+    pub fn parse(text: &str, map: &'a OperatorMap) -> Result<Expr> {
+        return parse::parse(text, map);
+    }
+
+    /* operators
+    * Get the operators of the expression in prefix order.
+    */
+    pub fn operators(&self) -> &[Operator] {
+        return &self.operators;
+    }
+
+    /* size
+    * Get the number of operators (nodes) in the expression.
+    */
+    pub fn size(&self) -> usize {
+        return self.operators.len();
+    }
+
+    /* depth
+    * Get the depth of the expression's tree, zero for a single leaf.
+    */
+    pub fn depth(&self) -> usize {
+        return Node::from_expr(self).depth();
     }
 
     /* to_string
+    * Describe the expression as space separated tokens in prefix order.
+    * Panics if the expression contains an operator not in the map, see 
+    * try_to_string.
     */
     pub fn to_string(&self, map: &'a OperatorMap) -> String {
+        return self.try_to_string(map).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /* try_to_string
+    * Describe the expression as space separated tokens in prefix order.
+    * Output:
+    *     The description, or an UnmappedOperator error if an operator other 
+    *     than an anonymous constant is not in the map.
+    */
+    pub fn try_to_string(&self, map: &'a OperatorMap) -> Result<String> {
         let mut description = String::from("");
         for operator in self.operators.iter() {
             let token = map.get(operator);
@@ -107,44 +218,66 @@ impl<'a> Expr {
                     match operator {
                         Operator::Constant(c) => 
                             description.push_str(&f64::from_bits(*c).to_string()),
-                        _ => panic!("Encountered operator not in map."),
+                        _ => return Err(Error::UnmappedOperator),
                     }
                 },
             }
             description.push(SEP_CHAR);
         }
-        return description;
+        return Ok(description);
     }
 
     //_______________________________________________________________
     //                                     Evaluation and ODE Helpers
 
     /* eval
-    * Evaluate the ODE's expression at a given time and position.
+    * Evaluate the ODE's expression at a given state.
     * Input:
-    *     time - The value of the time variable.
-    *     position - The value of the position variable.
+    *     state - The values of the time and state variables.
     * Output:
-        The value of the evaluated expression.
+    *     The value of the evaluated expression.
+    * Panics if the expression is malformed, see try_eval.
     */
-    pub fn eval(&self, state: State) -> f64 {
+    pub fn eval(&self, state: &State) -> f64 {
+        return self.try_eval(state).unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /* try_eval
+    * Evaluate the ODE's expression at a given state.
+    * Input:
+    *     state - The values of the time and state variables.
+    * Output:
+    *     The value of the evaluated expression, or a MalformedExpr error if 
+    *     the operators do not form exactly one expression, or an 
+    *     UnknownVariable or UnknownVelocity error if the state has too few 
+    *     variables.
+    */
+    pub fn try_eval(&self, state: &State) -> Result<f64> {
         let mut stack: Vec<f64> = Vec::new();
 
         for operator in self.operators.iter().rev() {
             match operator {
                 Operator::Time => stack.push(state.time),
-                Operator::Position => stack.push(state.position),
+                Operator::Position => stack.push(
+                    *state.values.first().ok_or(Error::UnknownVariable(0))?),
+                Operator::Variable(idx) => stack.push(
+                    *state.values.get(*idx)
+                        .ok_or(Error::UnknownVariable(*idx))?),
+                Operator::Velocity(idx) => stack.push(
+                    *state.velocities.get(*idx)
+                        .ok_or(Error::UnknownVelocity(*idx))?),
                 Operator::Constant(c) => stack.push(f64::from_bits(*c)),
 
-                // TODO: We are assuming here that the expression is valid.
-                //       Need to account for case where it is not.
                 Operator::Unary(f) => {
-                    let arg = stack.pop().unwrap();
+                    let arg = stack.pop()
+                        .ok_or(Error::MalformedExpr(MISSING_OPERAND))?;
                     stack.push(f(arg));
                 }, 
                 Operator::Binary(f) => {
-                    let arg1 = stack.pop().unwrap();
-                    let arg2 = stack.pop().unwrap();
+                    let arg1 = stack.pop()
+                        .ok_or(Error::MalformedExpr(MISSING_OPERAND))?;
+                    let arg2 = stack.pop()
+                        .ok_or(Error::MalformedExpr(MISSING_OPERAND))?;
                     stack.push(f(arg1, arg2));
                 },
             }
@@ -153,94 +286,48 @@ impl<'a> Expr {
         // If expression is valid, there is exactly one value remaining in the 
         // stack representing the result.
         match stack.len() {
-            0 => panic!(
-                "Malformed expression, \
-                no operands remaining in the stack."),
-            1 => return stack.pop().unwrap(),
-            _ => panic!(
-                "Malformed expression, \
-                more than one operand remaining in the stack."),
+            0 => return Err(Error::MalformedExpr(
+                "no operands remaining in the stack")),
+            1 => return Ok(stack.pop().unwrap()),
+            _ => return Err(Error::MalformedExpr(
+                "more than one operand remaining in the stack")),
         }
     }
 
     /* fitness
-    * Compute the fitness of an individual against some given data. We asssume 
-    * here that an individual will only be tested against the same set of data
-    * and as such, we may reuse a fitness value that has been repviously 
-    * calculated. 
+    * Compute the fitness of the expression as the ODE x' = f(x, t) against 
+    * some given data, using the first state variable of the data. See 
+    * System::fitness.
     */
-    pub fn fitness(&self, states: &'a Vec<State>, step: f64) -> f64 {
-        let mut state_iter = states.iter();
-
-        // Initialize our data bounds.
-        let mut prev = state_iter.next();
-        let mut next = state_iter.next();
-
-        let mut curr_state = State{
-            time: prev.unwrap().time, 
-            position: prev.unwrap().position,
-        };
-
-        // Simulate the ODE over the time of the data given.
-        let mut fitness = 0.0;
-
-        while next != None {
-            // Compute the how well the ODE fits the data. Note that we 
-            // test against a linear interpolation between the previous 
-            // time and position data and the next time and position 
-            // data.
-            let prev_state = prev.unwrap();
-            let next_state = next.unwrap();
-
-            // Compute area by the shoelace method.
-            let area = (
-                (curr_state.time - next_state.time) *
-                (prev_state.position - curr_state.position) -
-                (curr_state.time - prev_state.time) *
-                (next_state.position - curr_state.position))
-                .abs() / 2.0;
-
-            fitness += area;
-            
-            curr_state = self.next(curr_state, step);
-
-            // Increment our data bounds.
-            if curr_state.time >= next_state.time {
-                prev = next;
-                next = state_iter.next();
-            }
-        }
-
-        return fitness;
+    pub fn fitness(
+        &self, 
+        states: &[State], 
+        step: f64, 
+        integrator: &dyn Integrator,
+        metric: &dyn FitnessMetric,
+        sampling: Sampling) -> f64 {
+        let rhs = |state: &State| vec![self.eval(state)];
+        let initial = states[0].clone();
+        return metric.fitness(
+            &samples(&rhs, initial, states, step, integrator, sampling));
     }
 
     /* simulate
+    * Simulate the expression as the ODE x' = f(x, t), see System::simulate.
+    * Only the first state variable changes over the trajectory.
     */
-    pub fn simulate(&self, states: Vec<State>, step: f64) {
-
-    }
-
-    /* next
-    * Simulate the next step of the ODE using the Runge-Kutta 45 method with 
-    * the given initial conditions and time step size.
-    */
-    fn next(&self, state: State, step: f64) -> State {
-
-        // Runge-Kutta 45 method for ODEs.
-        let rk45_increment = |dt: f64, dp: f64| 
-            self.eval(State::new(state.time + dt, state.position + dp));
-
-        let k1 = rk45_increment(0.0, 0.0);
-        let k2 = rk45_increment(step / 2.0, step * k1 / 2.0);
-        let k3 = rk45_increment(step / 2.0, step * k2 / 2.0);
-        let k4 = rk45_increment(step, step * k3);
-
-        let new_state = State::new(
-            state.time + step,
-            state.position + (step / 6.0) * 
-                (k1 + (2.0 * k2) + (2.0 * k3) + k4));
-        
-        return new_state;
+    pub fn simulate(
+        &self, 
+        states: &[State], 
+        step: f64, 
+        integrator: &dyn Integrator,
+        dense: bool) -> Vec<State> {
+        let rhs = |state: &State| vec![self.eval(state)];
+        return match states.first() {
+            Some(initial) => simulate(
+                &rhs, initial.clone(), states, step, integrator, dense),
+            None => Vec::new(),
+        };
     }
 
     //_______________________________________________________________
@@ -251,9 +338,16 @@ impl<'a> Expr {
     * Output:
     *     A Expr struct correpsonding to a subexpression.
     */
-    fn sub_expr(&self) -> RangeInclusive<usize> {
-        let start = rand::random::<usize>() % self.operators.len();
+    pub(crate) fn sub_expr<R>(&self, rng: &mut R) -> RangeInclusive<usize>
+        where R: Rng + ?Sized {
+        let start = rng.gen_range(0, self.operators.len());
+        return self.subtree(start);
+    }
 
+    /* subtree
+    * Get the subexpression starting at the given operator.
+    */
+    pub(crate) fn subtree(&self, start: usize) -> RangeInclusive<usize> {
         // Find the end point of the subexpression.
         let mut end = start;
         let mut args_needed: i32 = 1;
@@ -277,39 +371,869 @@ impl<'a> Expr {
         return start..=end;
     }
 
+    /* splice
+    * Get the operators of self with the given subexpression replaced.
+    */
+    pub(crate) fn splice(
+        &self, 
+        range: RangeInclusive<usize>, 
+        replacement: &[Operator]) -> Vec<Operator> {
+        let mut operators = self.operators[..*range.start()].to_vec();
+        operators.extend_from_slice(replacement);
+        operators.extend_from_slice(&self.operators[(*range.end() + 1)..]);
+        return operators;
+    }
+
     /* crossover
-    * Replace a random subexression in self with the given sub expression.
+    * Replace a subexpression in self with one of the given expression.
+    * Input:
+    *     other - The expression subexpressions are taken from.
+    *     crossover - How the subexpressions are chosen.
+    *     rng - Random number generator to draw from.
     * Output:
     *     A Expr struct correpsonding to the crossover.
     */
-    pub fn crossover(&self, other: &'a Expr) -> Expr {
-        let sub_self = self.sub_expr();
-        let sub_other = other.sub_expr();
+    pub fn crossover<R>(
+        &self, 
+        other: &'a Expr, 
+        crossover: &Crossover, 
+        rng: &mut R) -> Expr
+        where R: Rng + ?Sized {
+        return crossover.apply(self, other, rng);
+    }
+
+    /* mutate
+    * Mutate self by one of the kinds of mutation, chosen at random.
+    * Input:
+    *     map - Operators new parts of the expression are drawn from.
+    *     mutation - Probability of each kind of mutation.
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     A Expr struct correpsonding to the mutation.
+    */
+    pub fn mutate<R>(
+        &self, 
+        map: &'a OperatorMap, 
+        mutation: &Mutation, 
+        rng: &mut R) -> Expr
+        where R: Rng + ?Sized {
+        return mutation.apply(self, map, rng);
+    }
+}
+
+//_____________________________________________________________________________
+//                                                           System Type & Impl
+
+// A system of coupled ODEs, with one expression giving the derivative of 
+// each state variable, or for second-order systems, its second derivative.
+#[derive(Clone)]
+pub struct System {
+    exprs: Vec<Expr>,
+    order: Order,
+    // Velocities second-order systems start from in each trajectory fit, 
+    // estimated from the data when not given.
+    velocities: Option<Vec<Vec<f64>>>,
+    // Values of the state variables simulations start from in each 
+    // trajectory fit, the first data point when not given.
+    initial_values: Option<Vec<Vec<f64>>>,
+}
+
+impl From<Expr> for System {
+    fn from(expr: Expr) -> System {
+        return System::new(vec![expr]);
+    }
+}
+
+impl<'a> System {
+    /* new
+    * Create a first-order system from the expressions of each state variable.
+    */
+    pub fn new(exprs: Vec<Expr>) -> System {
+        return System {
+            exprs, 
+            order: Order::First, 
+            velocities: None, 
+            initial_values: None,
+        };
+    }
+
+    /* generate
+    * Generate a random first-order system using operators from our given map.
+    * Input:
+    *     map - Operators to draw from.
+    *     dimension - Number of state variables.
+    *     rng - Random number generator to draw from.
+    */
+    pub fn generate<R>(map: &'a OperatorMap, dimension: usize, rng: &mut R) 
+        -> System
+        where R: Rng + ?Sized {
+        let exprs = (0..dimension).map(|_| Expr::generate(map, rng)).collect();
+        return System::new(exprs);
+    }
+
+    /* parse
+    * Parse a first-order system from text, with the expression of each state
+    * variable separated by semicolons (see Expr::parse).
+    */
+    pub fn parse(text: &str, map: &'a OperatorMap) -> Result<System> {
+        let exprs = text.split(SYSTEM_SEP_CHAR)
+            .map(|component| Expr::parse(component, map))
+            .collect::<Result<Vec<Expr>>>()?;
+        return Ok(System::new(exprs));
+    }
+
+    /* exprs
+    * Get the expression of each state variable.
+    */
+    pub fn exprs(&self) -> &[Expr] {
+        return &self.exprs;
+    }
+
+    /* exprs_mut
+    * Get the expression of each state variable for modification in place.
+    */
+    pub fn exprs_mut(&mut self) -> &mut [Expr] {
+        return &mut self.exprs;
+    }
+
+    /* dimension
+    * Get the number of state variables.
+    */
+    pub fn dimension(&self) -> usize {
+        return self.exprs.len();
+    }
+
+    /* size
+    * Get the total number of operators in the expressions.
+    */
+    pub fn size(&self) -> usize {
+        return self.exprs.iter().map(Expr::size).sum();
+    }
+
+    /* order
+    * Get the order of the derivative the expressions give.
+    */
+    pub fn order(&self) -> Order {
+        return self.order;
+    }
+
+    /* set_order
+    * Set the order of the derivative the expressions give.
+    */
+    pub fn set_order(&mut self, order: Order) {
+        self.order = order;
+    }
+
+    /* velocities
+    * Get the initial velocities of a second-order system in each trajectory,
+    * if they are part of the system rather than estimated from the data.
+    */
+    pub fn velocities(&self) -> Option<&[Vec<f64>]> {
+        return self.velocities.as_deref();
+    }
+
+    /* set_velocities
+    * Set the initial velocities of a second-order system in each trajectory,
+    * or None to estimate them from the data (see estimate_velocities). 
+    * Trajectories without velocities given are also estimated.
+    */
+    pub fn set_velocities(&mut self, velocities: Option<Vec<Vec<f64>>>) {
+        self.velocities = velocities;
+    }
+
+    /* initial_values
+    * Get the values of the state variables simulations start from in each 
+    * trajectory, if they are part of the system rather than the first data 
+    * point.
+    */
+    pub fn initial_values(&self) -> Option<&[Vec<f64>]> {
+        return self.initial_values.as_deref();
+    }
+
+    /* set_initial_values
+    * Set the values of the state variables simulations start from in each 
+    * trajectory, or None to start from the first data point. Trajectories 
+    * without values given also start from their first data point.
+    */
+    pub fn set_initial_values(&mut self, 
+        initial_values: Option<Vec<Vec<f64>>>) {
+        self.initial_values = initial_values;
+    }
+
+    /* to_string
+    * Describe the system as the prefix tokens of each expression separated by
+    * semicolons.
+    */
+    pub fn to_string(&self, map: &'a OperatorMap) -> String {
+        return self.exprs.iter()
+            .map(|expr| expr.to_string(map))
+            .collect::<Vec<String>>()
+            .join(&format!("{} ", SYSTEM_SEP_CHAR));
+    }
+
+    /* eval
+    * Evaluate the expression of each state variable at the given state.
+    */
+    pub fn eval(&self, state: &State) -> Vec<f64> {
+        return self.exprs.iter().map(|expr| expr.eval(state)).collect();
+    }
+
+    /* derivative
+    * Evaluate the derivative of the integrated variables at the given state.
+    * For second-order systems these are the velocities followed by the 
+    * accelerations given by the expressions.
+    */
+    fn derivative(&self, state: &State) -> Vec<f64> {
+        return match self.order {
+            Order::First => self.eval(state),
+            Order::Second => {
+                let mut derivative = state.velocities.clone();
+                derivative.extend(self.eval(state));
+                derivative
+            },
+        };
+    }
+
+    /* initial_state
+    * Get the state simulations of the system begin from, at the time of the
+    * first of the given states, along with the initial velocities for 
+    * second-order systems.
+    * Input:
+    *     states - Data of the trajectory.
+    *     trajectory - Index of the trajectory, selecting the initial values
+    *         and velocities that are part of the system.
+    */
+    pub fn initial_state(&self, states: &[State], trajectory: usize) 
+        -> Option<State> {
+        let mut state = states.first()?.clone();
+        let initial_values = self.initial_values.as_ref()
+            .and_then(|initial_values| initial_values.get(trajectory));
+        if let Some(initial_values) = initial_values {
+            for (value, initial) in 
+                state.values.iter_mut().zip(initial_values.iter()) {
+                *value = *initial;
+            }
+        }
+        if self.order == Order::Second {
+            let velocities = self.velocities.as_ref()
+                .and_then(|velocities| velocities.get(trajectory));
+            let mut velocities = match velocities {
+                Some(velocities) => velocities.clone(),
+                None => estimate_velocities(states),
+            };
+            velocities.resize(state.dimension(), 0.0);
+            state.velocities = velocities;
+        }
+        return Some(state);
+    }
+
+    /* fitness
+    * Compute the fitness of the system against some given data over all 
+    * state variables.
+    * Input:
+    *     states - Data to fit.
+    *     trajectory - Index of the trajectory the data is, see initial_state.
+    *     step - The time step size, or the initial step size of adaptive 
+    *         integrators.
+    *     integrator - Integration scheme to simulate with.
+    *     metric - Scores the simulated trajectory against the data.
+    *     sampling - Where the trajectory is compared against the data.
+    */
+    pub fn fitness(
+        &self, 
+        states: &[State], 
+        trajectory: usize,
+        step: f64, 
+        integrator: &dyn Integrator,
+        metric: &dyn FitnessMetric,
+        sampling: Sampling) -> f64 {
+        return metric.fitness(
+            &self.samples(states, trajectory, step, integrator, sampling));
+    }
 
-        // Form operator vector of new expression.
-        let mut operators = self.operators[..*sub_self.start()].to_vec();
-        operators.extend_from_slice(&other.operators[sub_other]);
-        operators.extend_from_slice(&self.operators[(*sub_self.end() + 1)..]);
+    /* samples
+    * Simulate the system against some given data and compare the trajectory
+    * with it, see fitness. Metrics score the samples returned.
+    */
+    pub fn samples(
+        &self, 
+        states: &[State], 
+        trajectory: usize,
+        step: f64, 
+        integrator: &dyn Integrator,
+        sampling: Sampling) -> Vec<Sample> {
+        let rhs = |state: &State| self.derivative(state);
+        let initial = self.initial_state(states, trajectory).unwrap();
+        return samples(&rhs, initial, states, step, integrator, sampling);
+    }
 
-        return Expr {operators};
+    /* gradient_fitness
+    * Compute the fitness of the system against derivatives estimated from 
+    * some data, without simulating it. Much cheaper than fitness but 
+    * sensitive to noise in the data, which the estimate should smooth.
+    * Input:
+    *     targets - Derivatives estimated from the data, see gradient::targets.
+    *     metric - Scores the expressions against the derivatives.
+    */
+    pub fn gradient_fitness(
+        &self, 
+        targets: &[Target], 
+        metric: &dyn FitnessMetric) -> f64 {
+        return metric.fitness(&self.gradient_samples(targets));
+    }
+
+    /* gradient_samples
+    * Compare the expressions of the system against derivatives estimated 
+    * from some data, see gradient_fitness.
+    */
+    pub fn gradient_samples(&self, targets: &[Target]) -> Vec<Sample> {
+        let rhs = |state: &State| self.eval(state);
+        return gradient::samples(&rhs, targets);
+    }
+
+    /* simulate
+    * Simulate the system starting from the first of the given states and 
+    * sample the trajectory at the times of the remaining states. Steps are 
+    * shortened where needed so that the trajectory lands exactly on each data
+    * time.
+    * Input:
+    *     states - Data whose times the trajectory is sampled at.
+    *     trajectory - Index of the trajectory the data is, see initial_state.
+    *     step - The maximum time step size, or the initial step size of 
+    *         adaptive integrators.
+    *     integrator - Integration scheme to simulate with.
+    *     dense - Whether to also record every internal step.
+    * Output:
+    *     Vector of simulated states, beginning with the first given state. 
+    *     States of second-order systems include their velocities.
+    */
+    pub fn simulate(
+        &self, 
+        states: &[State], 
+        trajectory: usize,
+        step: f64, 
+        integrator: &dyn Integrator,
+        dense: bool) -> Vec<State> {
+        let rhs = |state: &State| self.derivative(state);
+        return match self.initial_state(states, trajectory) {
+            Some(initial) => 
+                simulate(&rhs, initial, states, step, integrator, dense),
+            None => Vec::new(),
+        };
+    }
+
+    /* crossover
+    * Crossover the expressions of one random state variable, keeping the 
+    * others and the initial velocities of self.
+    */
+    pub fn crossover<R>(
+        &self, 
+        other: &'a System, 
+        crossover: &Crossover, 
+        rng: &mut R) -> System
+        where R: Rng + ?Sized {
+        let idx = rng.gen_range(0, self.exprs.len());
+        let mut system = self.clone();
+        system.exprs[idx] = 
+            self.exprs[idx].crossover(&other.exprs[idx], crossover, rng);
+        return system;
     }
 
     /* mutate
+    * Mutate the expression of one random state variable, and the initial 
+    * velocity of one random state variable in one random trajectory if they
+    * are part of the system.
     */
-    pub fn mutate(&self) -> Expr {
-        let rand = rand::random::<bool>();
-        let var = match rand {
-            true => Operator::Time,
-            false => Operator::Position,
+    pub fn mutate<R>(
+        &self, 
+        map: &'a OperatorMap, 
+        mutation: &Mutation, 
+        rng: &mut R) -> System
+        where R: Rng + ?Sized {
+        let idx = rng.gen_range(0, self.exprs.len());
+        let mut system = self.clone();
+        system.exprs[idx] = self.exprs[idx].mutate(map, mutation, rng);
+
+        if let Some(trajectories) = system.velocities.as_mut() {
+            if !trajectories.is_empty() {
+                let trajectory = rng.gen_range(0, trajectories.len());
+                let velocities = &mut trajectories[trajectory];
+                if !velocities.is_empty() {
+                    let idx = rng.gen_range(0, velocities.len());
+                    velocities[idx] = perturb(velocities[idx], rng);
+                }
+            }
+        }
+        return system;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                  ODE Helpers
+
+/* estimate_velocities
+* Estimate the velocity of each state variable at the first data point by a
+* one-sided finite difference, second order accurate when there are at least
+* three data points.
+* Input:
+*     states - Data ordered by time.
+* Output:
+*     The velocity of each state variable, zero if there is too little data.
+*/
+pub fn estimate_velocities(states: &[State]) -> Vec<f64> {
+    let dimension = states.first().map_or(0, State::dimension);
+
+    return match states {
+        [s0, s1, s2, ..] => {
+            // Weights of the three point difference for uneven spacing.
+            let h1 = s1.time - s0.time;
+            let h2 = s2.time - s1.time;
+            let w0 = -(2.0 * h1 + h2) / (h1 * (h1 + h2));
+            let w1 = (h1 + h2) / (h1 * h2);
+            let w2 = -h1 / (h2 * (h1 + h2));
+            (0..dimension)
+                .map(|idx| w0 * s0.values[idx] + w1 * s1.values[idx] 
+                    + w2 * s2.values[idx])
+                .collect()
+        },
+        [s0, s1] => (0..dimension)
+            .map(|idx| (s1.values[idx] - s0.values[idx]) / (s1.time - s0.time))
+            .collect(),
+        _ => vec![0.0; dimension],
+    };
+}
+
+/* estimate_initial_values
+* Estimate the value of each state variable at the first data point from a 
+* least squares quadratic through the first few data points, which smooths 
+* out noise in the first measurement.
+* Input:
+*     states - Data ordered by time.
+*     points - Number of data points to fit, fewer fit a lower degree.
+* Output:
+*     The smoothed value of each state variable at the first data time.
+*/
+pub fn estimate_initial_values(states: &[State], points: usize) -> Vec<f64> {
+    let states = &states[..points.min(states.len())];
+    let first = match states.first() {
+        Some(first) => first,
+        None => return Vec::new(),
+    };
+    let degree = states.len().saturating_sub(1).min(2);
+
+    // Normal equations of the fit in time relative to the first data point,
+    // so that the constant coefficient is the value there.
+    let powers = |state: &State| (0..=degree)
+        .map(|power| (state.time - first.time).powi(power as i32))
+        .collect::<Vec<f64>>();
+    let mut matrix = vec![vec![0.0; degree + 1]; degree + 1];
+    for state in states.iter() {
+        let powers = powers(state);
+        for (row, a) in matrix.iter_mut().zip(powers.iter()) {
+            for (entry, b) in row.iter_mut().zip(powers.iter()) {
+                *entry += a * b;
+            }
+        }
+    }
+
+    return (0..first.dimension())
+        .map(|idx| {
+            let mut rhs = vec![0.0; degree + 1];
+            for state in states.iter() {
+                for (entry, power) in rhs.iter_mut().zip(powers(state)) {
+                    *entry += power * state.values[idx];
+                }
+            }
+            match integrate::solve(matrix.clone(), rhs) {
+                Some(coefficients) => coefficients[0],
+                None => first.values[idx],
+            }
+        })
+        .collect();
+}
+
+/* perturb
+* Perturb an evolved value by Gaussian noise relative to its magnitude.
+*/
+pub(crate) fn perturb<R>(value: f64, rng: &mut R) -> f64
+    where R: Rng + ?Sized {
+    let normal = Normal::new(0.0, 0.1 * value.abs().max(1.0)).unwrap();
+    return value + rng.sample(normal);
+}
+
+/* samples
+* Simulate the ODE with the given right-hand side from the initial state and
+* compare the trajectory against the data, see Sampling.
+*/
+fn samples<F>(
+    rhs: &F, 
+    initial: State, 
+    states: &[State], 
+    step: f64, 
+    integrator: &dyn Integrator,
+    sampling: Sampling) -> Vec<Sample>
+    where F: Fn(&State) -> Vec<f64> {
+    return match sampling {
+        Sampling::Steps => step_samples(rhs, initial, states, step, integrator),
+        Sampling::DataTimes => {
+            let dimension = rhs(&initial).len().min(initial.dimension());
+            let trajectory = 
+                simulate(rhs, initial, states, step, integrator, false);
+            data_samples(&trajectory, states, dimension)
+        },
+    };
+}
+
+/* data_samples
+* Compare a trajectory sampled at the data times against the data. Each 
+* sample spans the data points on either side of it.
+*/
+fn data_samples(trajectory: &[State], states: &[State], dimension: usize) 
+    -> Vec<Sample> {
+    let last = states.len() - 1;
+    return trajectory.iter().zip(states.iter()).enumerate()
+        .map(|(idx, (simulated, observed))| {
+            let before = &states[idx.saturating_sub(1)];
+            let after = &states[(idx + 1).min(last)];
+            let observed = observed.values[..dimension].to_vec();
+            Sample {
+                time: simulated.time,
+                residuals: (0..dimension)
+                    .map(|i| simulated.values[i] - observed[i])
+                    .collect(),
+                observed,
+                weight: 1.0,
+                span: after.time - before.time,
+            }
+        })
+        .collect();
+}
+
+/* step_samples
+* Compare each point of the trajectory against the linear interpolation of 
+* the neighbouring data points. With adaptive integrators each sample is 
+* weighted by the length of the step taken from it relative to the given 
+* step, so that the fitness does not depend on the number of steps taken.
+*/
+fn step_samples<F>(
+    rhs: &F, 
+    initial: State, 
+    states: &[State], 
+    step: f64, 
+    integrator: &dyn Integrator) -> Vec<Sample>
+    where F: Fn(&State) -> Vec<f64> {
+    let mut state_iter = states.iter();
+
+    // Initialize our data bounds.
+    let mut prev = state_iter.next();
+    let mut next = state_iter.next();
+
+    let mut curr_state = initial;
+    let dimension = rhs(&curr_state).len().min(curr_state.dimension());
+    let mut suggested = step;
+    let mut steps = 0;
+    let budget = step_budget(states, step, integrator);
+
+    // Simulate the ODE over the time of the data given.
+    let mut samples = Vec::new();
+
+    while next.is_some() {
+        let prev_state = prev.unwrap();
+        let next_state = next.unwrap();
+
+        // Adaptive steps may be of any length, but never pass the next data
+        // point so that the data bounds stay correct.
+        let adaptive = integrator.is_adaptive();
+        let limit = match adaptive {
+            true => next_state.time - curr_state.time,
+            false => step,
         };
-        let expr = Expr {operators: vec![var]};
-        return self.crossover(&expr);
+        let (mut new_state, taken) = 
+            advance(rhs, integrator, &curr_state, limit, &mut suggested);
+        if adaptive && taken >= limit {
+            new_state.time = next_state.time;
+        }
+        // Once abandoned, jump straight to each remaining data point.
+        steps += 1;
+        if steps > budget {
+            new_state.variables_mut().for_each(|value| *value = f64::NAN);
+            new_state.time = next_state.time;
+        }
+
+        // Compare against a linear interpolation between the previous and 
+        // next data points.
+        let span = next_state.time - prev_state.time;
+        let fraction = match span > 0.0 {
+            true => (curr_state.time - prev_state.time) / span,
+            false => 1.0,
+        };
+        let observed: Vec<f64> = (0..dimension)
+            .map(|idx| prev_state.values[idx] 
+                + fraction * (next_state.values[idx] - prev_state.values[idx]))
+            .collect();
+        let residuals = (0..dimension)
+            .map(|idx| curr_state.values[idx] - observed[idx])
+            .collect();
+        samples.push(Sample {
+            time: curr_state.time,
+            residuals,
+            observed,
+            weight: taken / step,
+            span,
+        });
+        
+        curr_state = new_state;
+
+        // Increment our data bounds.
+        if curr_state.time >= next_state.time {
+            prev = next;
+            next = state_iter.next();
+        }
+    }
+
+    return samples;
+}
+
+/* simulate
+* Simulate the ODE with the given right-hand side, see System::simulate.
+*/
+fn simulate<F>(
+    rhs: &F, 
+    initial: State, 
+    states: &[State], 
+    step: f64, 
+    integrator: &dyn Integrator,
+    dense: bool) -> Vec<State>
+    where F: Fn(&State) -> Vec<f64> {
+    // Integration begins at the first data point.
+    let mut curr_state = initial;
+    let mut trajectory = vec![curr_state.clone()];
+    let mut suggested = step;
+    let mut steps = 0;
+    let budget = step_budget(states, step, integrator);
+
+    for target in states.iter().skip(1) {
+        loop {
+            let remaining = target.time - curr_state.time;
+            let limit = match integrator.is_adaptive() {
+                true => remaining,
+                false => remaining.min(step),
+            };
+            let (new_state, taken) = 
+                advance(rhs, integrator, &curr_state, limit, &mut suggested);
+            curr_state = new_state;
+
+            // Once abandoned, jump straight to each remaining data point.
+            steps += 1;
+            let abandoned = steps > budget;
+            if abandoned {
+                curr_state.variables_mut().for_each(|value| *value = f64::NAN);
+            }
+
+            // Stop once we land on the data time. Note that we set the time
+            // directly to avoid drift from accumulated rounding error.
+            if abandoned || taken >= remaining {
+                curr_state.time = target.time;
+                break;
+            }
+
+            if dense {
+                trajectory.push(curr_state.clone());
+            }
+        }
+        trajectory.push(curr_state.clone());
     }
+
+    return trajectory;
 }
 
-// A struct representing the start and end positions of a sub-expression
-// in an expressions' vector of operators.
-struct SubExpr {
-    start: usize,
-    end: usize,
+/* step_budget
+* Get the number of steps after which a simulation over the given data is 
+* abandoned. Fixed steps may take as many as needed to cover the data, one 
+* shortened step per data point and MAX_STEPS more, while a step that is not
+* positive and finite, which would never reach the next data point, gets 
+* MAX_STEPS only.
+*/
+fn step_budget(states: &[State], step: f64, integrator: &dyn Integrator) 
+    -> usize {
+    if integrator.is_adaptive() || !(step > 0.0 && step.is_finite()) {
+        return MAX_STEPS;
+    }
+    let span = match (states.first(), states.last()) {
+        (Some(first), Some(last)) => last.time - first.time,
+        _ => 0.0,
+    };
+    // Note that the cast saturates for spans too long to count the steps of.
+    let needed = (span / step).ceil() as usize;
+    return MAX_STEPS.saturating_add(needed).saturating_add(states.len());
+}
+
+/* advance
+* Take a single step of the ODE with the given right-hand side.
+* Input:
+*     limit - The length of the step, or for adaptive integrators the 
+*         maximum length.
+*     suggested - Length of the next adaptive step, updated as we go.
+* Output:
+*     The new state and the length of the step taken.
+*/
+fn advance<F>(
+    rhs: &F, 
+    integrator: &dyn Integrator,
+    state: &State, 
+    limit: f64, 
+    suggested: &mut f64) -> (State, f64)
+    where F: Fn(&State) -> Vec<f64> {
+    if !integrator.is_adaptive() {
+        let (new_state, taken, _) = integrator.step(rhs, state, limit);
+        return (new_state, taken);
+    }
+
+    // A step shortened only to respect the limit should not shrink the 
+    // steps that follow.
+    let clipped = limit < *suggested;
+    let (new_state, taken, next) = 
+        integrator.step(rhs, state, suggested.min(limit));
+    *suggested = match clipped && taken >= limit {
+        true => suggested.max(next),
+        false => next,
+    };
+    return (new_state, taken);
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::integrate::{DormandPrince, Rk4};
+
+    fn data() -> Vec<State> {
+        return vec![State::new(0.0, 1.0), State::new(1.0, 2.0)];
+    }
+
+    #[test]
+    fn simulate_abandons_steps_that_make_no_progress() {
+        let map = OperatorMap::new();
+        let expr = Expr::parse("POS", &map).unwrap();
+        for step in [0.0, -0.1].iter() {
+            let trajectory = expr.simulate(&data(), *step, &Rk4, false);
+            assert_eq!(trajectory.len(), 2);
+            assert_eq!(trajectory[1].time, 1.0);
+            assert!(trajectory[1].position().is_nan());
+        }
+        let trajectory = expr.simulate(&data(), 0.0, &DormandPrince::default(),
+            false);
+        assert!(trajectory[1].position().is_nan());
+    }
+
+    #[test]
+    fn fitness_abandons_steps_that_make_no_progress() {
+        let map = OperatorMap::new();
+        let expr = Expr::parse("POS", &map).unwrap();
+        let fitness = expr.fitness(
+            &data(), 0.0, &Rk4, &crate::metric::Area, Sampling::Steps);
+        assert!(fitness.is_nan());
+    }
+
+    #[test]
+    fn step_budget_covers_the_data() {
+        let states: Vec<State> = (0..=10)
+            .map(|i| State::new(i as f64 * 10.0, 0.0))
+            .collect();
+        assert_eq!(step_budget(&states, 0.01, &Rk4), MAX_STEPS + 10_000 + 11);
+        assert_eq!(step_budget(&states, 0.0, &Rk4), MAX_STEPS);
+        assert_eq!(step_budget(&states, f64::NAN, &Rk4), MAX_STEPS);
+    }
+
+    // Unevenly sampled data of x' = x.
+    fn exponential() -> Vec<State> {
+        return [0.0, 0.1, 0.5, 0.6, 1.5, 2.0].iter()
+            .map(|time| State::new(*time, time.exp()))
+            .collect();
+    }
+
+    #[test]
+    fn data_times_samples_at_the_data() {
+        let rhs = |state: &State| vec![state.position()];
+        let states = exponential();
+        for integrator in [&Rk4 as &dyn Integrator, 
+            &DormandPrince::default()].iter() {
+            let samples = samples(&rhs, states[0].clone(), &states, 0.03, 
+                *integrator, Sampling::DataTimes);
+            assert_eq!(samples.len(), states.len());
+            for (sample, state) in samples.iter().zip(states.iter()) {
+                assert_eq!(sample.time, state.time);
+                assert_eq!(sample.observed, state.values);
+            }
+            // Each sample spans the data points on either side.
+            assert_eq!(samples[0].span, 0.1);
+            assert_eq!(samples[2].span, 0.5);
+        }
+    }
+
+    #[test]
+    fn data_times_fit_the_exact_model() {
+        let map = OperatorMap::new();
+        let expr = Expr::parse("POS", &map).unwrap();
+        let states = exponential();
+        let fitness = |sampling| expr.fitness(&states, 0.03, &Rk4, 
+            &crate::metric::MaxError, sampling);
+
+        assert!(fitness(Sampling::DataTimes) < 1e-6);
+        // Steps are compared against the data interpolated between the 
+        // distant points, which the exact model is not.
+        assert!(fitness(Sampling::Steps) > 1e-2);
+    }
+
+    // Samples of a function every tenth of a unit of time up to ten.
+    fn sampled(position: fn(f64) -> f64) -> Vec<State> {
+        return (0..=100)
+            .map(|i| i as f64 * 0.1)
+            .map(|time| State::new(time, position(time)))
+            .collect();
+    }
+
+    // The second-order system x'' = -x.
+    fn oscillator() -> System {
+        let map = crate::operator::builtin_map();
+        let mut system = System::from(Expr::parse("SUB 0 POS", &map).unwrap());
+        system.set_order(Order::Second);
+        return system;
+    }
+
+    #[test]
+    fn second_order_systems_reproduce_cosine() {
+        let states = sampled(f64::cos);
+        let mut system = oscillator();
+        system.set_velocities(Some(vec![vec![0.0]]));
+
+        let trajectory = system.simulate(&states, 0, 0.01, &Rk4, false);
+        assert_eq!(trajectory.len(), states.len());
+        for state in trajectory.iter() {
+            assert!((state.position() - state.time.cos()).abs() < 1e-6);
+            assert!((state.velocities[0] + state.time.sin()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn initial_velocities_are_estimated_from_the_data() {
+        // The error of the one-sided difference is about h^2 / 3.
+        let states = sampled(f64::sin);
+        assert!((estimate_velocities(&states)[0] - 1.0).abs() < 4e-3);
+        // Uneven spacing is second order accurate as well.
+        let uneven: Vec<State> = [0.0, 0.01, 0.03].iter()
+            .map(|time| State::new(*time, time.sin()))
+            .collect();
+        assert!((estimate_velocities(&uneven)[0] - 1.0).abs() < 1e-4);
+        assert_eq!(estimate_velocities(&states[..1]), vec![0.0]);
+
+        // Systems without velocities start from the estimate.
+        let trajectory = oscillator().simulate(&states, 0, 0.01, &Rk4, false);
+        assert!((trajectory[0].velocities[0] - 1.0).abs() < 4e-3);
+        for state in trajectory.iter() {
+            assert!((state.position() - state.time.sin()).abs() < 1e-2);
+        }
+    }
 }
@@ -5,19 +5,43 @@
 
 // External imports.
 use std::collections::HashMap;
-use rand;
+use rand::Rng;
 
-const TIME_TOKEN: &'static str = "TIME";
-const POS_TOKEN: &'static str = "POS";
+// Internal imports.
+use crate::error::{Error, Result};
+
+const TIME_TOKEN: &str = "TIME";
+const POS_TOKEN: &str = "POS";
+
+// Tokens of the operators available through the builtin function below.
+pub const BUILTIN_TOKENS: [&str; 13] = [
+    "ADD", "SUB", "MUL", "DIV", 
+    "SQUARE", "SQRT", 
+    "SIN", "COS", "TAN", 
+    "EXP", "LN", 
+    "ONE", "PI",
+];
 
 //_____________________________________________________________________________
 //                                                      Operator Type and Trait
 
+// Unary and binary operators compare and hash by function pointer, which is
+// what OperatorMap and Simplifier key on. The same function may have several
+// addresses (e.g. an inlined std method cast in different crates), so only
+// operators taken from one source compare equal reliably. Operators of
+// builtin are backed by the non-inlined functions below and are stable. Note
+// that the lint is unknown before Rust 1.85, hence allowing unknown lints.
 #[derive(Copy, Clone)]
 #[derive(PartialEq, Eq, Hash)] // Required for use as keys in HashMap.
+#[allow(unknown_lints, unpredictable_function_pointer_comparisons)]
 pub enum Operator {
     Time,
     Position, 
+    // State variable of a system by its index. Note that index zero is the
+    // same variable as Position.
+    Variable(usize),
+    // Velocity of a state variable by its index, for second-order systems.
+    Velocity(usize),
     // We store constants as the bits of a float. Note that most constants 
     // declared will be anonymous. That is, they won't be contained within the
     // operator map, only within expressions.
@@ -26,6 +50,19 @@ pub enum Operator {
     Binary(fn(f64, f64) -> f64),
 }
 
+impl Operator {
+    /* arity
+    * Get the number of arguments taken by the operator.
+    */
+    pub fn arity(&self) -> usize {
+        return match self {
+            Operator::Unary(_) => 1,
+            Operator::Binary(_) => 2,
+            _ => 0,
+        };
+    }
+}
+
 // Implement a trait for the above specified types to more easily convert to an 
 // operator type.
 pub trait ToOperator {
@@ -56,11 +93,89 @@ impl ToOperator for fn(f64, f64) -> f64 {
     }
 }
 
+/* builtin
+* Get one of the commonly used operators by its token. Each call with the same
+* token returns an equal operator, see the note on Operator.
+* Input:
+*     token - Token of the operator, one of BUILTIN_TOKENS.
+* Output:
+*     The corresponding operator, or None if the token is not a builtin.
+*/
+pub fn builtin(token: &str) -> Option<Operator> {
+    let operator = match token {
+        // Basic arithmetic operators.
+        "ADD" => (add as fn(f64, f64) -> f64).to_operator(),
+        "SUB" => (sub as fn(f64, f64) -> f64).to_operator(),
+        "MUL" => (mul as fn(f64, f64) -> f64).to_operator(),
+        "DIV" => (div as fn(f64, f64) -> f64).to_operator(),
+
+        "SQUARE" => (square as fn(f64) -> f64).to_operator(),
+        "SQRT" => (sqrt as fn(f64) -> f64).to_operator(),
+
+        // Trigonometric functions.
+        "SIN" => (sin as fn(f64) -> f64).to_operator(),
+        "COS" => (cos as fn(f64) -> f64).to_operator(),
+        "TAN" => (tan as fn(f64) -> f64).to_operator(),
+
+        // Logarithmic functions.
+        "EXP" => (exp as fn(f64) -> f64).to_operator(),
+        "LN" => (ln as fn(f64) -> f64).to_operator(),
+
+        // Named constants.
+        "ONE" => 1.0.to_operator(),
+        "PI" => std::f64::consts::PI.to_operator(),
+
+        _ => return None,
+    };
+    return Some(operator);
+}
+
+/* builtin_map
+* Get a map of every builtin operator by its token, for tests.
+*/
+#[cfg(test)]
+pub(crate) fn builtin_map() -> OperatorMap<'static> {
+    let mut map = OperatorMap::new();
+    for token in BUILTIN_TOKENS.iter() {
+        map.insert(builtin(token).unwrap(), token);
+    }
+    return map;
+}
+
+// Functions behind the builtin operators. These are never inlined so that
+// each has a single address, and have distinct bodies so that they are not
+// merged with one another.
+#[inline(never)]
+fn add(x: f64, y: f64) -> f64 { x + y }
+#[inline(never)]
+fn sub(x: f64, y: f64) -> f64 { x - y }
+#[inline(never)]
+fn mul(x: f64, y: f64) -> f64 { x * y }
+#[inline(never)]
+fn div(x: f64, y: f64) -> f64 { x / y }
+#[inline(never)]
+fn square(x: f64) -> f64 { x * x }
+#[inline(never)]
+fn sqrt(x: f64) -> f64 { x.sqrt() }
+#[inline(never)]
+fn sin(x: f64) -> f64 { x.sin() }
+#[inline(never)]
+fn cos(x: f64) -> f64 { x.cos() }
+#[inline(never)]
+fn tan(x: f64) -> f64 { x.tan() }
+#[inline(never)]
+fn exp(x: f64) -> f64 { x.exp() }
+#[inline(never)]
+fn ln(x: f64) -> f64 { x.ln() }
+
 //_____________________________________________________________________________
 //                                                      OperatorMap Type & Impl
 
 pub struct OperatorMap<'a> {
     map: HashMap<Operator, &'a str>,
+    // Operators in insertion order, so that random choices do not depend on 
+    // the iteration order of the HashMap.
+    order: Vec<Operator>,
 }
 
 impl<'a> OperatorMap<'a> {
@@ -71,14 +186,19 @@ impl<'a> OperatorMap<'a> {
     *     OperatorMap struct.
     */
     pub fn new() -> OperatorMap<'a> {
-        let mut map = HashMap::new();
+        let mut map = OperatorMap {
+            map: HashMap::new(),
+            order: Vec::new(),
+        };
 
         // Time and position are required to be in the map.
         // Note that this allows us to assume the map is not empty.
-        map.insert(Operator::Time, TIME_TOKEN);
-        map.insert(Operator::Position, POS_TOKEN);
+        map.map.insert(Operator::Time, TIME_TOKEN);
+        map.map.insert(Operator::Position, POS_TOKEN);
+        map.order.push(Operator::Time);
+        map.order.push(Operator::Position);
 
-        return OperatorMap {map};
+        return map;
     }
 
     /* len
@@ -95,21 +215,45 @@ impl<'a> OperatorMap<'a> {
     * Input:
     *     operator - Instance of operator struct (see above).
     *     token - Name of operator.
+    * Panics if the token is invalid, see try_insert.
     */
     pub fn insert<T>(&mut self, operator: T, token: &'a str)
+        where T: ToOperator {
+        if let Err(error) = self.try_insert(operator, token) {
+            panic!("{}", error);
+        }
+    }
+
+    /* try_insert
+    * Insert a given operator and corresponding token into the map.
+    * Input:
+    *     operator - Instance of operator struct (see above).
+    *     token - Name of operator.
+    * Output:
+    *     An InvalidToken error if the token contains non-alphanumeric 
+    *     characters or begins with a numeric character.
+    */
+    pub fn try_insert<T>(&mut self, operator: T, token: &'a str) -> Result<()>
         where T: ToOperator {
         // Ensure adherence to token specifications.
         if !token.chars().all(|c: char| c.is_alphanumeric()) {
-            panic!("Token {} invalid, \
-                cannot contain non-alphanumeric characters.",
-                token);
+            return Err(Error::InvalidToken {
+                token: String::from(token),
+                reason: "cannot contain non-alphanumeric characters",
+            });
         } else if token.starts_with(|c: char| c.is_numeric()) {
-            panic!("Token {} invalid, \
-                cannot begin with numeric characters.",
-                token);
-        } else {
-            self.map.insert(operator.to_operator(), token);
+            return Err(Error::InvalidToken {
+                token: String::from(token),
+                reason: "cannot begin with numeric characters",
+            });
+        }
+
+        // Replacing the token of an operator keeps its original position.
+        let operator = operator.to_operator();
+        if self.map.insert(operator, token).is_none() {
+            self.order.push(operator);
         }
+        return Ok(());
     }
 
     /* get
@@ -119,19 +263,88 @@ impl<'a> OperatorMap<'a> {
     * Output:
     *     The token of the operator. 
     */
-    pub fn get(&self, operator: &'a Operator) -> Option<&&str> {
+    pub fn get(&self, operator: &Operator) -> Option<&&'a str> {
         return self.map.get(operator);
     }
 
+    /* find
+    * Get the operator corresponding to the given token from our map.
+    * Input:
+    *     token - Name of an operator.
+    * Output:
+    *     The operator, if the token is in the map.
+    */
+    pub fn find(&self, token: &str) -> Option<Operator> {
+        return self.order.iter()
+            .find(|operator| self.map[operator] == token)
+            .copied();
+    }
+
+    /* operators
+    * Get the operators in the map in the order they were inserted.
+    */
+    pub fn operators(&self) -> &[Operator] {
+        return &self.order;
+    }
+
+    /* rand_variable
+    * Get a random state variable (position, other variable or velocity) from
+    * our map.
+    * Input:
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     Reference to an operator. 
+    */
+    pub fn rand_variable<R>(&self, rng: &mut R) -> &Operator
+        where R: Rng + ?Sized {
+        let variables: Vec<&Operator> = self.order.iter()
+            .filter(|operator| matches!(operator, 
+                Operator::Position | Operator::Variable(_) 
+                | Operator::Velocity(_)))
+            .collect();
+
+        // Note that position is always in the map from new.
+        let idx = rng.gen_range(0, variables.len());
+        return variables[idx];
+    }
+
     /* rand_operator
     * Get a random operator from our map.
+    * Input:
+    *     rng - Random number generator to draw from.
     * Output:
     *     Reference to an operator. 
     */
-    pub fn rand_operator(&self) -> &Operator {
-        let idx = rand::random::<usize>() % self.len();
-
+    pub fn rand_operator<R>(&self, rng: &mut R) -> &Operator
+        where R: Rng + ?Sized {
         // Note that there are at least two elements in map from new.
-        return self.map.keys().skip(idx).next().unwrap();
+        let idx = rng.gen_range(0, self.len());
+        return &self.order[idx];
+    }
+
+    /* rand_with_arity
+    * Get a random operator taking the given number of arguments from our map.
+    * Input:
+    *     arity - Number of arguments of the operator.
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     Reference to an operator, or None if no operator has the arity.
+    */
+    pub fn rand_with_arity<R>(&self, arity: usize, rng: &mut R) 
+        -> Option<&Operator>
+        where R: Rng + ?Sized {
+        let operators: Vec<&Operator> = self.order.iter()
+            .filter(|operator| operator.arity() == arity)
+            .collect();
+        if operators.is_empty() {
+            return None;
+        }
+        return Some(operators[rng.gen_range(0, operators.len())]);
     }
-}
\ No newline at end of file
+}
+
+impl<'a> Default for OperatorMap<'a> {
+    fn default() -> OperatorMap<'a> {
+        return OperatorMap::new();
+    }
+}
@@ -0,0 +1,537 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Parsing of expressions from text, the inverse of Expr::to_string.
+//_____________________________________________________________________________
+
+// Internal imports.
+use crate::error::{Error, Result};
+use crate::ode::Expr;
+use crate::operator::{Operator, ToOperator, OperatorMap};
+
+// Tokens of the operators that infix symbols stand for. These must be in the
+// map for the symbols to be used.
+const INFIX_TOKENS: [(char, &str); 5] = [
+    ('+', "ADD"), ('-', "SUB"), ('*', "MUL"), ('/', "DIV"), ('^', "POW"),
+];
+const SQUARE_TOKEN: &str = "SQUARE";
+
+// Symbols that the default infix rendering gives operators (see 
+// render::Renderer::new), and the tokens they stand for, so that rendered 
+// expressions parse back. Tokens in the map take priority.
+const SYMBOL_TOKENS: [(&str, &str); 3] = [
+    ("t", "TIME"), ("x", "POS"), ("x'", "VEL"),
+];
+
+/* parse
+* Parse an expression in either prefix or infix notation. Prefix notation is
+* tried first as it is what Expr::to_string produces.
+* Input:
+*     text - Text of the expression.
+*     map - Operators the tokens of the text refer to.
+* Output:
+*     The parsed expression.
+*/
+pub fn parse(text: &str, map: &OperatorMap) -> Result<Expr> {
+    let error = match prefix(text, map) {
+        Ok(expr) => return Ok(expr),
+        Err(error) => error,
+    };
+
+    // When every word is a valid prefix token the text was most likely meant
+    // as prefix notation, so report the prefix error if infix fails too.
+    let all_tokens = text.split_whitespace()
+        .all(|word| lookup(word, map).is_ok());
+
+    return match infix(text, map) {
+        Ok(expr) => Ok(expr),
+        Err(_) if all_tokens => Err(error),
+        Err(infix_error) => Err(infix_error),
+    };
+}
+
+/* prefix
+* Parse an expression of whitespace separated operator tokens and numbers in
+* prefix order, e.g. "DIV ADD TIME 3.2 POS".
+*/
+pub fn prefix(text: &str, map: &OperatorMap) -> Result<Expr> {
+    let operators = text.split_whitespace()
+        .map(|word| lookup(word, map))
+        .collect::<Result<Vec<Operator>>>()?;
+
+    if operators.is_empty() {
+        return Err(Error::Parse(String::from("expression is empty")));
+    }
+    return Expr::from_operators(operators);
+}
+
+/* infix
+* Parse an expression in infix notation, e.g. "2*TIME + SIN(POS)". The usual
+* precedence of + - * / ^ applies, functions are called with parentheses and
+* tokens may be written in lowercase or by their rendered symbols, e.g. 
+* "2*t + sin(x)". This reads back the output of render::Renderer::infix.
+*/
+pub fn infix(text: &str, map: &OperatorMap) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: lex(text)?,
+        idx: 0,
+        map,
+    };
+
+    let operators = parser.expr()?;
+    if let Some((position, token)) = parser.tokens.get(parser.idx) {
+        return Err(Error::Parse(format!(
+            "unexpected {} at position {}", token.describe(), position)));
+    }
+    return Expr::from_operators(operators);
+}
+
+/* lookup
+* Get the operator of a token, falling back to its uppercase form and then to
+* an anonymous constant.
+*/
+fn lookup(word: &str, map: &OperatorMap) -> Result<Operator> {
+    if let Some(operator) = map.find(word) {
+        return Ok(operator);
+    }
+    if let Some(operator) = map.find(&word.to_uppercase()) {
+        return Ok(operator);
+    }
+    if let Ok(c) = word.parse::<f64>() {
+        return Ok(c.to_operator());
+    }
+    return Err(Error::UnknownToken(String::from(word)));
+}
+
+//_____________________________________________________________________________
+//                                                                        Lexer
+
+#[derive(Clone)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Symbol(char),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        return match self {
+            Token::Number(c) => format!("number {}", c),
+            Token::Ident(name) => format!("token {}", name),
+            Token::Symbol(c) => format!("symbol {}", c),
+        };
+    }
+}
+
+/* lex
+* Split infix text into tokens paired with their character positions.
+*/
+fn lex(text: &str) -> Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        let c = chars[idx];
+        let start = idx;
+
+        if c.is_whitespace() {
+            idx += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            // Numbers, allowing for an exponent such as 1.5e-3.
+            while idx < chars.len() && (chars[idx].is_ascii_digit()
+                || chars[idx] == '.'
+                || ((chars[idx] == 'e' || chars[idx] == 'E')
+                    && idx + 1 < chars.len()
+                    && (chars[idx + 1].is_ascii_digit()
+                        || chars[idx + 1] == '-' || chars[idx + 1] == '+'))
+                || ((chars[idx] == '-' || chars[idx] == '+')
+                    && (chars[idx - 1] == 'e' || chars[idx - 1] == 'E'))) {
+                idx += 1;
+            }
+            let word: String = chars[start..idx].iter().collect();
+            match word.parse::<f64>() {
+                Ok(c) => tokens.push((start, Token::Number(c))),
+                Err(_) => return Err(Error::Parse(format!(
+                    "invalid number {} at position {}", word, start))),
+            }
+        } else if c.is_alphanumeric() {
+            while idx < chars.len() && chars[idx].is_alphanumeric() {
+                idx += 1;
+            }
+            // Primes, as in the symbol x' of velocity.
+            while idx < chars.len() && chars[idx] == '\'' {
+                idx += 1;
+            }
+            let word: String = chars[start..idx].iter().collect();
+            tokens.push((start, Token::Ident(word)));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            idx += 1;
+        } else {
+            return Err(Error::Parse(format!(
+                "unexpected character {} at position {}", c, start)));
+        }
+    }
+
+    return Ok(tokens);
+}
+
+//_____________________________________________________________________________
+//                                                                       Parser
+
+// Recursive descent parser producing operators in prefix order.
+struct Parser<'m, 'a> {
+    tokens: Vec<(usize, Token)>,
+    idx: usize,
+    map: &'m OperatorMap<'a>,
+}
+
+impl<'m, 'a> Parser<'m, 'a> {
+    /* expr
+    * expr := term (('+' | '-') term)*
+    */
+    fn expr(&mut self) -> Result<Vec<Operator>> {
+        let mut lhs = self.term()?;
+        while let Some(symbol) = self.eat_symbol(&['+', '-']) {
+            let rhs = self.term()?;
+            lhs = self.apply(symbol, lhs, rhs)?;
+        }
+        return Ok(lhs);
+    }
+
+    /* term
+    * term := unary (('*' | '/') unary)*
+    */
+    fn term(&mut self) -> Result<Vec<Operator>> {
+        let mut lhs = self.unary()?;
+        while let Some(symbol) = self.eat_symbol(&['*', '/']) {
+            let rhs = self.unary()?;
+            lhs = self.apply(symbol, lhs, rhs)?;
+        }
+        return Ok(lhs);
+    }
+
+    /* unary
+    * unary := '-' unary | power
+    */
+    fn unary(&mut self) -> Result<Vec<Operator>> {
+        if self.eat_symbol(&['-']).is_none() {
+            return self.power();
+        }
+
+        let operand = self.unary()?;
+
+        // Negative numbers become a single constant.
+        if let [Operator::Constant(c)] = operand.as_slice() {
+            return Ok(vec![(-f64::from_bits(*c)).to_operator()]);
+        }
+
+        // Otherwise negate by subtracting from zero, or failing that, by
+        // multiplying by negative one.
+        if let Some(sub) = self.binary_token("SUB") {
+            let mut operators = vec![sub, 0.0.to_operator()];
+            operators.extend(operand);
+            return Ok(operators);
+        }
+        let mul = self.binary_token("MUL")
+            .ok_or_else(|| Error::UnknownToken(String::from("SUB")))?;
+        let mut operators = vec![mul, (-1.0).to_operator()];
+        operators.extend(operand);
+        return Ok(operators);
+    }
+
+    /* power
+    * power := atom ('^' unary)?
+    * Without a POW operator in the map, squares are written with SQUARE.
+    */
+    fn power(&mut self) -> Result<Vec<Operator>> {
+        let base = self.atom()?;
+        if self.eat_symbol(&['^']).is_none() {
+            return Ok(base);
+        }
+
+        let exponent = self.unary()?;
+        if self.binary_token("POW").is_some() {
+            return self.apply('^', base, exponent);
+        }
+
+        let is_two = match exponent.as_slice() {
+            [Operator::Constant(c)] => f64::from_bits(*c) == 2.0,
+            _ => false,
+        };
+        match self.map.find(SQUARE_TOKEN) {
+            Some(square) if is_two && square.arity() == 1 => {
+                let mut operators = vec![square];
+                operators.extend(base);
+                return Ok(operators);
+            },
+            _ => return Err(Error::UnknownToken(String::from("POW"))),
+        }
+    }
+
+    /* atom
+    * atom := number | token | token '(' expr (',' expr)* ')' | '(' expr ')'
+    */
+    fn atom(&mut self) -> Result<Vec<Operator>> {
+        let (position, token) = match self.tokens.get(self.idx) {
+            Some((position, token)) => (*position, token.clone()),
+            None => return Err(Error::Parse(
+                String::from("unexpected end of expression"))),
+        };
+        self.idx += 1;
+
+        match token {
+            Token::Number(c) => return Ok(vec![c.to_operator()]),
+            Token::Symbol('(') => {
+                let operators = self.expr()?;
+                self.expect(')')?;
+                return Ok(operators);
+            },
+            Token::Symbol(_) => return Err(Error::Parse(format!(
+                "unexpected {} at position {}", token.describe(), position))),
+            Token::Ident(name) => {
+                let operator = lookup(&name, self.map)
+                    .or_else(|error| self.symbol(&name).ok_or(error))?;
+
+                if self.eat_symbol(&['(']).is_none() {
+                    if operator.arity() != 0 {
+                        return Err(Error::Parse(format!(
+                            "{} at position {} requires arguments",
+                            name, position)));
+                    }
+                    return Ok(vec![operator]);
+                }
+
+                // Function call, parse the comma separated arguments.
+                let mut operators = vec![operator];
+                let mut num_args = 0;
+                if self.eat_symbol(&[')']).is_none() {
+                    loop {
+                        operators.extend(self.expr()?);
+                        num_args += 1;
+                        if self.eat_symbol(&[',']).is_none() {
+                            break;
+                        }
+                    }
+                    self.expect(')')?;
+                }
+
+                if num_args != operator.arity() {
+                    return Err(Error::Parse(format!(
+                        "{} at position {} takes {} arguments, got {}",
+                        name, position, operator.arity(), num_args)));
+                }
+                return Ok(operators);
+            },
+        }
+    }
+
+    /* apply
+    * Combine two operands with the binary operator of an infix symbol.
+    */
+    fn apply(&self, symbol: char, lhs: Vec<Operator>, rhs: Vec<Operator>)
+        -> Result<Vec<Operator>> {
+        let token = INFIX_TOKENS.iter()
+            .find(|(c, _)| *c == symbol)
+            .map(|(_, token)| *token)
+            .unwrap();
+        let operator = self.binary_token(token)
+            .ok_or_else(|| Error::UnknownToken(String::from(token)))?;
+
+        let mut operators = vec![operator];
+        operators.extend(lhs);
+        operators.extend(rhs);
+        return Ok(operators);
+    }
+
+    /* symbol
+    * Get the operator a rendered symbol stands for, see SYMBOL_TOKENS.
+    */
+    fn symbol(&self, name: &str) -> Option<Operator> {
+        return SYMBOL_TOKENS.iter()
+            .find(|(symbol, _)| *symbol == name)
+            .and_then(|(_, token)| self.map.find(token));
+    }
+
+    /* binary_token
+    * Get a binary operator from the map by its token.
+    */
+    fn binary_token(&self, token: &str) -> Option<Operator> {
+        return self.map.find(token).filter(|operator| operator.arity() == 2);
+    }
+
+    /* eat_symbol
+    * Consume the next token if it is one of the given symbols.
+    */
+    fn eat_symbol(&mut self, symbols: &[char]) -> Option<char> {
+        if let Some((_, Token::Symbol(c))) = self.tokens.get(self.idx) {
+            if symbols.contains(c) {
+                self.idx += 1;
+                return Some(*c);
+            }
+        }
+        return None;
+    }
+
+    /* expect
+    * Consume the given symbol, failing if it is not next.
+    */
+    fn expect(&mut self, symbol: char) -> Result<()> {
+        if self.eat_symbol(&[symbol]).is_some() {
+            return Ok(());
+        }
+        return match self.tokens.get(self.idx) {
+            Some((position, token)) => Err(Error::Parse(format!(
+                "expected {} but found {} at position {}",
+                symbol, token.describe(), position))),
+            None => Err(Error::Parse(format!(
+                "expected {} but found end of expression", symbol))),
+        };
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::builtin_map;
+    use crate::render::Renderer;
+
+    /* prefix_of
+    * Get the prefix description of an expression without the trailing 
+    * separator.
+    */
+    fn prefix_of(expr: &Expr, map: &OperatorMap) -> String {
+        return String::from(expr.to_string(map).trim_end());
+    }
+
+    #[test]
+    fn prefix_round_trips_through_to_string() {
+        let map = builtin_map();
+        let cases = [
+            "POS",
+            "-2.5",
+            "DIV ADD TIME 3.2 POS",
+            "SIN MUL PI SQUARE SUB POS ONE",
+            "EXP LN SQRT TAN COS 0.001",
+        ];
+        for text in cases.iter() {
+            let expr = prefix(text, &map).unwrap();
+            assert_eq!(prefix_of(&expr, &map), *text);
+            assert_eq!(prefix_of(&parse(text, &map).unwrap(), &map), *text);
+        }
+    }
+
+    #[test]
+    fn prefix_accepts_lowercase_tokens() {
+        let map = builtin_map();
+        let expr = prefix("add pos time", &map).unwrap();
+        assert_eq!(prefix_of(&expr, &map), "ADD POS TIME");
+    }
+
+    #[test]
+    fn infix_follows_precedence() {
+        let map = builtin_map();
+        let cases = [
+            ("1 + 2 * POS", "ADD ONE MUL 2 POS"),
+            ("(1 + 2) * POS", "MUL ADD ONE 2 POS"),
+            ("TIME - POS - 2", "SUB SUB TIME POS 2"),
+            ("TIME - (POS - 2)", "SUB TIME SUB POS 2"),
+            ("TIME / POS * 2", "MUL DIV TIME POS 2"),
+            ("-POS * 2", "MUL SUB 0 POS 2"),
+            ("-3 + TIME", "ADD -3 TIME"),
+            ("2 * -3", "MUL 2 -3"),
+            ("POS^2", "SQUARE POS"),
+            ("sin(pos) + Cos(TIME)", "ADD SIN POS COS TIME"),
+            ("1.5e-3 * t", "MUL 0.0015 TIME"),
+        ];
+        for (text, expected) in cases.iter() {
+            let expr = infix(text, &map)
+                .unwrap_or_else(|e| panic!("{}: {}", text, e));
+            assert_eq!(prefix_of(&expr, &map), *expected, "{}", text);
+            assert_eq!(prefix_of(&parse(text, &map).unwrap(), &map), 
+                *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn infix_round_trips_through_renderer() {
+        let map = builtin_map();
+        let renderer = Renderer::new();
+        let cases = [
+            "DIV ADD TIME 3.2 POS",
+            "SUB TIME SUB POS 2",
+            "SUB SUB TIME POS 2",
+            "MUL ADD POS PI SUB TIME -0.5",
+            "DIV DIV TIME POS MUL POS 2",
+            "SQUARE SIN SUB POS 1.5",
+            "EXP DIV -1 TIME",
+        ];
+        for text in cases.iter() {
+            let expr = prefix(text, &map).unwrap();
+            let rendered = renderer.infix(&expr, &map).unwrap();
+            let parsed = infix(&rendered, &map)
+                .unwrap_or_else(|e| panic!("{}: {}", rendered, e));
+            assert_eq!(prefix_of(&parsed, &map), *text, "via {}", rendered);
+        }
+    }
+
+    #[test]
+    fn infix_reads_rendered_symbols_after_map_tokens() {
+        let mut map = builtin_map();
+        map.insert(Operator::Velocity(0), "VEL");
+        let expr = infix("t * x + x'", &map).unwrap();
+        assert_eq!(prefix_of(&expr, &map), "ADD MUL TIME POS VEL");
+
+        // Tokens in the map take priority over the symbols.
+        let mut map = OperatorMap::new();
+        map.insert(Operator::Variable(1), "x");
+        let expr = infix("x", &map).unwrap();
+        assert!(matches!(expr.operators(), [Operator::Variable(1)]));
+    }
+
+    #[test]
+    fn arity_errors() {
+        let map = builtin_map();
+        for text in ["ADD POS", "SIN", "POS TIME", "ADD POS TIME 1"].iter() {
+            assert!(matches!(prefix(text, &map), 
+                Err(Error::MalformedExpr(_))), "{}", text);
+        }
+        for text in ["sin(POS, TIME)", "ADD(POS)", "SIN", "ADD()"].iter() {
+            assert!(matches!(infix(text, &map), Err(Error::Parse(_))),
+                "{}", text);
+        }
+        assert!(matches!(parse("ADD POS", &map), 
+            Err(Error::MalformedExpr(_))));
+    }
+
+    #[test]
+    fn unknown_token_errors() {
+        let map = builtin_map();
+        assert!(matches!(prefix("ADD POS FOO", &map), 
+            Err(Error::UnknownToken(token)) if token == "FOO"));
+        assert!(matches!(infix("POS + foo(TIME)", &map),
+            Err(Error::UnknownToken(token)) if token == "foo"));
+        assert!(matches!(parse("POS + y", &map),
+            Err(Error::UnknownToken(token)) if token == "y"));
+
+        // Infix symbols need their operators in the map.
+        let map = OperatorMap::new();
+        assert!(matches!(infix("POS + TIME", &map),
+            Err(Error::UnknownToken(token)) if token == "ADD"));
+        assert!(matches!(infix("POS^3", &map),
+            Err(Error::UnknownToken(token)) if token == "POW"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let map = builtin_map();
+        for text in ["", "(POS", "POS)", "POS +", "POS $ 2", "1.2.3"].iter() {
+            assert!(matches!(infix(text, &map), Err(Error::Parse(_))),
+                "{}", text);
+        }
+        assert!(matches!(prefix("", &map), Err(Error::Parse(_))));
+    }
+}
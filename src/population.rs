@@ -5,14 +5,34 @@
 //external imports.
 use std::cmp::Ordering;
 
-use rand::Rng;
-use rand_distr::Exp;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
 // Internal imports.
+use crate::crossover::Crossover;
+use crate::data::{self, Trajectory};
+use crate::error::{Error, Result};
+use crate::gradient::{self, Target};
+use crate::initialize::Initializer;
 use crate::operator::OperatorMap;
-use crate::ode::{State, Expr};
+use crate::integrate::{Integrator, Rk4};
+use crate::metric::{Aggregate, Area, FitnessMetric, Sample, Sampling};
+use crate::mutation::Mutation;
+use crate::ode::{self, Expr, Order, State, System};
+use crate::operator::Operator;
+use crate::preprocess::{Differentiation, Preprocessor};
+use crate::selection::{self, Exponential, Selection};
+use crate::simplify::Simplifier;
 
-const TIME_STEP: f64 = 0.01;
+pub const TIME_STEP: f64 = 0.01;
+
+// Number of expressions generated in search of one within the limits 
+// before settling for a leaf.
+const GENERATE_ATTEMPTS: usize = 100;
+
+// Number of golden section iterations used to optimize each initial value,
+// each narrowing the search interval by a factor of about 0.618.
+const LINE_SEARCH_ITERATIONS: usize = 20;
 
 //_____________________________________________________________________________
 //                                                       Individual Type & Impl
@@ -20,7 +40,11 @@ const TIME_STEP: f64 = 0.01;
 #[derive(Clone)]
 pub struct Individual {
     pub fitness: f64,
-    pub expr: Expr,
+    // The evolved ODE, with one expression per state variable.
+    pub system: System,
+    // Error of each state variable at each data point of each trajectory in
+    // turn, see selection::cases. Only found for selections that use them.
+    pub cases: Vec<f64>,
 }
 
 // Implement an ordering to allow for sorting.
@@ -50,12 +74,154 @@ impl PartialEq for Individual {
 impl Eq for Individual { }
 
 
+//_____________________________________________________________________________
+//                                                         InitialVelocity Type
+
+// How second-order individuals get the velocities they start from.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InitialVelocity {
+    // Estimated from the data by finite differences.
+    Estimate,
+    // Part of each individual, starting near the estimate and evolved along 
+    // with the expressions.
+    Evolve,
+}
+
+//_____________________________________________________________________________
+//                                                        InitialCondition Type
+
+// Where simulations of individuals start from.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum InitialCondition {
+    // The first data point.
+    First,
+    // A least squares quadratic through the given number of data points 
+    // evaluated at the first data time, smoothing noise in the first point.
+    Smoothed(usize),
+    // Free parameters optimized for each individual by a line search on each
+    // state variable in turn.
+    Optimize,
+}
+
+//_____________________________________________________________________________
+//                                                              Evaluation Type
+
+// How new individuals are tested against the data.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Evaluation {
+    // Simulate each individual and compare its trajectory with the data.
+    Integrate,
+    // Compare the expressions of each individual against derivatives 
+    // estimated from the data, without simulating. Much faster, but noise 
+    // in the data must be smoothed by the differentiation.
+    Gradient,
+    // Screen each batch of new individuals by gradient matching, simulating
+    // only the given fraction that match best. The rest are given infinite 
+    // fitness.
+    Screen(f64),
+}
+
+//_____________________________________________________________________________
+//                                                           Limits Type & Impl
+
+// Bounds on the expressions of new individuals, controlling bloat. Note that
+// expressions added to the population directly are not bounded.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Limits {
+    // Greatest depth of each expression, see Expr::depth.
+    pub depth: Option<usize>,
+    // Greatest number of operators in each expression.
+    pub size: Option<usize>,
+    // What happens to offspring exceeding the limits.
+    pub overflow: Overflow,
+}
+
+impl Limits {
+    /* new
+    * Create limits bounding nothing.
+    */
+    pub fn new() -> Limits {
+        return Limits {depth: None, size: None, overflow: Overflow::Reject};
+    }
+
+    /* allows
+    * Whether an expression is within the limits.
+    */
+    pub fn allows(&self, expr: &Expr) -> bool {
+        let within = |value: usize, limit: Option<usize>| match limit {
+            Some(limit) => value <= limit,
+            None => true,
+        };
+        return within(expr.size(), self.size)
+            && within(expr.depth(), self.depth);
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        return Limits::new();
+    }
+}
+
+// What happens to offspring exceeding the limits.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Overflow {
+    // The first parent is copied into the next generation instead.
+    Reject,
+    // Crossover and mutation of the same parents is retried up to the given
+    // number of times before the offspring is rejected.
+    Retry(usize),
+}
+
 //_____________________________________________________________________________
 //                                                       Population Type & Impl
 
 pub struct Population {
-    // Data we are trying to fit.
-    states: Vec<State>,
+    // Data we are trying to fit, one or more runs of the same system.
+    trajectories: Vec<Trajectory>,
+
+    // Time step and integration scheme used when simulating individuals.
+    step: f64,
+    integrator: Box<dyn Integrator>,
+
+    // Scores simulated individuals against the data, compared where given
+    // by the sampling and combined over trajectories by the aggregate.
+    metric: Box<dyn FitnessMetric>,
+    sampling: Sampling,
+    aggregate: Aggregate,
+
+    // Source of all randomness during evolution. Seeding it reproduces a run.
+    rng: ChaCha8Rng,
+
+    // Applied to every new individual, if set.
+    simplifier: Option<Simplifier>,
+
+    // Order of the ODEs being fit, and for second-order ODEs how the initial
+    // velocities are found.
+    order: Order,
+    initial_velocity: InitialVelocity,
+
+    // Where simulations of individuals start from.
+    initial_condition: InitialCondition,
+
+    // How new individuals are tested, and how derivatives of the data are 
+    // estimated for gradient matching.
+    evaluation: Evaluation,
+    differentiation: Differentiation,
+
+    // Chooses the parents of each generation, and how their offspring are 
+    // formed and mutated.
+    selection: Box<dyn Selection>,
+    crossover: Crossover,
+    mutation: Mutation,
+
+    // How the expressions of the initial population are generated.
+    initializer: Initializer,
+
+    // Bounds on new expressions, and the fitness added per operator of each
+    // individual to favour smaller ones.
+    limits: Limits,
+    parsimony: f64,
 
     // Information on the population.
     pub population: Vec<Individual>,
@@ -65,13 +231,26 @@ pub struct Population {
 impl<'a> Population {
 
     /* new
+    * Create an empty population fit to the given time and position data.
+    * Panics if the data is mismatched, empty or invalid, see try_new.
     */
     pub fn new(times: Vec<f64>, positions: Vec<f64>) -> Population {
+        return Population::try_new(times, positions)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /* try_new
+    * Create an empty population fit to the given time and position data.
+    * Output:
+    *     The population, or an error if the times and positions differ in 
+    *     length, are empty or are invalid (see try_from_trajectories).
+    */
+    pub fn try_new(times: Vec<f64>, positions: Vec<f64>) -> Result<Population> {
         if times.len() != positions.len() {
-            panic!("Time and position data must be of equal lengths.");
-        }
-        if times.len() == 0 {
-            panic!("Time and position data cannot be emtpy.");
+            return Err(Error::MismatchedData {
+                times: times.len(), 
+                positions: positions.len(),
+            });
         }
 
         let states = 
@@ -79,103 +258,1066 @@ impl<'a> Population {
             .map(|state: (&f64, &f64)| State::new(*state.0, *state.1))
             .collect();
 
+        return Population::try_from_states(states);
+    }
+
+    /* from_states
+    * Create an empty population fit to the given states, such as those read
+    * by the data module. Panics if there are no states, see try_from_states.
+    */
+    pub fn from_states(states: Vec<State>) -> Population {
+        return Population::try_from_states(states)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /* try_from_states
+    * Create an empty population fit to the given states. States with more 
+    * than one variable are fit by a system of coupled ODEs.
+    * Output:
+    *     The population, or an EmptyData error if there are no states or a 
+    *     DimensionMismatch error if they have different numbers of variables.
+    */
+    pub fn try_from_states(states: Vec<State>) -> Result<Population> {
+        return Population::try_from_trajectories(
+            vec![Trajectory::new("data", states)]);
+    }
+
+    /* from_trajectories
+    * Create an empty population fit to several trajectories of the same 
+    * system at once. Panics if any trajectory is empty, see 
+    * try_from_trajectories.
+    */
+    pub fn from_trajectories(trajectories: Vec<Trajectory>) -> Population {
+        return Population::try_from_trajectories(trajectories)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    /* try_from_trajectories
+    * Create an empty population fit to several trajectories of the same 
+    * system at once, each simulated from its own initial state.
+    * Output:
+    *     The population, or an EmptyData error if there are no trajectories
+    *     or any has no states, a DimensionMismatch error if the states 
+    *     have different numbers of variables, or a data error if any are 
+    *     non-finite or out of time order (see data::clean).
+    */
+    pub fn try_from_trajectories(trajectories: Vec<Trajectory>) 
+        -> Result<Population> {
+        let states = || trajectories.iter()
+            .flat_map(|trajectory| trajectory.states.iter());
+        let dimension = match states().next() {
+            Some(state) if state.dimension() > 0 => state.dimension(),
+            _ => return Err(Error::EmptyData),
+        };
+        if trajectories.iter().any(|trajectory| trajectory.states.is_empty()) {
+            return Err(Error::EmptyData);
+        }
+
+        if let Some(state) = states().find(|s| s.dimension() != dimension) {
+            return Err(Error::DimensionMismatch {
+                expected: dimension,
+                found: state.dimension(),
+            });
+        }
+        for trajectory in trajectories.iter() {
+            data::validate(&trajectory.states)?;
+        }
+
         let population = Vec::new();
         let generation = 0;
 
-        return Population {
-            states, 
+        return Ok(Population {
+            trajectories, 
+            step: TIME_STEP,
+            integrator: Box::new(Rk4),
+            metric: Box::new(Area),
+            sampling: Sampling::Steps,
+            aggregate: Aggregate::Sum,
+            rng: ChaCha8Rng::from_entropy(),
+            simplifier: None,
+            order: Order::First,
+            initial_velocity: InitialVelocity::Estimate,
+            initial_condition: InitialCondition::First,
+            evaluation: Evaluation::Integrate,
+            differentiation: Differentiation::FiniteDifference,
+            selection: Box::new(Exponential::default()),
+            crossover: Crossover::Subtree,
+            mutation: Mutation::new(),
+            initializer: Initializer::new(),
+            limits: Limits::new(),
+            parsimony: 0.0,
             population, 
             generation,
-        };
+        });
+    }
+
+    /* states
+    * Get the data of the first trajectory the population is being fit to, 
+    * the only one unless created from several.
+    */
+    pub fn states(&self) -> &[State] {
+        return &self.trajectories[0].states;
+    }
+
+    /* trajectories
+    * Get the data the population is being fit to.
+    */
+    pub fn trajectories(&self) -> &[Trajectory] {
+        return &self.trajectories;
+    }
+
+    /* preprocess
+    * Replace the data of every trajectory with the data preprocessed, such
+    * as smoothed or resampled. Note that individuals already in the 
+    * population keep the fitness they had against the original data.
+    * Output:
+    *     An EmptyData error if any preprocessed trajectory has no states, or
+    *     a data error if any are non-finite or out of time order (see 
+    *     data::validate), in which case the data is unchanged.
+    */
+    pub fn preprocess(&mut self, preprocessor: &dyn Preprocessor) 
+        -> Result<()> {
+        let mut processed = Vec::with_capacity(self.trajectories.len());
+        for trajectory in self.trajectories.iter() {
+            let states = preprocessor.apply(&trajectory.states);
+            if states.is_empty() {
+                return Err(Error::EmptyData);
+            }
+            data::validate(&states)?;
+            processed.push(states);
+        }
+
+        for (trajectory, states) in self.trajectories.iter_mut()
+            .zip(processed) {
+            trajectory.states = states;
+        }
+        return Ok(());
+    }
+
+    /* dimension
+    * Get the number of state variables of the data, and so the number of 
+    * expressions of each individual.
+    */
+    pub fn dimension(&self) -> usize {
+        return self.states()[0].dimension();
+    }
+
+    /* step
+    * Get the time step used when simulating individuals.
+    */
+    pub fn step(&self) -> f64 {
+        return self.step;
+    }
+
+    /* set_step
+    * Set the time step used when simulating individuals. Note that this only 
+    * affects individuals created after the call. Panics if the step is not
+    * positive and finite, see try_set_step.
+    */
+    pub fn set_step(&mut self, step: f64) {
+        if let Err(error) = self.try_set_step(step) {
+            panic!("{}", error);
+        }
+    }
+
+    /* try_set_step
+    * Set the time step used when simulating individuals.
+    * Output:
+    *     An InvalidStep error if the step is not positive and finite, in 
+    *     which case the step is unchanged.
+    */
+    pub fn try_set_step(&mut self, step: f64) -> Result<()> {
+        if !(step > 0.0 && step.is_finite()) {
+            return Err(Error::InvalidStep(step));
+        }
+        self.step = step;
+        return Ok(());
+    }
+
+    /* integrator
+    * Get the integration scheme used when simulating individuals.
+    */
+    pub fn integrator(&self) -> &dyn Integrator {
+        return self.integrator.as_ref();
+    }
+
+    /* set_integrator
+    * Set the integration scheme used when simulating individuals, RK4 by 
+    * default. Adaptive integrators start from the time step. Note that this
+    * only affects individuals created after the call.
+    */
+    pub fn set_integrator(&mut self, integrator: Box<dyn Integrator>) {
+        self.integrator = integrator;
+    }
+
+    /* metric
+    * Get the metric individuals are scored by.
+    */
+    pub fn metric(&self) -> &dyn FitnessMetric {
+        return self.metric.as_ref();
+    }
+
+    /* set_metric
+    * Set the metric individuals are scored by, the area between the 
+    * simulated and observed trajectories by default. Choose it to match the
+    * noise of the data. Note that this only affects individuals created 
+    * after the call.
+    */
+    pub fn set_metric(&mut self, metric: Box<dyn FitnessMetric>) {
+        self.metric = metric;
+    }
+
+    /* sampling
+    * Get where simulated individuals are compared against the data.
+    */
+    pub fn sampling(&self) -> Sampling {
+        return self.sampling;
+    }
+
+    /* set_sampling
+    * Set where simulated individuals are compared against the data, at every
+    * integration step by default. Note that this only affects individuals 
+    * created after the call.
+    */
+    pub fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    /* aggregate
+    * Get how the fitness of each trajectory is combined.
+    */
+    pub fn aggregate(&self) -> Aggregate {
+        return self.aggregate;
+    }
+
+    /* set_aggregate
+    * Set how the fitness of each trajectory is combined, summed by default.
+    * Note that this only affects individuals created after the call.
+    */
+    pub fn set_aggregate(&mut self, aggregate: Aggregate) {
+        self.aggregate = aggregate;
+    }
+
+    /* set_simplifier
+    * Set the simplifier applied to every new individual, or None to leave 
+    * expressions as they are generated.
+    */
+    pub fn set_simplifier(&mut self, simplifier: Option<Simplifier>) {
+        self.simplifier = simplifier;
+    }
+
+    /* order
+    * Get the order of the ODEs being fit.
+    */
+    pub fn order(&self) -> Order {
+        return self.order;
+    }
+
+    /* set_order
+    * Set the order of the ODEs being fit. Second-order individuals give the 
+    * acceleration x'' = f(x, x', t) of each state variable, where the 
+    * velocities are referred to by Operator::Velocity. Note that this only 
+    * affects individuals created after the call.
+    */
+    pub fn set_order(&mut self, order: Order) {
+        self.order = order;
+    }
+
+    /* set_initial_velocity
+    * Set how second-order individuals get the velocities they start from.
+    * Note that this only affects individuals created after the call.
+    */
+    pub fn set_initial_velocity(&mut self, initial_velocity: InitialVelocity) {
+        self.initial_velocity = initial_velocity;
+    }
+
+    /* set_initial_condition
+    * Set where simulations of individuals start from, the first data point 
+    * by default. The values found are kept with each individual's system. 
+    * Note that this only affects individuals created after the call.
+    */
+    pub fn set_initial_condition(&mut self, 
+        initial_condition: InitialCondition) {
+        self.initial_condition = initial_condition;
+    }
+
+    /* evaluation
+    * Get how new individuals are tested against the data.
+    */
+    pub fn evaluation(&self) -> Evaluation {
+        return self.evaluation;
+    }
+
+    /* set_evaluation
+    * Set how new individuals are tested against the data, by simulation by
+    * default. Note that this only affects individuals created after the 
+    * call.
+    */
+    pub fn set_evaluation(&mut self, evaluation: Evaluation) {
+        self.evaluation = evaluation;
+    }
+
+    /* set_differentiation
+    * Set how derivatives of the data are estimated for gradient matching, 
+    * by finite differences by default. Note that this only affects 
+    * individuals created after the call.
+    */
+    pub fn set_differentiation(&mut self, differentiation: Differentiation) {
+        self.differentiation = differentiation;
+    }
+
+    /* selection
+    * Get how the parents of each generation are chosen.
+    */
+    pub fn selection(&self) -> &dyn Selection {
+        return self.selection.as_ref();
+    }
+
+    /* set_selection
+    * Set how the parents of each generation are chosen, by an exponentially
+    * distributed offset from the best fitness by default. Selections that 
+    * compare individuals at each data point, such as lexicase, only see 
+    * individuals created after the call.
+    */
+    pub fn set_selection(&mut self, selection: Box<dyn Selection>) {
+        self.selection = selection;
+    }
+
+    /* crossover
+    * Get how offspring are formed from their parents.
+    */
+    pub fn crossover(&self) -> Crossover {
+        return self.crossover;
+    }
+
+    /* set_crossover
+    * Set how offspring are formed from their parents, by uniformly random 
+    * subtree crossover by default.
+    */
+    pub fn set_crossover(&mut self, crossover: Crossover) {
+        self.crossover = crossover;
+    }
+
+    /* mutation
+    * Get how offspring are mutated.
+    */
+    pub fn mutation(&self) -> Mutation {
+        return self.mutation;
+    }
+
+    /* set_mutation
+    * Set the probability of each kind of mutation of offspring, by default 
+    * always shrinking a random subtree to a leaf.
+    */
+    pub fn set_mutation(&mut self, mutation: Mutation) {
+        self.mutation = mutation;
+    }
+
+    /* initializer
+    * Get how the expressions of the initial population are generated.
+    */
+    pub fn initializer(&self) -> Initializer {
+        return self.initializer;
+    }
+
+    /* set_initializer
+    * Set how the expressions of individuals grown are generated, ramped 
+    * half-and-half from depth one to four by default.
+    */
+    pub fn set_initializer(&mut self, initializer: Initializer) {
+        self.initializer = initializer;
+    }
+
+    /* limits
+    * Get the bounds on the expressions of new individuals.
+    */
+    pub fn limits(&self) -> Limits {
+        return self.limits;
+    }
+
+    /* set_limits
+    * Set the bounds on the expressions of new individuals, enforced when 
+    * growing the population and on offspring, unbounded by default.
+    */
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /* parsimony
+    * Get the fitness added per operator of each individual.
+    */
+    pub fn parsimony(&self) -> f64 {
+        return self.parsimony;
+    }
+
+    /* set_parsimony
+    * Set the fitness added per operator of each individual, zero by default.
+    * The penalty is included in the fitness of each individual, so that 
+    * smaller individuals are favoured over larger ones of similar fit. Note
+    * that this only affects individuals created after the call.
+    */
+    pub fn set_parsimony(&mut self, parsimony: f64) {
+        self.parsimony = parsimony;
+    }
+
+    /* set_seed
+    * Reseed the random number generator. Growing and evolving a population 
+    * from the same seed, data and operator map reproduces the same run.
+    */
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
     }
 
     /* grow
     * Grow the population by the specified number of individuals.
     */
     pub fn grow(&mut self, n: usize, map: &'a OperatorMap) {
+        let dimension = self.dimension();
+        let mut systems = Vec::with_capacity(n);
         for _ in 0..n {
-            let expr = Expr::generate(map);
-            let fitness = expr.fitness(&self.states, TIME_STEP);
-            
-            let individual = Individual {fitness, expr};
-            self.population.push(individual);
+            let mut system = System::new((0..dimension)
+                .map(|_| self.generate(map))
+                .collect());
+
+            // Evolved initial velocities start scattered around the estimate.
+            if self.evolves_velocities() {
+                let velocities = self.trajectories.iter()
+                    .map(|trajectory| 
+                        ode::estimate_velocities(&trajectory.states))
+                    .collect::<Vec<Vec<f64>>>()
+                    .into_iter()
+                    .map(|velocities| velocities.into_iter()
+                        .map(|velocity| ode::perturb(velocity, &mut self.rng))
+                        .collect())
+                    .collect();
+                system.set_velocities(Some(velocities));
+            }
+            systems.push(system);
         }
+
+        let individuals = self.individuals(systems);
+        self.population.extend(individuals);
+    }
+
+    /* add
+    * Add an individual with the given system (or expression), such as a 
+    * candidate model, to the population. Panics if the system does not have
+    * one expression per state variable, see try_add.
+    */
+    pub fn add<S>(&mut self, system: S)
+        where S: Into<System> {
+        if let Err(error) = self.try_add(system) {
+            panic!("{}", error);
+        }
+    }
+
+    /* try_add
+    * Add an individual with the given system (or expression) to the 
+    * population.
+    * Output:
+    *     A DimensionMismatch error if the system does not have one expression
+    *     per state variable.
+    */
+    pub fn try_add<S>(&mut self, system: S) -> Result<()>
+        where S: Into<System> {
+        let system = system.into();
+        if system.dimension() != self.dimension() {
+            return Err(Error::DimensionMismatch {
+                expected: self.dimension(),
+                found: system.dimension(),
+            });
+        }
+
+        let individuals = self.individuals(vec![system]);
+        self.population.extend(individuals);
+        return Ok(());
     }
 
     /* best_fit
     */
     pub fn best_fit(&mut self) -> &Individual {
         self.population.sort();
-        let individual = self.population.iter().next().unwrap();
-        return &individual;
+        return self.population.first().unwrap();
     }
 
     /* evolve
+    * Replace the population with the next generation. Panics if the 
+    * population is empty, see try_evolve.
+    */
+    pub fn evolve(&mut self, map: &'a OperatorMap) {
+        if let Err(error) = self.try_evolve(map) {
+            panic!("{}", error);
+        }
+    }
+
+    /* try_evolve
+    * Replace the population with the next generation.
+    * Input:
+    *     map - Operators mutations draw from, usually those grown from.
+    * Output:
+    *     An EmptyPopulation error if there are no individuals to evolve.
     */
-    pub fn evolve(&mut self) {
+    pub fn try_evolve(&mut self, map: &'a OperatorMap) -> Result<()> {
         let size = self.population.len();
 
         if size == 0 {
-            panic!("Cannot evolve population with no individuals.");
+            return Err(Error::EmptyPopulation);
         }
 
-        // Note that the population is sorted when we call best_fit.
-        let min_fitness = self.best_fit().fitness;
+        // Selections are given the population sorted best first.
+        self.population.sort();
 
         // Build new population and keep the top 10% unchagned.
         let num_unchanged = size / 10;
         let mut new_population = self.population[0..num_unchanged].to_vec();
 
-        // Initialize random number generator.
-        let mut rng = rand::thread_rng();
+        // Generate the rest of the new population by crossover of pairs of 
+        // selected parents. Offspring exceeding the limits are retried if 
+        // enabled, then the first parent is copied instead.
+        let parents = self.selection.select(&self.population, 
+            2 * (size - num_unchanged), &mut self.rng);
+        let attempts = match self.limits.overflow {
+            Overflow::Reject => 1,
+            Overflow::Retry(retries) => 1 + retries,
+        };
+        let (crossover, mutation) = (self.crossover, self.mutation);
+        let (limits, rng) = (self.limits, &mut self.rng);
+        let mut systems = Vec::with_capacity(size - num_unchanged);
+        for pair in parents.chunks(2) {
+            let system1 = &self.population[pair[0]].system;
+            let system2 = &self.population[pair[1]].system;
+
+            let offspring = (0..attempts)
+                .map(|_| system1.crossover(system2, &crossover, rng)
+                    .mutate(map, &mutation, rng))
+                .find(|system| system.exprs().iter()
+                    .all(|expr| limits.allows(expr)));
+            match offspring {
+                Some(system) => systems.push(system),
+                None => new_population.push(self.population[pair[0]].clone()),
+            }
+        }
+
+        // Test how well the new systems fit the data.
+        new_population.extend(self.individuals(systems));
+
+        self.population = new_population;
+        self.generation += 1;
+        return Ok(());
+    }
+
+    /* individuals
+    * Create individuals from systems of the population's order, simplifying
+    * them if enabled and testing how well they fit the data (see 
+    * Evaluation).
+    */
+    fn individuals(&self, systems: Vec<System>) -> Vec<Individual> {
+        let mut systems: Vec<System> = systems.into_iter()
+            .map(|system| self.prepare(system))
+            .collect();
+
+        let gradient_fitnesses = || {
+            let targets: Vec<Vec<Target>> = self.trajectories.iter()
+                .map(|trajectory| gradient::targets(&trajectory.states, 
+                    self.order, self.differentiation))
+                .collect();
+            return systems.iter()
+                .map(|system| self.score(&targets.iter()
+                    .map(|targets| system.gradient_samples(targets))
+                    .collect::<Vec<Vec<Sample>>>()))
+                .collect::<Vec<(f64, Vec<f64>)>>();
+        };
+
+        let scores = match self.evaluation {
+            Evaluation::Integrate => systems.iter_mut()
+                .map(|system| self.integrated_fitness(system))
+                .collect(),
+            Evaluation::Gradient => gradient_fitnesses(),
+            Evaluation::Screen(fraction) => {
+                // Rank by gradient matching, diverged expressions last.
+                let screens = gradient_fitnesses();
+                let score = |idx: &usize| match screens[*idx].0.is_nan() {
+                    true => f64::INFINITY,
+                    false => screens[*idx].0,
+                };
+                let mut ranks: Vec<usize> = (0..systems.len()).collect();
+                ranks.sort_by(|a, b| score(a).total_cmp(&score(b)));
+
+                let kept = (fraction * systems.len() as f64).ceil() as usize;
+                let mut scores = vec![(f64::INFINITY, Vec::new()); 
+                    systems.len()];
+                for idx in ranks.into_iter().take(kept) {
+                    scores[idx] = self.integrated_fitness(&mut systems[idx]);
+                }
+                scores
+            },
+        };
+
+        return systems.into_iter().zip(scores)
+            .map(|(system, (fitness, cases))| {
+                let fitness = fitness + self.parsimony * system.size() as f64;
+                Individual {fitness, system, cases}
+            })
+            .collect();
+    }
 
-        // We will use the Pareto distribution due to its heavier tails than 
-        // alternatives (like the exponential distribution).
-        let lambda = 0.1;
-        let exp_distr = Exp::new(lambda).unwrap();
-        let mut get_rand = || rng.sample(exp_distr) + min_fitness;
+    /* generate
+    * Generate a random expression within the limits, or a random state 
+    * variable if none is found.
+    */
+    fn generate(&mut self, map: &'a OperatorMap) -> Expr {
+        for _ in 0..GENERATE_ATTEMPTS {
+            let expr = self.initializer.generate(map, &mut self.rng);
+            if self.limits.allows(&expr) {
+                return expr;
+            }
+        }
+        let leaf: Operator = *map.rand_variable(&mut self.rng);
+        return Expr::from_operators(vec![leaf]).unwrap();
+    }
 
-        // Generate the rest of the new population by crossover.
-        for _ in 0..(size - num_unchanged) {
-            // Get two individuals, randomly chosen proportionally to their 
-            // fitness, and crossover.
-            let expr1 = &self.closest(get_rand()).expr;
-            let expr2 = &self.closest(get_rand()).expr;
+    /* score
+    * Combine the samples of a system against each trajectory into its 
+    * fitness, along with its error at each data point if the selection uses
+    * them.
+    */
+    fn score(&self, samples: &[Vec<Sample>]) -> (f64, Vec<f64>) {
+        let fitness = self.aggregate.combine(&samples.iter()
+            .map(|samples| self.metric.fitness(samples))
+            .collect::<Vec<f64>>());
+        if !self.selection.uses_cases() {
+            return (fitness, Vec::new());
+        }
 
-            let expr = expr1.crossover(expr2).mutate();
+        let cases = samples.iter().zip(self.trajectories.iter())
+            .flat_map(|(samples, trajectory)| 
+                selection::cases(samples, &trajectory.states))
+            .collect();
+        return (fitness, cases);
+    }
 
-            // Test how well the new expression fits the data.
-            let fitness = expr.fitness(&self.states, TIME_STEP);
+    /* prepare
+    * Give a system the population's order, simplifying it if enabled and 
+    * filling in any evolved initial velocities it lacks.
+    */
+    fn prepare(&self, system: System) -> System {
+        let mut system = system;
+        if let Some(simplifier) = &self.simplifier {
+            for expr in system.exprs_mut().iter_mut() {
+                *expr = simplifier.simplify(expr);
+            }
+        }
+        system.set_order(self.order);
 
-            let individual = Individual {fitness, expr};
-            new_population.push(individual);
+        // Systems given without initial velocities start from the estimate.
+        if self.evolves_velocities() && system.velocities().is_none() {
+            system.set_velocities(Some(self.trajectories.iter()
+                .map(|trajectory| ode::estimate_velocities(&trajectory.states))
+                .collect()));
         }
+        return system;
+    }
 
-        self.population = new_population;
-        self.generation += 1;
+    /* integrated_fitness
+    * Test how well a system fits the data by simulating it from the 
+    * population's initial condition, which is kept with the system. Gives
+    * the fitness and errors at each data point, see score.
+    */
+    fn integrated_fitness(&self, system: &mut System) -> (f64, Vec<f64>) {
+        match self.initial_condition {
+            InitialCondition::First => (),
+            InitialCondition::Smoothed(points) => 
+                system.set_initial_values(Some(self.trajectories.iter()
+                    .map(|trajectory| ode::estimate_initial_values(
+                        &trajectory.states, points))
+                    .collect())),
+            InitialCondition::Optimize => self.optimize_initial_values(system),
+        }
+
+        return self.score(&(0..self.trajectories.len())
+            .map(|trajectory| system.samples(
+                &self.trajectories[trajectory].states, trajectory, self.step, 
+                self.integrator(), self.sampling))
+            .collect::<Vec<Vec<Sample>>>());
+    }
+
+    /* fitness
+    * Test how well a system fits the data of one trajectory.
+    */
+    fn fitness(&self, system: &System, trajectory: usize) -> f64 {
+        return system.fitness(&self.trajectories[trajectory].states, 
+            trajectory, self.step, self.integrator(), self.metric(), 
+            self.sampling);
     }
 
-    /* closest
-    * Find the individual with a fitness closest to the given value.
+    /* optimize_initial_values
+    * Set the initial values of a system to those fitting the data best, 
+    * found for each trajectory by a golden section search on each state 
+    * variable in turn over the range of its data centred on the first data
+    * point. Note that the trajectories are independent given the 
+    * expressions, so are optimized separately.
     */
-    fn closest(&self, num: f64) -> &Individual {
-        let mut iter = self.population.iter();
+    fn optimize_initial_values(&self, system: &mut System) {
+        // Diverged simulations compare as the worst fit.
+        let score = |fitness: f64| match fitness.is_nan() {
+            true => f64::INFINITY,
+            false => fitness,
+        };
+
+        let mut initial_values: Vec<Vec<f64>> = self.trajectories.iter()
+            .map(|trajectory| trajectory.states[0].values.clone())
+            .collect();
+
+        for (trajectory, data) in self.trajectories.iter().enumerate() {
+            system.set_initial_values(Some(initial_values.clone()));
+            let mut best = score(self.fitness(system, trajectory));
+
+            for idx in 0..data.states[0].dimension() {
+                let (min, max) = data.states.iter()
+                    .map(|state| state.values[idx])
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), 
+                        |(min, max), value| (min.min(value), max.max(value)));
+                let centre = initial_values[trajectory][idx];
+                let radius = 
+                    ((max - min) / 2.0).max(1e-3 * (1.0 + centre.abs()));
+
+                let mut objective = |value: f64| {
+                    let mut trial = initial_values.clone();
+                    trial[trajectory][idx] = value;
+                    system.set_initial_values(Some(trial));
+                    return score(self.fitness(system, trajectory));
+                };
+                let value = line_search(&mut objective, centre - radius, 
+                    centre + radius);
+
+                // Keep the starting value unless the search improved on it.
+                let fitness = objective(value);
+                if fitness < best {
+                    best = fitness;
+                    initial_values[trajectory][idx] = value;
+                }
+            }
+        }
+
+        system.set_initial_values(Some(initial_values));
+    }
+
+    /* evolves_velocities
+    * Whether initial velocities are evolved as part of each individual.
+    */
+    fn evolves_velocities(&self) -> bool {
+        return self.order == Order::Second 
+            && self.initial_velocity == InitialVelocity::Evolve;
+    }
+}
+
+/* line_search
+* Find the minimum of a unimodal function over an interval by golden section
+* search.
+* Input:
+*     f - The function to minimize.
+*     lo, hi - Bounds of the interval.
+* Output:
+*     The point of the final interval with the lowest value found.
+*/
+fn line_search<F>(f: &mut F, lo: f64, hi: f64) -> f64
+    where F: FnMut(f64) -> f64 {
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let (mut lo, mut hi) = (lo, hi);
+    let mut x1 = hi - ratio * (hi - lo);
+    let mut x2 = lo + ratio * (hi - lo);
+    let mut f1 = f(x1);
+    let mut f2 = f(x2);
+
+    for _ in 0..LINE_SEARCH_ITERATIONS {
+        if f1 <= f2 {
+            hi = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = hi - ratio * (hi - lo);
+            f1 = f(x1);
+        } else {
+            lo = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = lo + ratio * (hi - lo);
+            f2 = f(x2);
+        }
+    }
+
+    return match f1 <= f2 {
+        true => x1,
+        false => x2,
+    };
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::DataError;
+    use crate::operator::builtin_map;
+    use crate::preprocess::TotalVariation;
+
+    fn exponential() -> Population {
+        let times: Vec<f64> = (0..5).map(|i| i as f64 * 0.25).collect();
+        let positions = times.iter().map(|time| time.exp()).collect();
+        return Population::new(times, positions);
+    }
+
+    #[test]
+    fn try_set_step_rejects_unusable_steps() {
+        let mut population = Population::new(vec![0.0, 1.0], vec![1.0, 2.0]);
+        for step in [0.0, -0.1, f64::NAN, f64::INFINITY].iter() {
+            match population.try_set_step(*step) {
+                Err(Error::InvalidStep(_)) => (),
+                other => panic!("expected InvalidStep, got {:?}", other),
+            }
+        }
+        assert_eq!(population.step(), TIME_STEP);
+        assert!(population.try_set_step(0.5).is_ok());
+        assert_eq!(population.step(), 0.5);
+    }
+
+    // Preprocessor replacing the data with fixed states.
+    struct Replace(Vec<State>);
+
+    impl Preprocessor for Replace {
+        fn apply(&self, _: &[State]) -> Vec<State> {
+            return self.0.clone();
+        }
+    }
+
+    #[test]
+    fn preprocess_validates_the_result() {
+        let mut population = Population::new(vec![0.0, 1.0], vec![1.0, 2.0]);
+        let original = population.states().to_vec();
+
+        let invalid = [
+            Replace(Vec::new()),
+            Replace(vec![State::new(0.0, f64::NAN)]),
+            Replace(vec![State::new(1.0, 1.0), State::new(0.0, 1.0)]),
+        ];
+        for preprocessor in invalid.iter() {
+            assert!(population.preprocess(preprocessor).is_err());
+            assert_eq!(population.states(), &original[..]);
+        }
+
+        let valid = Replace(vec![State::new(0.0, 3.0)]);
+        assert!(population.preprocess(&valid).is_ok());
+        assert_eq!(population.states(), &valid.0[..]);
 
-        let mut prev = iter.next();
-        let mut next = iter.next();
+        // A builtin preprocessor producing non-finite values.
+        let mut population = Population::new(
+            vec![0.0, 1.0, 2.0, 3.0], vec![1.0, 2.0, 4.0, 8.0]);
+        let diverged = TotalVariation::new(f64::NAN);
+        assert!(matches!(population.preprocess(&diverged), 
+            Err(Error::Data(DataError::Missing {point: 1}))));
+        assert_eq!(population.states()[3], State::new(3.0, 8.0));
+    }
+
+    #[test]
+    fn limits_bound_size_and_depth() {
+        let map = builtin_map();
+        // Four operators, two levels below the root.
+        let expr = Expr::parse("ADD SIN POS TIME", &map).unwrap();
+        let limits = |depth, size| Limits {depth, size, ..Limits::new()};
 
-        while next != None {
-            if prev.unwrap().fitness <= num &&
-                next.unwrap().fitness >= num {
-                    return prev.unwrap();
-                } 
+        assert!(Limits::new().allows(&expr));
+        assert!(limits(Some(2), Some(4)).allows(&expr));
+        assert!(!limits(None, Some(3)).allows(&expr));
+        assert!(!limits(Some(1), None).allows(&expr));
+        assert!(!limits(Some(1), Some(3)).allows(&expr));
+        assert!(limits(Some(0), Some(1))
+            .allows(&Expr::parse("POS", &map).unwrap()));
+    }
 
-            prev = next;
-            next = iter.next();
+    // Number of individuals exceeding the limits after a generation of only
+    // crossover, from parents all exceeding them.
+    fn overflowing(overflow: Overflow) -> usize {
+        let map = builtin_map();
+        let limits = Limits {size: Some(1), overflow, ..Limits::new()};
+        let mut population = exponential();
+        population.set_seed(1);
+        population.set_crossover(Crossover::Subtree);
+        population.set_mutation(Mutation::none());
+        population.set_limits(limits);
+        for _ in 0..20 {
+            population.add(Expr::parse("ADD POS TIME", &map).unwrap());
         }
 
-        // If we don't find a closest individual, we return the first 
-        // individiual in our population.
-        return prev.unwrap();
+        population.evolve(&map);
+        return population.population.iter()
+            .filter(|individual| !individual.system.exprs().iter()
+                .all(|expr| limits.allows(expr)))
+            .count();
+    }
+
+    #[test]
+    fn overflowing_offspring_are_rejected_or_retried() {
+        // Offspring are within the limits only when the whole of the first
+        // parent is replaced by a leaf, so most are rejected and the first
+        // parent copied instead.
+        assert!(overflowing(Overflow::Reject) > 8);
+        // Only the two individuals kept unchanged remain with retries.
+        assert_eq!(overflowing(Overflow::Retry(50)), 2);
+    }
+
+    #[test]
+    fn parsimony_penalizes_size() {
+        let map = builtin_map();
+        let expr = Expr::parse("ADD POS TIME", &map).unwrap();
+        let mut plain = exponential();
+        let mut parsimonious = exponential();
+        parsimonious.set_parsimony(0.5);
+        plain.add(expr.clone());
+        parsimonious.add(expr);
+
+        let penalty = parsimonious.population[0].fitness
+            - plain.population[0].fitness;
+        assert!((penalty - 0.5 * 3.0).abs() < 1e-12);
+    }
+
+    // Expressions and fitnesses of the population after a few generations
+    // from the given seed.
+    fn evolved(seed: u64) -> Vec<(String, f64)> {
+        let map = builtin_map();
+        let mut population = exponential();
+        population.set_seed(seed);
+        population.grow(30, &map);
+        for _ in 0..3 {
+            population.evolve(&map);
+        }
+        population.population.sort();
+        return population.population.iter()
+            .map(|individual| (individual.system.exprs()[0].to_string(&map), 
+                individual.fitness))
+            .collect();
+    }
+
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        let run = evolved(42);
+        let rerun = evolved(42);
+        assert_eq!(run.len(), rerun.len());
+        // Compared by bits, as diverged individuals have NaN fitness.
+        for ((expr, fitness), (reexpr, refitness)) in 
+            run.iter().zip(rerun.iter()) {
+            assert_eq!(expr, reexpr);
+            assert_eq!(fitness.to_bits(), refitness.to_bits());
+        }
+        assert_ne!(run, evolved(43));
+    }
+
+    // The exact model of x' = x fit from the given initial condition to data
+    // whose first point is off by a half. Gives the fitness and initial value
+    // of the model.
+    fn initial_fit(initial_condition: InitialCondition) -> (f64, f64) {
+        let times: Vec<f64> = (0..21).map(|i| i as f64 * 0.1).collect();
+        let mut positions: Vec<f64> = 
+            times.iter().map(|time| time.exp()).collect();
+        positions[0] += 0.5;
+        let mut population = Population::new(times, positions);
+        population.set_initial_condition(initial_condition);
+        population.add(Expr::parse("POS", &OperatorMap::new()).unwrap());
+
+        let individual = &population.population[0];
+        let initial = match individual.system.initial_values() {
+            Some(values) => values[0][0],
+            None => population.states()[0].position(),
+        };
+        return (individual.fitness, initial);
+    }
+
+    #[test]
+    fn initial_conditions_smooth_the_first_point() {
+        let (first, first_initial) = initial_fit(InitialCondition::First);
+        let (smoothed, smoothed_initial) = 
+            initial_fit(InitialCondition::Smoothed(11));
+        let (optimized, optimized_initial) = 
+            initial_fit(InitialCondition::Optimize);
+
+        assert_eq!(first_initial, 1.5);
+        assert!((smoothed_initial - 1.0).abs() < 0.4);
+        assert!((optimized_initial - 1.0).abs() < 0.05);
+        assert!(optimized < smoothed && smoothed < first);
+    }
+
+    #[test]
+    fn aggregates_combine_the_fitness_of_each_trajectory() {
+        let times: Vec<f64> = (0..11).map(|i| i as f64 * 0.1).collect();
+        let trajectory = |name, position: fn(f64) -> f64| Trajectory::new(
+            name, times.iter().map(|t| State::new(*t, position(*t))).collect());
+        let trajectories = vec![
+            trajectory("exponential", f64::exp),
+            trajectory("line", |t| 1.0 + 2.0 * t),
+        ];
+        let expr = Expr::parse("POS", &OperatorMap::new()).unwrap();
+        let fitnesses: Vec<f64> = trajectories.iter()
+            .map(|trajectory| expr.fitness(&trajectory.states, TIME_STEP, 
+                &Rk4, &Area, Sampling::Steps))
+            .collect();
+        assert!(fitnesses[1] > fitnesses[0]);
+
+        let aggregated = |aggregate| {
+            let mut population = 
+                Population::try_from_trajectories(trajectories.clone())
+                .unwrap();
+            population.set_aggregate(aggregate);
+            population.add(expr.clone());
+            return population.population[0].fitness;
+        };
+        let sum = fitnesses[0] + fitnesses[1];
+        assert!((aggregated(Aggregate::Sum) - sum).abs() < 1e-12);
+        assert!((aggregated(Aggregate::Mean) - sum / 2.0).abs() < 1e-12);
+        assert_eq!(aggregated(Aggregate::Worst), fitnesses[1]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn gradient_evaluation_needs_no_simulation() {
+        // Finite differences are exact on x = t^2.
+        let times: Vec<f64> = (0..11).map(|i| i as f64 * 0.1).collect();
+        let positions = times.iter().map(|time| time * time).collect();
+        let map = builtin_map();
+        let mut population = Population::new(times, positions);
+        population.set_evaluation(Evaluation::Gradient);
+        population.add(Expr::parse("MUL 2 TIME", &map).unwrap());
+        population.add(Expr::parse("POS", &map).unwrap());
+
+        assert!(population.population[0].fitness < 1e-9);
+        assert!(population.population[1].fitness > 0.1);
+    }
+
+    #[test]
+    fn screening_simulates_the_best_gradient_matches() {
+        let map = builtin_map();
+        let mut population = exponential();
+        population.set_seed(3);
+        population.set_evaluation(Evaluation::Screen(0.25));
+        population.grow(20, &map);
+
+        // Score each by gradient matching, diverged individuals last.
+        let targets = gradient::targets(population.states(), Order::First, 
+            Differentiation::default());
+        let screen = |system: &System| 
+            match system.gradient_fitness(&targets, &Area) {
+                fitness if fitness.is_nan() => f64::INFINITY,
+                fitness => fitness,
+            };
+        let mut screens: Vec<f64> = population.population.iter()
+            .map(|individual| screen(&individual.system))
+            .collect();
+        screens.sort_by(|a, b| a.total_cmp(b));
+
+        // Those screened out are given infinite fitness without simulating.
+        let simulated: Vec<&Individual> = population.population.iter()
+            .filter(|individual| individual.fitness != f64::INFINITY)
+            .collect();
+        assert!(!simulated.is_empty() && simulated.len() <= 5);
+        for individual in simulated.iter() {
+            assert!(screen(&individual.system) <= screens[4]);
+        }
+    }
+}
@@ -0,0 +1,852 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Smoothing, differentiation and resampling of noisy data before it is fit.
+//_____________________________________________________________________________
+
+// Internal imports.
+use crate::integrate;
+use crate::ode::State;
+
+// Lagged diffusivity iterations of total variation regularized
+// differentiation, and the smoothing of the absolute value in its penalty.
+const TV_ITERATIONS: usize = 20;
+const TV_EPSILON: f64 = 1e-8;
+
+// Conjugate gradient iterations stop once the residual is this small
+// relative to the right-hand side.
+const CG_TOLERANCE: f64 = 1e-10;
+
+//_____________________________________________________________________________
+//                                                           Preprocessor Trait
+
+pub trait Preprocessor {
+    /* apply
+    * Preprocess the states of a trajectory.
+    * Input:
+    *     states - Data ordered by strictly increasing time.
+    * Output:
+    *     The preprocessed states, without velocities.
+    */
+    fn apply(&self, states: &[State]) -> Vec<State>;
+}
+
+/* column
+* Get the values of one state variable over all states.
+*/
+fn column(states: &[State], idx: usize) -> Vec<f64> {
+    return states.iter().map(|state| state.values[idx]).collect();
+}
+
+/* from_columns
+* Create states from their times and the values of each state variable over
+* all states.
+*/
+fn from_columns(times: &[f64], columns: &[Vec<f64>]) -> Vec<State> {
+    return times.iter().enumerate()
+        .map(|(idx, time)| State::from_values(*time,
+            columns.iter().map(|column| column[idx]).collect()))
+        .collect();
+}
+
+/* map_columns
+* Apply a function to the values of each state variable over all states.
+*/
+fn map_columns<F>(states: &[State], f: F) -> Vec<State>
+    where F: Fn(&[f64], &[f64]) -> Vec<f64> {
+    let times: Vec<f64> = states.iter().map(|state| state.time).collect();
+    let columns: Vec<Vec<f64>> = (0..states.first().map_or(0, State::dimension))
+        .map(|idx| f(&times, &column(states, idx)))
+        .collect();
+    return from_columns(&times, &columns);
+}
+
+//_____________________________________________________________________________
+//                                                        Builtin Preprocessors
+
+// Mean of each data point and its neighbours, the window narrowing at the
+// ends of the data so that it stays centred.
+#[derive(Copy, Clone, Debug)]
+pub struct MovingAverage {
+    pub window: usize,
+}
+
+impl MovingAverage {
+    /* new
+    * Create the filter averaging over the given number of data points.
+    */
+    pub fn new(window: usize) -> MovingAverage {
+        return MovingAverage {window};
+    }
+}
+
+impl Preprocessor for MovingAverage {
+    fn apply(&self, states: &[State]) -> Vec<State> {
+        let half = self.window / 2;
+        return map_columns(states, |_, values| (0..values.len())
+            .map(|idx| {
+                let half = half.min(idx).min(values.len() - 1 - idx);
+                let window = &values[(idx - half)..=(idx + half)];
+                window.iter().sum::<f64>() / window.len() as f64
+            })
+            .collect());
+    }
+}
+
+// Least squares polynomial through a window of data points around each,
+// which smooths noise while keeping peaks better than a moving average.
+#[derive(Copy, Clone, Debug)]
+pub struct SavitzkyGolay {
+    pub window: usize,
+    pub degree: usize,
+}
+
+impl SavitzkyGolay {
+    /* new
+    * Create the filter fitting a polynomial of the given degree through the
+    * given number of data points.
+    */
+    pub fn new(window: usize, degree: usize) -> SavitzkyGolay {
+        return SavitzkyGolay {window, degree};
+    }
+}
+
+impl Preprocessor for SavitzkyGolay {
+    fn apply(&self, states: &[State]) -> Vec<State> {
+        let times: Vec<f64> = states.iter().map(|state| state.time).collect();
+        let values = savitzky_golay(states, self.window, self.degree, 0);
+        return times.iter().zip(values)
+            .map(|(time, values)| State::from_values(*time, values))
+            .collect();
+    }
+}
+
+// Positions recovered from the derivative estimated by total variation
+// regularized differentiation, see total_variation. Suits data whose
+// derivative is piecewise smooth with jumps.
+#[derive(Copy, Clone, Debug)]
+pub struct TotalVariation {
+    // Weight of the regularization, larger for smoother derivatives.
+    pub alpha: f64,
+}
+
+impl TotalVariation {
+    /* new
+    * Create the filter with the given weight of regularization.
+    */
+    pub fn new(alpha: f64) -> TotalVariation {
+        return TotalVariation {alpha};
+    }
+}
+
+impl Preprocessor for TotalVariation {
+    fn apply(&self, states: &[State]) -> Vec<State> {
+        return map_columns(states, |times, values|
+            total_variation(times, values, self.alpha).1);
+    }
+}
+
+// Cubic smoothing spline, trading closeness to the data against the
+// integrated squared second derivative weighted by lambda. A lambda of zero
+// interpolates the data.
+#[derive(Copy, Clone, Debug)]
+pub struct SmoothingSpline {
+    pub lambda: f64,
+}
+
+impl SmoothingSpline {
+    /* new
+    * Create the filter with the given weight of smoothness.
+    */
+    pub fn new(lambda: f64) -> SmoothingSpline {
+        return SmoothingSpline {lambda};
+    }
+}
+
+impl Preprocessor for SmoothingSpline {
+    fn apply(&self, states: &[State]) -> Vec<State> {
+        return map_columns(states, |times, values|
+            spline(times, values, self.lambda).0);
+    }
+}
+
+// Evenly spaced points over the time span of the data, interpolated by a
+// natural cubic spline. Unevenly sampled data can then be used with
+// methods assuming even spacing.
+#[derive(Copy, Clone, Debug)]
+pub struct Resample {
+    pub points: usize,
+}
+
+impl Resample {
+    /* new
+    * Create the filter resampling onto the given number of points.
+    */
+    pub fn new(points: usize) -> Resample {
+        return Resample {points};
+    }
+}
+
+impl Preprocessor for Resample {
+    fn apply(&self, states: &[State]) -> Vec<State> {
+        let (first, last) = match (states.first(), states.last()) {
+            (Some(first), Some(last)) if self.points > 1 =>
+                (first.time, last.time),
+            _ => return map_columns(states, |_, values| values.to_vec()),
+        };
+
+        let times: Vec<f64> = states.iter().map(|state| state.time).collect();
+        let grid: Vec<f64> = (0..self.points)
+            .map(|idx| first
+                + (last - first) * idx as f64 / (self.points - 1) as f64)
+            .collect();
+        let columns: Vec<Vec<f64>> = (0..states[0].dimension())
+            .map(|idx| {
+                let (values, curvatures) =
+                    spline(&times, &column(states, idx), 0.0);
+                grid.iter()
+                    .map(|time| spline_at(&times, &values, &curvatures, *time))
+                    .collect()
+            })
+            .collect();
+        return from_columns(&grid, &columns);
+    }
+}
+
+// Stages applied one after another.
+pub struct Pipeline {
+    stages: Vec<Box<dyn Preprocessor>>,
+}
+
+impl Pipeline {
+    /* new
+    * Create an empty pipeline, which leaves data unchanged.
+    */
+    pub fn new() -> Pipeline {
+        return Pipeline {stages: Vec::new()};
+    }
+
+    /* push
+    * Add a stage to the end of the pipeline.
+    */
+    pub fn push(&mut self, stage: Box<dyn Preprocessor>) {
+        self.stages.push(stage);
+    }
+
+    /* is_empty
+    * Whether the pipeline has no stages.
+    */
+    pub fn is_empty(&self) -> bool {
+        return self.stages.is_empty();
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Pipeline {
+        return Pipeline::new();
+    }
+}
+
+impl Preprocessor for Pipeline {
+    fn apply(&self, states: &[State]) -> Vec<State> {
+        let mut states = map_columns(states, |_, values| values.to_vec());
+        for stage in self.stages.iter() {
+            states = stage.apply(&states);
+        }
+        return states;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                          Differentiation Type
+
+// How derivatives of the data are estimated.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Differentiation {
+    // Quadratic through each data point and its neighbours, the three point
+    // finite difference for unevenly spaced data.
+    FiniteDifference,
+    // Least squares polynomial of the given degree through a window of data
+    // points around each, smoothing noise that finite differences amplify.
+    SavitzkyGolay {window: usize, degree: usize},
+    // Total variation regularized differentiation with the given weight of
+    // regularization, see total_variation.
+    TotalVariation {alpha: f64},
+}
+
+impl Default for Differentiation {
+    fn default() -> Differentiation {
+        return Differentiation::FiniteDifference;
+    }
+}
+
+impl Differentiation {
+    /* differentiate
+    * Estimate a derivative of each state variable at every data point.
+    * Input:
+    *     states - Data ordered by time.
+    *     derivative - Order of the derivative, zero for the smoothed values.
+    * Output:
+    *     The derivative of each state variable at each data point.
+    */
+    pub fn differentiate(&self, states: &[State], derivative: usize)
+        -> Vec<Vec<f64>> {
+        return match *self {
+            Differentiation::FiniteDifference =>
+                savitzky_golay(states, 3, 2, derivative),
+            Differentiation::SavitzkyGolay {window, degree} =>
+                savitzky_golay(states, window, degree, derivative),
+            Differentiation::TotalVariation {alpha} => {
+                // Higher derivatives difference the regularized one.
+                let times: Vec<f64> =
+                    states.iter().map(|state| state.time).collect();
+                let columns: Vec<Vec<f64>> = (0..states[0].dimension())
+                    .map(|idx| {
+                        let values = column(states, idx);
+                        let (slopes, values) =
+                            total_variation(&times, &values, alpha);
+                        match derivative {
+                            0 => values,
+                            _ => slopes,
+                        }
+                    })
+                    .collect();
+                let states = from_columns(&times, &columns);
+                match derivative {
+                    0 | 1 => states.into_iter()
+                        .map(|state| state.values)
+                        .collect(),
+                    _ => savitzky_golay(&states, 3, 2, derivative - 1),
+                }
+            },
+        };
+    }
+}
+
+/* savitzky_golay
+* Estimate a derivative of each state variable at every data point from a
+* least squares polynomial through the window of data points centred on it,
+* shifted inwards at the ends of the data. Unlike the classic filter the
+* data need not be evenly spaced.
+* Input:
+*     states - Data ordered by time.
+*     window - Number of data points fit around each.
+*     degree - Degree of the polynomial, lowered where the window holds too
+*         few points.
+*     derivative - Order of the derivative, zero for the smoothed values.
+* Output:
+*     The derivative of each state variable at each data point, zero where
+*     the polynomial is of lower degree than the derivative.
+*/
+pub fn savitzky_golay(
+    states: &[State],
+    window: usize,
+    degree: usize,
+    derivative: usize) -> Vec<Vec<f64>> {
+    let window = window.clamp(1, states.len().max(1));
+    let degree = degree.min(window - 1);
+
+    return (0..states.len())
+        .map(|idx| {
+            let start = idx.saturating_sub(window / 2)
+                .min(states.len() - window);
+            local_derivative(&states[start..(start + window)],
+                &states[idx], degree, derivative)
+        })
+        .collect();
+}
+
+/* local_derivative
+* Fit a polynomial to some data points by least squares and differentiate it
+* at the given point.
+*/
+fn local_derivative(
+    states: &[State],
+    centre: &State,
+    degree: usize,
+    derivative: usize) -> Vec<f64> {
+    let dimension = centre.dimension();
+    if derivative > degree {
+        return vec![0.0; dimension];
+    }
+
+    // Time is centred on the point and scaled by the span of the window so
+    // that the normal equations are well conditioned.
+    let scale = match states.last().unwrap().time - states[0].time {
+        span if span > 0.0 => span,
+        _ => 1.0,
+    };
+    let powers = |state: &State| (0..=degree)
+        .map(|power| ((state.time - centre.time) / scale).powi(power as i32))
+        .collect::<Vec<f64>>();
+
+    let mut matrix = vec![vec![0.0; degree + 1]; degree + 1];
+    for state in states.iter() {
+        let powers = powers(state);
+        for (row, a) in matrix.iter_mut().zip(powers.iter()) {
+            for (entry, b) in row.iter_mut().zip(powers.iter()) {
+                *entry += a * b;
+            }
+        }
+    }
+
+    // The derivative at the point is the coefficient of its power times its
+    // factorial, undoing the scaling of time.
+    let factorial = (1..=derivative).product::<usize>() as f64;
+    return (0..dimension)
+        .map(|idx| {
+            let mut rhs = vec![0.0; degree + 1];
+            for state in states.iter() {
+                for (entry, power) in rhs.iter_mut().zip(powers(state)) {
+                    *entry += power * state.values[idx];
+                }
+            }
+            match integrate::solve(matrix.clone(), rhs) {
+                Some(coefficients) => coefficients[derivative] * factorial
+                    / scale.powi(derivative as i32),
+                None => f64::NAN,
+            }
+        })
+        .collect();
+}
+
+/* total_variation
+* Differentiate data by total variation regularization (Chartrand, 2011):
+* the derivative u minimizes alpha * TV(u) + |A u - (x - x0)|^2 / 2, where A
+* integrates by the trapezoidal rule, by lagged diffusivity iterations each
+* solved by conjugate gradients.
+* Input:
+*     times - Strictly increasing times of the data.
+*     values - Data of one state variable.
+*     alpha - Weight of the regularization, larger for smoother derivatives.
+* Output:
+*     The derivative at each data point, and the values given by integrating
+*     it from the constant fitting the data best.
+*/
+pub fn total_variation(times: &[f64], values: &[f64], alpha: f64)
+    -> (Vec<f64>, Vec<f64>) {
+    let n = values.len();
+    let states = from_columns(times, &[values.to_vec()]);
+    let mut slopes: Vec<f64> = savitzky_golay(&states, 3, 2, 1).into_iter()
+        .map(|slope| slope[0])
+        .collect();
+    if n < 3 {
+        return (slopes, values.to_vec());
+    }
+
+    let steps: Vec<f64> = times.windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect();
+    let integrate = |u: &[f64]| {
+        let mut total = 0.0;
+        let mut integral = vec![0.0; n];
+        for idx in 1..n {
+            total += steps[idx - 1] * (u[idx - 1] + u[idx]) / 2.0;
+            integral[idx] = total;
+        }
+        return integral;
+    };
+    // Adjoint of integrate, through suffix sums of r.
+    let integrate_adjoint = |r: &[f64]| {
+        let mut suffix = vec![0.0; n + 1];
+        for idx in (0..n).rev() {
+            suffix[idx] = suffix[idx + 1] + r[idx];
+        }
+        return (0..n)
+            .map(|idx| {
+                let before = match idx {
+                    0 => 0.0,
+                    _ => steps[idx - 1] / 2.0 * suffix[idx],
+                };
+                let after = match idx + 1 < n {
+                    true => steps[idx] / 2.0 * suffix[idx + 1],
+                    false => 0.0,
+                };
+                before + after
+            })
+            .collect::<Vec<f64>>();
+    };
+
+    let offsets: Vec<f64> = values.iter()
+        .map(|value| value - values[0])
+        .collect();
+    let rhs = integrate_adjoint(&offsets);
+
+    for _ in 0..TV_ITERATIONS {
+        // Weights of the differences making the penalty quadratic about the
+        // current derivative.
+        let weights: Vec<f64> = slopes.windows(2)
+            .map(|pair| 1.0 / ((pair[1] - pair[0]).powi(2) + TV_EPSILON).sqrt())
+            .collect();
+        let operator = |u: &[f64]| {
+            let mut result = integrate_adjoint(&integrate(u));
+            for (idx, weight) in weights.iter().enumerate() {
+                let flux = alpha * weight * (u[idx + 1] - u[idx]);
+                result[idx] -= flux;
+                result[idx + 1] += flux;
+            }
+            return result;
+        };
+        slopes = conjugate_gradient(operator, &rhs, slopes, n);
+    }
+
+    // Integrate from the constant minimizing the squared error.
+    let integral = integrate(&slopes);
+    let offset = values.iter().zip(integral.iter())
+        .map(|(value, integral)| value - integral)
+        .sum::<f64>() / n as f64;
+    return (slopes, integral.iter().map(|value| value + offset).collect());
+}
+
+/* conjugate_gradient
+* Solve a symmetric positive definite system given by its product with a
+* vector.
+*/
+fn conjugate_gradient<F>(operator: F, b: &[f64], x: Vec<f64>, iterations: usize)
+    -> Vec<f64>
+    where F: Fn(&[f64]) -> Vec<f64> {
+    let dot = |u: &[f64], v: &[f64]|
+        u.iter().zip(v.iter()).map(|(a, b)| a * b).sum::<f64>();
+    let tolerance = CG_TOLERANCE * CG_TOLERANCE * dot(b, b);
+
+    let mut x = x;
+    let mut r: Vec<f64> = b.iter().zip(operator(&x))
+        .map(|(b, ax)| b - ax)
+        .collect();
+    let mut p = r.clone();
+    let mut rr = dot(&r, &r);
+
+    for _ in 0..iterations {
+        if rr <= tolerance {
+            break;
+        }
+        let ap = operator(&p);
+        let step = rr / dot(&p, &ap);
+        for ((x, r), (p, ap)) in x.iter_mut().zip(r.iter_mut())
+            .zip(p.iter().zip(ap.iter())) {
+            *x += step * p;
+            *r -= step * ap;
+        }
+        let next = dot(&r, &r);
+        for (p, r) in p.iter_mut().zip(r.iter()) {
+            *p = r + next / rr * *p;
+        }
+        rr = next;
+    }
+    return x;
+}
+
+/* spline
+* Fit a natural cubic smoothing spline by the Reinsch algorithm.
+* Input:
+*     times - Strictly increasing times of the data.
+*     values - Data of one state variable.
+*     lambda - Weight of the integrated squared second derivative.
+* Output:
+*     The values of the spline at the data times and its second derivatives
+*     there, see spline_at.
+*/
+fn spline(times: &[f64], values: &[f64], lambda: f64) -> (Vec<f64>, Vec<f64>) {
+    let n = values.len();
+    let steps: Vec<f64> = times.windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect();
+    if n < 3 || steps.iter()
+        .any(|step| step.is_nan() || *step <= 0.0) {
+        return (values.to_vec(), vec![0.0; n]);
+    }
+
+    // Column m of Q is non-zero in rows m, m + 1 and m + 2.
+    let m = n - 2;
+    let q: Vec<[f64; 3]> = (0..m)
+        .map(|col| [
+            1.0 / steps[col],
+            -1.0 / steps[col] - 1.0 / steps[col + 1],
+            1.0 / steps[col + 1],
+        ])
+        .collect();
+
+    // Band of R + lambda Q^T Q, with entry k of a row at column row + k - 2.
+    let mut band = vec![[0.0; 5]; m];
+    for row in 0..m {
+        band[row][2] = (steps[row] + steps[row + 1]) / 3.0
+            + lambda * q[row].iter().map(|q| q * q).sum::<f64>();
+        if row + 1 < m {
+            let upper = steps[row + 1] / 6.0 + lambda
+                * (q[row][1] * q[row + 1][0] + q[row][2] * q[row + 1][1]);
+            band[row][3] = upper;
+            band[row + 1][1] = upper;
+        }
+        if row + 2 < m {
+            let upper = lambda * q[row][2] * q[row + 2][0];
+            band[row][4] = upper;
+            band[row + 2][0] = upper;
+        }
+    }
+    let rhs: Vec<f64> = (0..m)
+        .map(|col| (0..3).map(|k| q[col][k] * values[col + k]).sum())
+        .collect();
+    let gamma = solve_banded(band, rhs);
+
+    let mut smoothed = values.to_vec();
+    for (col, gamma) in gamma.iter().enumerate() {
+        for k in 0..3 {
+            smoothed[col + k] -= lambda * q[col][k] * gamma;
+        }
+    }
+
+    let mut curvatures = vec![0.0; n];
+    curvatures[1..(n - 1)].copy_from_slice(&gamma);
+    return (smoothed, curvatures);
+}
+
+/* spline_at
+* Evaluate a natural cubic spline given its values and second derivatives at
+* the knots, holding it constant outside them.
+*/
+fn spline_at(times: &[f64], values: &[f64], curvatures: &[f64], time: f64)
+    -> f64 {
+    let last = times.len() - 1;
+    if time <= times[0] || last == 0 {
+        return values[0];
+    } else if time >= times[last] {
+        return values[last];
+    }
+
+    let idx = times.partition_point(|t| *t <= time) - 1;
+    let h = times[idx + 1] - times[idx];
+    let (a, b) = (time - times[idx], times[idx + 1] - time);
+    return (a * values[idx + 1] + b * values[idx]) / h
+        - a * b / 6.0 * ((1.0 + a / h) * curvatures[idx + 1]
+            + (1.0 + b / h) * curvatures[idx]);
+}
+
+/* solve_banded
+* Solve a symmetric positive definite pentadiagonal system by Gaussian
+* elimination, with entry k of each row of the band at column row + k - 2.
+*/
+fn solve_banded(band: Vec<[f64; 5]>, rhs: Vec<f64>) -> Vec<f64> {
+    let n = rhs.len();
+    let (mut band, mut rhs) = (band, rhs);
+
+    for col in 0..n {
+        for row in (col + 1)..(col + 3).min(n) {
+            let factor = band[row][col + 2 - row] / band[col][2];
+            for k in 0..3 {
+                if col + k < n && col + k + 2 >= row {
+                    band[row][col + k + 2 - row] -= factor * band[col][k + 2];
+                }
+            }
+            rhs[row] -= factor * rhs[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let upper = (1..3)
+            .filter(|k| row + k < n)
+            .map(|k| band[row][k + 2] * x[row + k])
+            .sum::<f64>();
+        x[row] = (rhs[row] - upper) / band[row][2];
+    }
+    return x;
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f64 = 1e-9;
+
+    // Unevenly spaced times over [0, 2].
+    const UNEVEN: [f64; 7] = [0.0, 0.1, 0.35, 0.5, 1.1, 1.6, 2.0];
+
+    fn sample<F>(times: &[f64], f: F) -> Vec<State>
+        where F: Fn(f64) -> f64 {
+        return times.iter().map(|t| State::new(*t, f(*t))).collect();
+    }
+
+    fn even(n: usize, span: f64) -> Vec<f64> {
+        return (0..n).map(|i| span * i as f64 / (n - 1) as f64).collect();
+    }
+
+    fn assert_close(actual: &[State], expected: &[State], tolerance: f64) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert_eq!(actual.time, expected.time);
+            assert!((actual.position() - expected.position()).abs() 
+                < tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn moving_average_keeps_constants_and_lines() {
+        let constant = sample(&even(9, 1.0), |_| 3.0);
+        let line = sample(&even(9, 1.0), |t| 2.0 * t - 1.0);
+        for window in [1, 3, 5, 100].iter() {
+            let filter = MovingAverage::new(*window);
+            assert_close(&filter.apply(&constant), &constant, TOLERANCE);
+            assert_close(&filter.apply(&line), &line, TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn moving_average_narrows_at_the_ends() {
+        let states = sample(&[0.0, 1.0, 2.0, 3.0], |t| t * t);
+        let smoothed = MovingAverage::new(3).apply(&states);
+        let expected = sample(&[0.0, 1.0, 2.0, 3.0], |t| match t as usize {
+            1 => 5.0 / 3.0,
+            2 => 14.0 / 3.0,
+            _ => t * t,
+        });
+        assert_close(&smoothed, &expected, TOLERANCE);
+    }
+
+    #[test]
+    fn savitzky_golay_is_exact_for_quadratics() {
+        let quadratic = |t: f64| 3.0 * t * t - 2.0 * t + 1.0;
+        for times in [even(11, 2.0), UNEVEN.to_vec()].iter() {
+            let states = sample(times, quadratic);
+            for window in [3, 5, 100].iter() {
+                let values = savitzky_golay(&states, *window, 2, 0);
+                let slopes = savitzky_golay(&states, *window, 2, 1);
+                let curvatures = savitzky_golay(&states, *window, 2, 2);
+                let cubic = savitzky_golay(&states, *window, 2, 3);
+                for (idx, state) in states.iter().enumerate() {
+                    let t = state.time;
+                    assert!((values[idx][0] - quadratic(t)).abs() < TOLERANCE);
+                    assert!((slopes[idx][0] - (6.0 * t - 2.0)).abs() < 1e-8);
+                    assert!((curvatures[idx][0] - 6.0).abs() < 1e-7);
+                    assert_eq!(cubic[idx][0], 0.0);
+                }
+            }
+
+            let smoothed = SavitzkyGolay::new(5, 2).apply(&states);
+            assert_close(&smoothed, &states, TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn savitzky_golay_lowers_the_degree_for_few_points() {
+        // A line through two points, as a quadratic needs three.
+        let states = sample(&[1.0, 1.5], |t| 4.0 * t);
+        let slopes = savitzky_golay(&states, 5, 2, 1);
+        assert!((slopes[0][0] - 4.0).abs() < TOLERANCE);
+        assert!((slopes[1][0] - 4.0).abs() < TOLERANCE);
+        assert_eq!(savitzky_golay(&states, 5, 2, 2), vec![vec![0.0]; 2]);
+    }
+
+    #[test]
+    fn finite_difference_is_exact_for_quadratics() {
+        let states = sample(&UNEVEN, |t| t * t);
+        let slopes = Differentiation::FiniteDifference
+            .differentiate(&states, 1);
+        for (state, slope) in states.iter().zip(slopes.iter()) {
+            assert!((slope[0] - 2.0 * state.time).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn total_variation_recovers_constant_slopes() {
+        for times in [even(21, 2.0), UNEVEN.to_vec()].iter() {
+            let states = sample(times, |t| 1.5 * t + 0.5);
+            let values: Vec<f64> = column(&states, 0);
+            let (slopes, integrated) = total_variation(times, &values, 0.1);
+            for idx in 0..times.len() {
+                assert!((slopes[idx] - 1.5).abs() < 1e-3);
+                assert!((integrated[idx] - values[idx]).abs() < 1e-3);
+            }
+
+            let smoothed = TotalVariation::new(0.1).apply(&states);
+            assert_close(&smoothed, &states, 1e-3);
+        }
+    }
+
+    #[test]
+    fn total_variation_keeps_fewer_than_three_points() {
+        let (slopes, values) = total_variation(&[0.0, 2.0], &[1.0, 5.0], 0.1);
+        assert!((slopes[0] - 2.0).abs() < TOLERANCE);
+        assert_eq!(values, vec![1.0, 5.0]);
+    }
+
+    #[test]
+    fn spline_interpolates_without_smoothing() {
+        let cubic = |t: f64| t * t * t - t;
+        let states = sample(&UNEVEN, cubic);
+        let smoothed = SmoothingSpline::new(0.0).apply(&states);
+        assert_close(&smoothed, &states, TOLERANCE);
+    }
+
+    #[test]
+    fn spline_keeps_lines_at_any_smoothing() {
+        // Lines have no curvature to penalize.
+        let states = sample(&UNEVEN, |t| 0.5 - 3.0 * t);
+        for lambda in [0.0, 1.0, 1e6].iter() {
+            let smoothed = SmoothingSpline::new(*lambda).apply(&states);
+            assert_close(&smoothed, &states, 1e-8);
+        }
+    }
+
+    #[test]
+    fn spline_tends_to_the_least_squares_line() {
+        let states = sample(&[0.0, 1.0, 2.0, 3.0, 4.0], |t| t * t);
+        let smoothed = SmoothingSpline::new(1e9).apply(&states);
+        // Least squares line of t^2 over the times is 4t - 2.
+        let line = sample(&[0.0, 1.0, 2.0, 3.0, 4.0], |t| 4.0 * t - 2.0);
+        assert_close(&smoothed, &line, 1e-4);
+    }
+
+    #[test]
+    fn spline_keeps_fewer_than_three_points() {
+        let states = sample(&[0.0, 1.0], |t| t + 1.0);
+        assert_eq!(SmoothingSpline::new(1.0).apply(&states), states);
+    }
+
+    #[test]
+    fn resample_keeps_endpoints() {
+        let states = sample(&UNEVEN, |t| (3.0 * t).sin());
+        let resampled = Resample::new(5).apply(&states);
+        assert_eq!(resampled.len(), 5);
+        assert_eq!(resampled[0], states[0]);
+        assert_eq!(resampled[4], states[UNEVEN.len() - 1]);
+        let times: Vec<f64> = resampled.iter().map(|s| s.time).collect();
+        assert_eq!(times, vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+    }
+
+    #[test]
+    fn resample_reproduces_lines_and_approximates_smooth_data() {
+        let line = |t: f64| 2.0 * t + 1.0;
+        let resampled = Resample::new(9).apply(&sample(&UNEVEN, line));
+        assert_close(&resampled, &sample(&even(9, 2.0), line), 1e-12);
+
+        let smooth = |t: f64| t.sin();
+        let resampled = Resample::new(9).apply(&sample(&even(21, 2.0), smooth));
+        assert_close(&resampled, &sample(&even(9, 2.0), smooth), 1e-3);
+
+        // Two points are joined by a line.
+        let resampled = Resample::new(3).apply(&sample(&[0.0, 2.0], line));
+        assert_close(&resampled, &sample(&[0.0, 1.0, 2.0], line), 1e-12);
+    }
+
+    #[test]
+    fn resample_to_fewer_than_two_points_keeps_the_data() {
+        let states = sample(&UNEVEN, |t| t);
+        assert_eq!(Resample::new(1).apply(&states), states);
+        assert_eq!(Resample::new(0).apply(&states), states);
+    }
+
+    #[test]
+    fn pipeline_applies_stages_in_order() {
+        let states = sample(&UNEVEN, |t| t * t);
+        assert_eq!(Pipeline::new().apply(&states), states);
+
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Box::new(Resample::new(5)));
+        pipeline.push(Box::new(MovingAverage::new(3)));
+        let expected = MovingAverage::new(3)
+            .apply(&Resample::new(5).apply(&states));
+        assert_eq!(pipeline.apply(&states), expected);
+    }
+}
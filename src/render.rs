@@ -0,0 +1,589 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Rendering of expressions as infix notation, LaTeX and MathML.
+//_____________________________________________________________________________
+
+// External imports.
+use std::collections::HashMap;
+
+// Internal imports.
+use crate::error::{Error, Result};
+use crate::ode::{Expr, Order};
+use crate::operator::{Operator, OperatorMap};
+use crate::tree::Node;
+
+// Precedence of fragments that never need parentheses.
+const ATOM: u8 = u8::MAX;
+// Precedence given to negative numbers, so that they are parenthesized as
+// the base of a power but not as a factor. Note that they are also 
+// parenthesized on the right of infix operators, e.g. "x - (-3)".
+const NEGATIVE: u8 = 2;
+
+//_____________________________________________________________________________
+//                                                        Target & Format Types
+
+// Notations an expression can be rendered in.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Target {
+    Infix,
+    Latex,
+    MathMl,
+}
+
+// How an operator is rendered. Operators without a format are rendered by
+// their token, called as functions if they take arguments.
+#[derive(Copy, Clone)]
+pub enum Format {
+    // A variable or named constant, given for each target. The MathML form
+    // is the content of an <mi> element.
+    Symbol {infix: &'static str, latex: &'static str, mathml: &'static str},
+    // A named constant rendered by its value.
+    Value,
+    // A binary operator written between its arguments. Operators of higher
+    // precedence bind tighter.
+    Infix {symbol: &'static str, precedence: u8, associative: bool},
+    // A binary operator written as a fraction.
+    Fraction,
+    // A binary operator raising its first argument to its second.
+    Power,
+    // A unary operator raising its argument to a fixed exponent.
+    Exponent(&'static str),
+    // A unary operator taking the square root of its argument.
+    Root,
+    // A unary operator raising e to its argument.
+    Exponential,
+    // A named function, e.g. sin.
+    Function(&'static str),
+    // A hook given the target and the rendered (and where needed
+    // parenthesized) arguments. Its output is never parenthesized.
+    Custom(fn(Target, &[String]) -> String),
+}
+
+//_____________________________________________________________________________
+//                                                         Renderer Type & Impl
+
+pub struct Renderer {
+    formats: HashMap<String, Format>,
+    // Number of significant digits shown for constants.
+    precision: Option<usize>,
+}
+
+impl Renderer {
+    /* new
+    * Create a renderer with formats for time, position, velocity (by the 
+    * token VEL) and the builtin operators (see operator::builtin).
+    */
+    pub fn new() -> Renderer {
+        let mut renderer = Renderer {
+            formats: HashMap::new(),
+            precision: None,
+        };
+
+        renderer.set("TIME", Format::Symbol {
+            infix: "t", latex: "t", mathml: "t"});
+        renderer.set("POS", Format::Symbol {
+            infix: "x", latex: "x", mathml: "x"});
+        renderer.set("VEL", Format::Symbol {
+            infix: "x'", latex: "\\dot{x}", mathml: "x\u{307}"});
+
+        renderer.set("ADD", Format::Infix {
+            symbol: "+", precedence: 1, associative: true});
+        renderer.set("SUB", Format::Infix {
+            symbol: "-", precedence: 1, associative: false});
+        renderer.set("MUL", Format::Infix {
+            symbol: "*", precedence: 2, associative: true});
+        renderer.set("DIV", Format::Fraction);
+        renderer.set("POW", Format::Power);
+
+        renderer.set("SQUARE", Format::Exponent("2"));
+        renderer.set("SQRT", Format::Root);
+        renderer.set("EXP", Format::Exponential);
+        renderer.set("LN", Format::Function("ln"));
+        renderer.set("SIN", Format::Function("sin"));
+        renderer.set("COS", Format::Function("cos"));
+        renderer.set("TAN", Format::Function("tan"));
+
+        renderer.set("ONE", Format::Value);
+        renderer.set("PI", Format::Symbol {
+            infix: "pi", latex: "\\pi", mathml: "\u{3c0}"});
+
+        return renderer;
+    }
+
+    /* set
+    * Set the format of the operator with the given token.
+    */
+    pub fn set(&mut self, token: &str, format: Format) {
+        self.formats.insert(String::from(token), format);
+    }
+
+    /* set_precision
+    * Set the number of significant digits shown for constants, or None to
+    * show them in full.
+    */
+    pub fn set_precision(&mut self, precision: Option<usize>) {
+        self.precision = precision;
+    }
+
+    /* infix
+    * Render the right-hand side of the ODE in infix notation with minimal
+    * parentheses, e.g. "(t + 3.2) / x". With the default formats the output
+    * parses back with parse::infix, up to the precision of constants.
+    */
+    pub fn infix(&self, expr: &Expr, map: &OperatorMap) -> Result<String> {
+        return self.render(expr, map, Target::Infix);
+    }
+
+    /* latex
+    * Render the ODE as a LaTeX equation, e.g. "\frac{dx}{dt} = x^{2}".
+    */
+    pub fn latex(&self, expr: &Expr, map: &OperatorMap) -> Result<String> {
+        return self.equation(expr, map, 0, Order::First, Target::Latex);
+    }
+
+    /* mathml
+    * Render the ODE as a MathML <math> element.
+    */
+    pub fn mathml(&self, expr: &Expr, map: &OperatorMap) -> Result<String> {
+        return self.equation(expr, map, 0, Order::First, Target::MathMl);
+    }
+
+    /* equation
+    * Render the ODE giving the derivative of the given order of the state 
+    * variable with the given index, e.g. "dx/dt = x^2" or 
+    * "d^2x/dt^2 = -x" in infix notation.
+    */
+    pub fn equation(
+        &self, 
+        expr: &Expr, 
+        map: &OperatorMap, 
+        variable: usize, 
+        order: Order,
+        target: Target) -> Result<String> {
+        let rhs = self.render(expr, map, target)?;
+
+        // Index zero is rendered as position.
+        let operator = match variable {
+            0 => Operator::Position,
+            idx => Operator::Variable(idx),
+        };
+        let lhs = self.node(&Node::leaf(operator), map, target)?.0;
+
+        return Ok(match (target, order) {
+            (Target::Infix, Order::First) => format!("d{}/dt = {}", lhs, rhs),
+            (Target::Infix, Order::Second) => 
+                format!("d^2{}/dt^2 = {}", lhs, rhs),
+            (Target::Latex, Order::First) => 
+                format!("\\frac{{d{}}}{{dt}} = {}", lhs, rhs),
+            (Target::Latex, Order::Second) => 
+                format!("\\frac{{d^{{2}}{}}}{{dt^{{2}}}} = {}", lhs, rhs),
+            (Target::MathMl, Order::First) => format!(
+                "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>\
+                <mfrac><mrow><mi>d</mi>{}</mrow>\
+                <mrow><mi>d</mi><mi>t</mi></mrow></mfrac>\
+                <mo>=</mo>{}</mrow></math>", lhs, rhs),
+            (Target::MathMl, Order::Second) => format!(
+                "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow>\
+                <mfrac><mrow><msup><mi>d</mi><mn>2</mn></msup>{}</mrow>\
+                <mrow><mi>d</mi><msup><mi>t</mi><mn>2</mn></msup></mrow>\
+                </mfrac><mo>=</mo>{}</mrow></math>", lhs, rhs),
+        });
+    }
+
+    /* render
+    * Render the expression alone in the given notation.
+    */
+    pub fn render(&self, expr: &Expr, map: &OperatorMap, target: Target)
+        -> Result<String> {
+        let tree = Node::from_expr(expr);
+        return Ok(self.node(&tree, map, target)?.0);
+    }
+
+    /* node
+    * Render a subtree, returning the text and its precedence.
+    */
+    fn node(&self, node: &Node, map: &OperatorMap, target: Target)
+        -> Result<(String, u8)> {
+        let token = map.get(&node.operator).copied();
+        let format = token.and_then(|token| self.formats.get(token));
+
+        // Anonymous constants, and named constants without a format, other
+        // than those rendered by value.
+        if let Operator::Constant(c) = node.operator {
+            match (token, format) {
+                (None, _) | (Some(_), Some(Format::Value)) =>
+                    return Ok(self.number(f64::from_bits(c), target)),
+                _ => (),
+            }
+        }
+
+        let token = token.ok_or(Error::UnmappedOperator)?;
+        let args = node.args.iter()
+            .map(|arg| self.node(arg, map, target))
+            .collect::<Result<Vec<(String, u8)>>>()?;
+
+        let format = match format {
+            Some(format) => *format,
+            None => return Ok(self.unformatted(token, &args, target)),
+        };
+
+        let rendered = match (format, args.as_slice()) {
+            (Format::Symbol {infix, latex, mathml}, []) => match target {
+                Target::Infix => String::from(infix),
+                Target::Latex => String::from(latex),
+                Target::MathMl => format!("<mi>{}</mi>", mathml),
+            },
+
+            (Format::Infix {symbol, precedence, associative}, [lhs, rhs]) => {
+                // Operators are left associative, so the right argument is
+                // parenthesized at equal precedence unless associative. 
+                // Negative numbers on the right are always parenthesized so
+                // that signs are not doubled up, e.g. "x - -3".
+                let lhs = wrap(lhs, precedence, target);
+                let rhs = match (associative, rhs.1 == NEGATIVE 
+                    && node.args[1].args.is_empty()) {
+                    (_, true) => wrap(rhs, ATOM, target),
+                    (true, false) => wrap(rhs, precedence, target),
+                    (false, false) => wrap(rhs, precedence + 1, target),
+                };
+                let symbol = match (target, symbol) {
+                    (Target::Latex, "*") => "\\cdot",
+                    (Target::MathMl, "*") => "\u{22c5}",
+                    _ => symbol,
+                };
+                let text = match target {
+                    Target::Infix | Target::Latex =>
+                        format!("{} {} {}", lhs, symbol, rhs),
+                    Target::MathMl =>
+                        format!("{}<mo>{}</mo>{}", lhs, symbol, rhs),
+                };
+                return Ok((text, precedence));
+            },
+
+            (Format::Fraction, [num, den]) => match target {
+                Target::Infix => {
+                    let text = format!("{} / {}",
+                        wrap(num, 2, target), wrap(den, 3, target));
+                    return Ok((text, 2));
+                },
+                Target::Latex =>
+                    format!("\\frac{{{}}}{{{}}}", num.0, den.0),
+                Target::MathMl => format!(
+                    "<mfrac><mrow>{}</mrow><mrow>{}</mrow></mfrac>",
+                    num.0, den.0),
+            },
+
+            (Format::Power, [base, exponent]) => {
+                let text = match target {
+                    Target::Infix => format!("{}^{}",
+                        wrap(base, ATOM, target), wrap(exponent, 3, target)),
+                    _ => power(&wrap(base, ATOM, target), &exponent.0, target),
+                };
+                return Ok((text, 3));
+            },
+
+            (Format::Exponent(exponent), [base]) => {
+                let exponent = match target {
+                    Target::MathMl => format!("<mn>{}</mn>", exponent),
+                    _ => String::from(exponent),
+                };
+                let text = match target {
+                    Target::Infix =>
+                        format!("{}^{}", wrap(base, ATOM, target), exponent),
+                    _ => power(&wrap(base, ATOM, target), &exponent, target),
+                };
+                return Ok((text, 3));
+            },
+
+            (Format::Root, [arg]) => match target {
+                Target::Infix => format!("sqrt({})", arg.0),
+                Target::Latex => format!("\\sqrt{{{}}}", arg.0),
+                Target::MathMl => format!("<msqrt>{}</msqrt>", arg.0),
+            },
+
+            (Format::Exponential, [arg]) => match target {
+                Target::Infix => format!("exp({})", arg.0),
+                Target::Latex => format!("e^{{{}}}", arg.0),
+                Target::MathMl => power("<mi>e</mi>", &arg.0, target),
+            },
+
+            (Format::Function(name), _) => call(name, &args, target),
+
+            (Format::Custom(hook), _) => {
+                let args: Vec<String> = args.iter()
+                    .map(|arg| wrap(arg, ATOM, target))
+                    .collect();
+                hook(target, &args)
+            },
+
+            // A format that does not suit the arity of the operator.
+            _ => return Ok(self.unformatted(token, &args, target)),
+        };
+
+        return Ok((rendered, ATOM));
+    }
+
+    /* unformatted
+    * Render an operator without a format by its token.
+    */
+    fn unformatted(&self, token: &str, args: &[(String, u8)], target: Target)
+        -> (String, u8) {
+        if !args.is_empty() {
+            return (call(token, args, target), ATOM);
+        }
+        let text = match target {
+            Target::Infix => String::from(token),
+            Target::Latex => format!("\\mathrm{{{}}}", token),
+            Target::MathMl => format!("<mi>{}</mi>", token),
+        };
+        return (text, ATOM);
+    }
+
+    /* number
+    * Render a constant, with its precedence.
+    */
+    fn number(&self, c: f64, target: Target) -> (String, u8) {
+        let text = match self.precision {
+            Some(digits) if c.is_finite() && c != 0.0 => {
+                // Round to the number of significant digits.
+                let magnitude = c.abs().log10().floor() as i32;
+                let decimals = (digits as i32 - 1 - magnitude).max(0) as usize;
+                let rounded = format!("{:.*}", decimals, c);
+                match rounded.contains('.') {
+                    true => String::from(
+                        rounded.trim_end_matches('0').trim_end_matches('.')),
+                    false => rounded,
+                }
+            },
+            _ => c.to_string(),
+        };
+
+        let precedence = if text.starts_with('-') {NEGATIVE} else {ATOM};
+        let text = match target {
+            Target::Infix => text,
+            Target::Latex => match text.as_str() {
+                "inf" => String::from("\\infty"),
+                "-inf" => String::from("-\\infty"),
+                _ => text,
+            },
+            Target::MathMl => match text.strip_prefix('-') {
+                Some(abs) => format!("<mrow><mo>-</mo><mn>{}</mn></mrow>", abs),
+                None => format!("<mn>{}</mn>", text),
+            },
+        };
+        return (text, precedence);
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        return Renderer::new();
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                      Helpers
+
+/* wrap
+* Parenthesize a fragment if it binds looser than the given precedence.
+*/
+fn wrap(fragment: &(String, u8), precedence: u8, target: Target) -> String {
+    let (text, fragment_precedence) = fragment;
+    if *fragment_precedence >= precedence {
+        return text.clone();
+    }
+    return match target {
+        Target::Infix => format!("({})", text),
+        Target::Latex => format!("\\left({}\\right)", text),
+        Target::MathMl => format!("<mrow><mo>(</mo>{}<mo>)</mo></mrow>", text),
+    };
+}
+
+/* power
+* Render a base raised to an exponent in LaTeX or MathML.
+*/
+fn power(base: &str, exponent: &str, target: Target) -> String {
+    return match target {
+        Target::MathMl =>
+            format!("<msup><mrow>{}</mrow><mrow>{}</mrow></msup>",
+                base, exponent),
+        _ => format!("{{{}}}^{{{}}}", base, exponent),
+    };
+}
+
+/* call
+* Render a function applied to its arguments.
+*/
+fn call(name: &str, args: &[(String, u8)], target: Target) -> String {
+    let args: Vec<&str> = args.iter().map(|arg| arg.0.as_str()).collect();
+    return match target {
+        Target::Infix => format!("{}({})", name, args.join(", ")),
+        Target::Latex => {
+            // LaTeX has commands for the common functions.
+            let name = match name {
+                "sin" | "cos" | "tan" | "exp" | "ln" | "log" =>
+                    format!("\\{}", name),
+                _ => format!("\\operatorname{{{}}}", name),
+            };
+            format!("{}\\left({}\\right)", name, args.join(", "))
+        },
+        Target::MathMl => format!(
+            "<mi>{}</mi><mo>&#x2061;</mo><mrow><mo>(</mo>{}<mo>)</mo></mrow>",
+            name, args.join("<mo>,</mo>")),
+    };
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::builtin_map;
+    use crate::parse;
+
+    // Builtin operators with velocity, rendered as x'.
+    fn velocity_map() -> OperatorMap<'static> {
+        let mut map = builtin_map();
+        map.insert(Operator::Velocity(0), "VEL");
+        return map;
+    }
+
+    fn infix(text: &str, map: &OperatorMap) -> String {
+        let expr = parse::prefix(text, map).unwrap();
+        return Renderer::new().infix(&expr, map).unwrap();
+    }
+
+    fn render(text: &str, map: &OperatorMap, target: Target) -> String {
+        let expr = parse::prefix(text, map).unwrap();
+        return Renderer::new().render(&expr, map, target).unwrap();
+    }
+
+    #[test]
+    fn infix_uses_minimal_parentheses() {
+        let map = velocity_map();
+        let cases = [
+            ("DIV ADD TIME 3.2 POS", "(t + 3.2) / x"),
+            ("ADD TIME DIV 3.2 POS", "t + 3.2 / x"),
+            ("ADD ADD TIME POS 2", "t + x + 2"),
+            ("ADD TIME ADD POS 2", "t + x + 2"),
+            ("SUB SUB TIME POS 2", "t - x - 2"),
+            ("SUB TIME SUB POS 2", "t - (x - 2)"),
+            ("SUB TIME ADD POS 2", "t - (x + 2)"),
+            ("MUL ADD TIME POS 2", "(t + x) * 2"),
+            ("MUL TIME MUL POS 2", "t * x * 2"),
+            ("DIV TIME MUL POS 2", "t / (x * 2)"),
+            ("DIV TIME DIV POS 2", "t / (x / 2)"),
+            ("MUL DIV TIME POS 2", "t / x * 2"),
+            ("SQUARE ADD TIME POS", "(t + x)^2"),
+            ("SQUARE -2", "(-2)^2"),
+            ("MUL -2 POS", "-2 * x"),
+            ("SQRT ADD TIME POS", "sqrt(t + x)"),
+            ("EXP MUL TIME POS", "exp(t * x)"),
+            ("SIN ADD PI ONE", "sin(pi + 1)"),
+        ];
+        for (text, expected) in cases.iter() {
+            assert_eq!(render(text, &map, Target::Infix), *expected);
+        }
+    }
+
+    #[test]
+    fn latex_uses_minimal_parentheses() {
+        let map = velocity_map();
+        let cases = [
+            ("DIV ADD TIME 3.2 POS", "\\frac{t + 3.2}{x}"),
+            ("SUB TIME SUB POS 2", "t - \\left(x - 2\\right)"),
+            ("MUL ADD TIME POS 2", "\\left(t + x\\right) \\cdot 2"),
+            ("SQUARE ADD TIME POS", "{\\left(t + x\\right)}^{2}"),
+            ("SQUARE POS", "{x}^{2}"),
+            ("SUB POS -3", "x - \\left(-3\\right)"),
+            ("SQRT POS", "\\sqrt{x}"),
+            ("EXP MUL TIME POS", "e^{t \\cdot x}"),
+            ("SIN MUL PI POS", "\\sin\\left(\\pi \\cdot x\\right)"),
+        ];
+        for (text, expected) in cases.iter() {
+            assert_eq!(render(text, &map, Target::Latex), *expected);
+        }
+    }
+
+    #[test]
+    fn mathml_uses_minimal_parentheses() {
+        let map = velocity_map();
+        let cases = [
+            ("DIV ADD TIME 3.2 POS", 
+                "<mfrac><mrow><mi>t</mi><mo>+</mo><mn>3.2</mn></mrow>\
+                <mrow><mi>x</mi></mrow></mfrac>"),
+            ("SUB TIME SUB POS 2", 
+                "<mi>t</mi><mo>-</mo><mrow><mo>(</mo>\
+                <mi>x</mi><mo>-</mo><mn>2</mn><mo>)</mo></mrow>"),
+            ("MUL ADD TIME POS 2",
+                "<mrow><mo>(</mo><mi>t</mi><mo>+</mo><mi>x</mi><mo>)</mo>\
+                </mrow><mo>\u{22c5}</mo><mn>2</mn>"),
+            ("SQUARE -2",
+                "<msup><mrow><mrow><mo>(</mo><mrow><mo>-</mo><mn>2</mn>\
+                </mrow><mo>)</mo></mrow></mrow><mrow><mn>2</mn></mrow></msup>"),
+            ("SUB POS -3",
+                "<mi>x</mi><mo>-</mo><mrow><mo>(</mo><mrow><mo>-</mo>\
+                <mn>3</mn></mrow><mo>)</mo></mrow>"),
+        ];
+        for (text, expected) in cases.iter() {
+            assert_eq!(render(text, &map, Target::MathMl), *expected);
+        }
+    }
+
+    #[test]
+    fn equations() {
+        let map = velocity_map();
+        let expr = parse::prefix("SQUARE POS", &map).unwrap();
+        let renderer = Renderer::new();
+        assert_eq!(renderer.latex(&expr, &map).unwrap(), 
+            "\\frac{dx}{dt} = {x}^{2}");
+        assert_eq!(renderer.equation(
+            &expr, &map, 0, Order::Second, Target::Infix).unwrap(),
+            "d^2x/dt^2 = x^2");
+        let mathml = renderer.mathml(&expr, &map).unwrap();
+        assert!(mathml.starts_with("<math xmlns="));
+        assert!(mathml.ends_with("<mo>=</mo><msup><mrow><mi>x</mi></mrow>\
+            <mrow><mn>2</mn></mrow></msup></mrow></math>"));
+    }
+
+    #[test]
+    fn precision_rounds_constants() {
+        let map = velocity_map();
+        let expr = parse::prefix("MUL 3.14159 POS", &map).unwrap();
+        let mut renderer = Renderer::new();
+        renderer.set_precision(Some(3));
+        assert_eq!(renderer.infix(&expr, &map).unwrap(), "3.14 * x");
+    }
+
+    #[test]
+    fn infix_parenthesizes_negative_right_operands() {
+        let map = velocity_map();
+        assert_eq!(infix("SUB POS -3", &map), "x - (-3)");
+        assert_eq!(infix("ADD POS -3", &map), "x + (-3)");
+        assert_eq!(infix("MUL POS -3", &map), "x * (-3)");
+        assert_eq!(infix("SUB -3 POS", &map), "-3 - x");
+    }
+
+    #[test]
+    fn infix_round_trips_through_parser() {
+        let map = velocity_map();
+        let cases = [
+            "DIV ADD TIME 3.2 POS",
+            "SUB POS -3",
+            "SUB POS SUB TIME VEL",
+            "MUL ADD POS PI SUB TIME -0.5",
+            "DIV POS DIV TIME -2",
+            "SQUARE SUB POS 1.5",
+            "EXP MUL -1 TIME",
+            "SIN ADD COS POS TAN SQRT LN TIME",
+        ];
+        for text in cases.iter() {
+            let rendered = infix(text, &map);
+            let parsed = parse::infix(&rendered, &map)
+                .unwrap_or_else(|e| panic!("{}: {}", rendered, e));
+            assert_eq!(parsed.to_string(&map).trim_end(), *text,
+                "via {}", rendered);
+        }
+    }
+}
@@ -0,0 +1,594 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Selection of the parents of each generation.
+//_____________________________________________________________________________
+
+// External imports.
+use rand::{Rng, RngCore};
+use rand::seq::SliceRandom;
+use rand_distr::Exp;
+
+// Internal imports.
+use crate::metric::Sample;
+use crate::ode::State;
+use crate::population::Individual;
+
+//_____________________________________________________________________________
+//                                                              Selection Trait
+
+pub trait Selection {
+    /* select
+    * Choose parents from the population, where each may be chosen more than
+    * once.
+    * Input:
+    *     population - Individuals sorted by fitness, best first.
+    *     count - Number of parents to choose.
+    *     rng - Random number generator to draw from.
+    * Output:
+    *     Indices of the parents in the population.
+    */
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize>;
+
+    /* uses_cases
+    * Whether the selection compares individuals by their error at each data
+    * point, which are otherwise not computed (see Individual::cases).
+    */
+    fn uses_cases(&self) -> bool {
+        return false;
+    }
+}
+
+/* score
+* Get the fitness of an individual for comparison, with diverged individuals
+* the worst.
+*/
+fn score(individual: &Individual) -> f64 {
+    return match individual.fitness.is_nan() {
+        true => f64::INFINITY,
+        false => individual.fitness,
+    };
+}
+
+/* spin
+* Choose indices at random with the given relative weights, uniformly if the
+* weights are all zero.
+*/
+fn spin(weights: &[f64], count: usize, rng: &mut dyn RngCore) -> Vec<usize> {
+    let mut total = 0.0;
+    let cumulative: Vec<f64> = weights.iter()
+        .map(|weight| {
+            total += weight;
+            total
+        })
+        .collect();
+
+    return (0..count)
+        .map(|_| match total > 0.0 {
+            true => {
+                let target = rng.gen_range(0.0, total);
+                cumulative.partition_point(|sum| *sum <= target)
+                    .min(weights.len() - 1)
+            },
+            false => rng.gen_range(0, weights.len()),
+        })
+        .collect();
+}
+
+/* cases
+* Get the error of each state variable at each data point from the samples
+* of a trajectory, the mean absolute residual of the samples nearest each
+* data point weighted as by the metrics.
+* Input:
+*     samples - Samples of a trajectory simulated against the data.
+*     states - The data, ordered by time.
+* Output:
+*     The errors at each data point in turn, infinite where the trajectory
+*     diverged or was not sampled.
+*/
+pub fn cases(samples: &[Sample], states: &[State]) -> Vec<f64> {
+    if states.is_empty() {
+        return Vec::new();
+    }
+    let dimension = states[0].dimension();
+    let mut totals = vec![0.0; states.len() * dimension];
+    let mut weights = vec![0.0; states.len()];
+
+    for sample in samples.iter() {
+        // Find the nearest data point.
+        let after = states.partition_point(|state| state.time < sample.time)
+            .min(states.len() - 1);
+        let idx = match after > 0 && sample.time - states[after - 1].time
+            < states[after].time - sample.time {
+            true => after - 1,
+            false => after,
+        };
+
+        for (total, residual) in totals[(idx * dimension)..]
+            .iter_mut().zip(sample.residuals.iter()) {
+            *total += sample.weight * residual.abs();
+        }
+        weights[idx] += sample.weight;
+    }
+
+    return totals.iter().enumerate()
+        .map(|(case, total)| match total / weights[case / dimension] {
+            error if error.is_finite() => error,
+            _ => f64::INFINITY,
+        })
+        .collect();
+}
+
+//_____________________________________________________________________________
+//                                                           Builtin Selections
+
+// Chooses the individual with fitness closest to the best fitness plus an
+// exponentially distributed offset, the original selection of the crate.
+// Note that the offset is in units of the fitness, so that how strongly
+// better individuals are favoured depends on the scale of the metric.
+#[derive(Copy, Clone, Debug)]
+pub struct Exponential {
+    // Rate of the offset, the reciprocal of its mean.
+    pub lambda: f64,
+}
+
+impl Exponential {
+    /* new
+    * Create the selection with offsets of the given rate.
+    */
+    pub fn new(lambda: f64) -> Exponential {
+        return Exponential {lambda};
+    }
+}
+
+impl Default for Exponential {
+    fn default() -> Exponential {
+        return Exponential::new(0.1);
+    }
+}
+
+impl Selection for Exponential {
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize> {
+        // The exponential distribution has heavier tails than, say, a half
+        // normal, so worse individuals are still chosen now and then.
+        let exp_distr = Exp::new(self.lambda).unwrap();
+        let min_fitness = population[0].fitness;
+
+        return (0..count)
+            .map(|_| {
+                let target = rng.sample(exp_distr) + min_fitness;
+                population.windows(2)
+                    .position(|pair|
+                        pair[0].fitness <= target && pair[1].fitness >= target)
+                    // If we don't find a closest individual, we return the
+                    // last individual in our population.
+                    .unwrap_or(population.len() - 1)
+            })
+            .collect();
+    }
+}
+
+// Chooses the best of a number of individuals drawn uniformly at random,
+// which depends only on the order of fitness. Larger tournaments favour
+// better individuals more strongly.
+#[derive(Copy, Clone, Debug)]
+pub struct Tournament {
+    pub size: usize,
+}
+
+impl Tournament {
+    /* new
+    * Create the selection with tournaments of the given size.
+    */
+    pub fn new(size: usize) -> Tournament {
+        return Tournament {size};
+    }
+}
+
+impl Default for Tournament {
+    fn default() -> Tournament {
+        return Tournament::new(7);
+    }
+}
+
+impl Selection for Tournament {
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize> {
+        // Note that the population is sorted, so the best of the tournament
+        // is the one with the lowest index.
+        return (0..count)
+            .map(|_| (0..self.size.max(1))
+                .map(|_| rng.gen_range(0, population.len()))
+                .min()
+                .unwrap())
+            .collect();
+    }
+}
+
+// Double tournament selection (Luke & Panait, 2002), a tournament on fitness
+// whose entrants are each the winner of a tournament on size between two 
+// random individuals. The smaller of the two wins with probability half the
+// parsimony, between one (no preference) and two (always), so that smaller
+// individuals are favoured without a penalty on fitness.
+#[derive(Copy, Clone, Debug)]
+pub struct DoubleTournament {
+    pub size: usize,
+    pub parsimony: f64,
+}
+
+impl DoubleTournament {
+    /* new
+    * Create the selection with fitness tournaments of the given size and the
+    * given preference for smaller individuals.
+    */
+    pub fn new(size: usize, parsimony: f64) -> DoubleTournament {
+        return DoubleTournament {size, parsimony};
+    }
+}
+
+impl Default for DoubleTournament {
+    fn default() -> DoubleTournament {
+        return DoubleTournament::new(7, 1.4);
+    }
+}
+
+impl Selection for DoubleTournament {
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize> {
+        let mut entrant = || {
+            let first = rng.gen_range(0, population.len());
+            let second = rng.gen_range(0, population.len());
+            let sizes = (population[first].system.size(), 
+                population[second].system.size());
+            let (smaller, larger) = match sizes.0 <= sizes.1 {
+                true => (first, second),
+                false => (second, first),
+            };
+            return match sizes.0 == sizes.1 
+                || rng.gen_range(0.0, 2.0) < self.parsimony {
+                true => smaller,
+                false => larger,
+            };
+        };
+
+        // Note that the population is sorted, so the best of the tournament
+        // is the one with the lowest index.
+        return (0..count)
+            .map(|_| (0..self.size.max(1))
+                .map(|_| entrant())
+                .min()
+                .unwrap())
+            .collect();
+    }
+}
+
+// Fitness proportional selection, choosing individuals with probability
+// proportional to 1 / (1 + f), where f is the fitness less the best fitness
+// of the population. Diverged individuals are never chosen.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Roulette;
+
+impl Selection for Roulette {
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize> {
+        let min_fitness = score(&population[0]);
+        let weights: Vec<f64> = population.iter()
+            .map(|individual| match score(individual) - min_fitness {
+                fitness if fitness.is_finite() => 1.0 / (1.0 + fitness),
+                _ => 0.0,
+            })
+            .collect();
+        return spin(&weights, count, rng);
+    }
+}
+
+// Linear ranking, choosing individuals with probability falling linearly in
+// their rank. The best individual is chosen pressure times as often as the
+// median, for a pressure between one (uniform) and two.
+#[derive(Copy, Clone, Debug)]
+pub struct Rank {
+    pub pressure: f64,
+}
+
+impl Rank {
+    /* new
+    * Create the selection with the given selection pressure.
+    */
+    pub fn new(pressure: f64) -> Rank {
+        return Rank {pressure};
+    }
+}
+
+impl Default for Rank {
+    fn default() -> Rank {
+        return Rank::new(1.5);
+    }
+}
+
+impl Selection for Rank {
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize> {
+        let last = (population.len() - 1).max(1) as f64;
+        let weights: Vec<f64> = (0..population.len())
+            .map(|rank| self.pressure
+                - (2.0 * self.pressure - 2.0) * rank as f64 / last)
+            .collect();
+        return spin(&weights, count, rng);
+    }
+}
+
+// Epsilon lexicase selection (La Cava et al., 2016). Each parent is chosen by
+// filtering the population through the data points in random order, keeping
+// at each the individuals within epsilon of the best error there, where
+// epsilon is the median absolute deviation of the errors. Individuals that
+// fit different parts of the data well are kept, rather than only those
+// best on average.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Lexicase;
+
+impl Selection for Lexicase {
+    fn select(&self, population: &[Individual], count: usize,
+        rng: &mut dyn RngCore) -> Vec<usize> {
+        let num_cases = population.iter()
+            .map(|individual| individual.cases.len())
+            .max()
+            .unwrap_or(0);
+        let error = |idx: usize, case: usize|
+            match population[idx].cases.get(case) {
+                Some(error) if error.is_finite() => *error,
+                _ => f64::INFINITY,
+            };
+
+        // Median absolute deviation of the finite errors of each case.
+        let median = |values: &mut Vec<f64>| {
+            values.sort_by(|a, b| a.total_cmp(b));
+            return match values.len() {
+                0 => 0.0,
+                n if n % 2 == 1 => values[n / 2],
+                n => (values[n / 2 - 1] + values[n / 2]) / 2.0,
+            };
+        };
+        let epsilons: Vec<f64> = (0..num_cases)
+            .map(|case| {
+                let mut errors: Vec<f64> = (0..population.len())
+                    .map(|idx| error(idx, case))
+                    .filter(|error| error.is_finite())
+                    .collect();
+                let centre = median(&mut errors);
+                let mut deviations: Vec<f64> = errors.iter()
+                    .map(|error| (error - centre).abs())
+                    .collect();
+                median(&mut deviations)
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..num_cases).collect();
+        return (0..count)
+            .map(|_| {
+                order.shuffle(rng);
+                let mut candidates: Vec<usize> = 
+                    (0..population.len()).collect();
+                for case in order.iter() {
+                    if candidates.len() <= 1 {
+                        break;
+                    }
+                    let best = candidates.iter()
+                        .map(|idx| error(*idx, *case))
+                        .fold(f64::INFINITY, f64::min);
+                    candidates.retain(|idx|
+                        error(*idx, *case) <= best + epsilons[*case]);
+                }
+                candidates[rng.gen_range(0, candidates.len())]
+            })
+            .collect();
+    }
+
+    fn uses_cases(&self) -> bool {
+        return true;
+    }
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::ode::{Expr, System};
+    use crate::operator::{OperatorMap, builtin_map};
+
+    const DRAWS: usize = 2000;
+
+    fn individual(fitness: f64, cases: Vec<f64>) -> Individual {
+        let map = OperatorMap::new();
+        let system = System::from(Expr::parse("POS", &map).unwrap());
+        return Individual {fitness, system, cases};
+    }
+
+    fn individual_of(text: &str, fitness: f64) -> Individual {
+        let map = builtin_map();
+        let system = System::from(Expr::parse(text, &map).unwrap());
+        return Individual {fitness, system, cases: Vec::new()};
+    }
+
+    // Individuals of the given fitness, which must be sorted.
+    fn population(fitnesses: &[f64]) -> Vec<Individual> {
+        return fitnesses.iter()
+            .map(|fitness| individual(*fitness, Vec::new()))
+            .collect();
+    }
+
+    // Number of times each index is chosen.
+    fn counts(selection: &dyn Selection, population: &[Individual])
+        -> Vec<usize> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut counts = vec![0; population.len()];
+        for idx in selection.select(population, DRAWS, &mut rng) {
+            counts[idx] += 1;
+        }
+        return counts;
+    }
+
+    fn sample(time: f64, residual: f64, weight: f64) -> Sample {
+        return Sample {
+            time, 
+            residuals: vec![residual], 
+            observed: vec![0.0], 
+            weight, 
+            span: 1.0,
+        };
+    }
+
+    #[test]
+    fn spin_follows_weights() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert!(spin(&[0.0, 0.0, 1.0], 100, &mut rng).iter().all(|i| *i == 2));
+        // Trailing zero weights are never chosen.
+        assert!(spin(&[1.0, 0.0], 100, &mut rng).iter().all(|i| *i == 0));
+        assert!(spin(&[1.0], 100, &mut rng).iter().all(|i| *i == 0));
+
+        let chosen = spin(&[1.0, 3.0], DRAWS, &mut rng);
+        let ones = chosen.iter().filter(|i| **i == 1).count();
+        assert!((ones as f64 / DRAWS as f64 - 0.75).abs() < 0.05);
+    }
+
+    #[test]
+    fn spin_is_uniform_without_weight() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let chosen = spin(&[0.0; 4], DRAWS, &mut rng);
+        for idx in 0..4 {
+            let count = chosen.iter().filter(|i| **i == idx).count();
+            assert!((count as f64 / DRAWS as f64 - 0.25).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn large_tournaments_choose_the_best() {
+        let population = population(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let counts = counts(&Tournament::new(50), &population);
+        assert_eq!(counts[0], DRAWS);
+    }
+
+    #[test]
+    fn tournaments_of_one_are_uniform() {
+        let population = population(&[1.0, 2.0, 3.0, 4.0]);
+        for count in counts(&Tournament::new(1), &population).iter() {
+            assert!((*count as f64 / DRAWS as f64 - 0.25).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn double_tournaments_favour_smaller_individuals() {
+        let population = vec![
+            individual_of("ADD SIN POS TIME", 1.0),
+            individual_of("POS", 2.0),
+        ];
+        // With no preference entrants are uniform, as the fitness
+        // tournament of one.
+        let counts_of = |parsimony| 
+            counts(&DoubleTournament::new(1, parsimony), &population);
+        let share = |count: usize| count as f64 / DRAWS as f64;
+        assert!((share(counts_of(1.0)[0]) - 0.5).abs() < 0.05);
+        // Always preferring the smaller, the larger must be drawn twice.
+        assert!((share(counts_of(2.0)[0]) - 0.25).abs() < 0.05);
+        assert!((share(counts_of(1.5)[0]) - 0.375).abs() < 0.05);
+    }
+
+    #[test]
+    fn double_tournaments_of_equal_sizes_are_tournaments() {
+        let population = population(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let counts = counts(&DoubleTournament::new(100, 2.0), &population);
+        assert_eq!(counts[0], DRAWS);
+    }
+
+    #[test]
+    fn roulette_never_chooses_diverged_individuals() {
+        let population = population(&[0.0, 1.0, 3.0, f64::NAN, f64::NAN]);
+        let counts = counts(&Roulette, &population);
+        assert_eq!(counts[3] + counts[4], 0);
+        // Weights are 1, 1/2 and 1/4.
+        assert!(counts[0] > counts[1] && counts[1] > counts[2]);
+        assert!((counts[0] as f64 / DRAWS as f64 - 4.0 / 7.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn roulette_is_uniform_when_all_diverged() {
+        let population = population(&[f64::NAN; 3]);
+        assert!(counts(&Roulette, &population).iter().all(|c| *c > 0));
+    }
+
+    #[test]
+    fn rank_pressure() {
+        let population = population(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        for count in counts(&Rank::new(1.0), &population).iter() {
+            assert!((*count as f64 / DRAWS as f64 - 0.2).abs() < 0.05);
+        }
+
+        // The worst has no weight at the greatest pressure.
+        let counts = counts(&Rank::new(2.0), &population);
+        assert_eq!(counts[4], 0);
+        assert!((counts[0] as f64 / DRAWS as f64 - 0.4).abs() < 0.05);
+    }
+
+    #[test]
+    fn lexicase_chooses_specialists() {
+        let population = vec![
+            individual(1.0, vec![1.0, 1.0, 5.0]),
+            individual(2.0, vec![1.0, 1.0, 5.0]),
+            individual(3.0, vec![10.0, 10.0, 0.0]),
+            individual(4.0, vec![20.0, 20.0, 20.0]),
+        ];
+        let counts = counts(&Lexicase, &population);
+        // The specialist is chosen whenever its case comes first.
+        assert!((counts[2] as f64 / DRAWS as f64 - 1.0 / 3.0).abs() < 0.05);
+        assert_eq!(counts[3], 0);
+        assert!(counts[0] > 0 && counts[1] > 0);
+    }
+
+    #[test]
+    fn lexicase_keeps_individuals_within_epsilon() {
+        // The median absolute deviation of the errors is 1.
+        let population = vec![
+            individual(1.0, vec![1.0]),
+            individual(2.0, vec![1.05]),
+            individual(3.0, vec![3.0]),
+            individual(4.0, vec![3.05]),
+        ];
+        let counts = counts(&Lexicase, &population);
+        assert!(counts[0] > 0 && counts[1] > 0);
+        assert_eq!(counts[2] + counts[3], 0);
+    }
+
+    #[test]
+    fn lexicase_without_cases_is_uniform() {
+        // As with screened evaluation, where cases are not computed.
+        let population = population(&[1.0, 2.0, 3.0]);
+        assert!(counts(&Lexicase, &population).iter().all(|c| *c > 0));
+    }
+
+    #[test]
+    fn cases_assign_samples_to_the_nearest_data_point() {
+        let states = vec![
+            State::new(0.0, 0.0), State::new(1.0, 0.0),
+            State::new(2.0, 0.0), State::new(3.0, 0.0),
+        ];
+        let samples = vec![
+            sample(0.0, 1.0, 1.0),
+            sample(0.4, -3.0, 1.0),
+            // Ties go to the later data point.
+            sample(0.5, 2.0, 2.0),
+            sample(1.0, 4.0, 1.0),
+            sample(3.0, f64::NAN, 1.0),
+        ];
+        let errors = cases(&samples, &states);
+        assert_eq!(errors[0], 2.0);
+        assert_eq!(errors[1], (2.0 * 2.0 + 4.0) / 3.0);
+        // Unsampled and diverged data points have infinite error.
+        assert_eq!(errors[2], f64::INFINITY);
+        assert_eq!(errors[3], f64::INFINITY);
+        assert!(cases(&samples, &[]).is_empty());
+    }
+}
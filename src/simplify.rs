@@ -0,0 +1,241 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Algebraic simplification and constant folding of expressions.
+//_____________________________________________________________________________
+
+// Internal imports.
+use crate::ode::{Expr, State};
+use crate::operator::{Operator, ToOperator, OperatorMap};
+use crate::tree::Node;
+
+//_____________________________________________________________________________
+//                                                       Simplifier Type & Impl
+
+// Simplifies expressions by folding constant subtrees and applying identity
+// rules for the builtin arithmetic operators. Note that rules such as 
+// MUL 0 x = 0 or DIV x x = 1 are not applied, as they fail where x is NaN,
+// infinite or zero, and would give finite fitness to diverging candidates.
+#[derive(Clone)]
+pub struct Simplifier {
+    // The arithmetic operators as they appear in the map, if they do.
+    add: Option<Operator>,
+    sub: Option<Operator>,
+    mul: Option<Operator>,
+    div: Option<Operator>,
+}
+
+impl Simplifier {
+    /* new
+    * Create a simplifier for expressions built from the given map. The
+    * arithmetic rules apply to the operators with tokens ADD, SUB, MUL and
+    * DIV, which are assumed to be those of operator::builtin.
+    */
+    pub fn new(map: &OperatorMap) -> Simplifier {
+        let binary = |token: &str|
+            map.find(token).filter(|operator| operator.arity() == 2);
+
+        return Simplifier {
+            add: binary("ADD"),
+            sub: binary("SUB"),
+            mul: binary("MUL"),
+            div: binary("DIV"),
+        };
+    }
+
+    /* simplify
+    * Simplify an expression. The result evaluates the same as the given
+    * expression at every state, up to the sign of zero.
+    */
+    pub fn simplify(&self, expr: &Expr) -> Expr {
+        let tree = Node::from_expr(expr);
+        return self.node(tree).to_expr();
+    }
+
+    /* node
+    * Simplify a subtree, children first.
+    */
+    fn node(&self, node: Node) -> Node {
+        let Node {operator, args} = node;
+        let args: Vec<Node> = args.into_iter()
+            .map(|arg| self.node(arg))
+            .collect();
+        let node = Node {operator, args};
+
+        // Fold subtrees without variables into a single constant. Note that
+        // the state does not matter as there are no variables, and that 
+        // adding zero turns negative zero into zero.
+        if !node.args.is_empty() && node.is_constant() {
+            let value = node.to_expr().eval(&State::new(0.0, 0.0)) + 0.0;
+            return Node::leaf(value.to_operator());
+        }
+
+        if node.args.len() != 2 {
+            return node;
+        }
+
+        let operator = Some(node.operator);
+        let mut args = node.args.into_iter();
+        let lhs = args.next().unwrap();
+        let rhs = args.next().unwrap();
+
+        if operator == self.add {
+            if is_value(&lhs, 0.0) {
+                return rhs;
+            }
+            if is_value(&rhs, 0.0) {
+                return lhs;
+            }
+        } else if operator == self.sub {
+            if is_value(&rhs, 0.0) {
+                return lhs;
+            }
+        } else if operator == self.mul {
+            if is_value(&lhs, 1.0) {
+                return rhs;
+            }
+            if is_value(&rhs, 1.0) {
+                return lhs;
+            }
+        } else if operator == self.div && is_value(&rhs, 1.0) {
+            return lhs;
+        }
+
+        return Node {operator: node.operator, args: vec![lhs, rhs]};
+    }
+}
+
+/* is_value
+* Whether a node is a constant with the given value.
+*/
+fn is_value(node: &Node, value: f64) -> bool {
+    return match node.operator {
+        Operator::Constant(c) => f64::from_bits(c) == value,
+        _ => false,
+    };
+}
+
+//_____________________________________________________________________________
+//                                                                        Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::{self, builtin_map};
+
+    fn simplified(text: &str, map: &OperatorMap) -> String {
+        let expr = Expr::parse(text, map).unwrap();
+        let simplified = Simplifier::new(map).simplify(&expr);
+        return String::from(simplified.to_string(map).trim_end());
+    }
+
+    fn assert_rules(rules: &[(&str, &str)]) {
+        let map = builtin_map();
+        for (text, expected) in rules.iter() {
+            assert_eq!(simplified(text, &map), *expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn folds_constant_subtrees() {
+        assert_rules(&[
+            ("ADD 2 3", "5"),
+            ("MUL ADD 2 3 SQUARE 2", "20"),
+            ("SIN 0", "0"),
+            ("ADD POS MUL 2 3", "ADD POS 6"),
+            ("MUL PI ONE", "PI"),
+            ("ADD PI ONE", "4.141592653589793"),
+            ("SUB 0 0", "0"),
+        ]);
+        // Negative zero is folded to zero.
+        assert_rules(&[("MUL -1 0", "0")]);
+    }
+
+    #[test]
+    fn add_rules() {
+        assert_rules(&[
+            ("ADD 0 POS", "POS"),
+            ("ADD POS 0", "POS"),
+            ("ADD POS SUB 1 1", "POS"),
+        ]);
+    }
+
+    #[test]
+    fn sub_rules() {
+        assert_rules(&[
+            ("SUB POS 0", "POS"),
+            ("SUB 0 POS", "SUB 0 POS"),
+            ("SUB POS TIME", "SUB POS TIME"),
+        ]);
+    }
+
+    #[test]
+    fn mul_rules() {
+        assert_rules(&[
+            ("MUL ONE POS", "POS"),
+            ("MUL POS ONE", "POS"),
+            ("MUL POS 1", "POS"),
+            ("MUL POS 2", "MUL POS 2"),
+        ]);
+    }
+
+    #[test]
+    fn div_rules() {
+        assert_rules(&[
+            ("DIV POS ONE", "POS"),
+            ("DIV ONE POS", "DIV ONE POS"),
+        ]);
+    }
+
+    #[test]
+    fn keeps_operands_that_may_not_be_finite() {
+        let map = builtin_map();
+        let cases = [
+            ("DIV TIME TIME", State::new(0.0, 1.0)),
+            ("DIV 0 POS", State::new(1.0, 0.0)),
+            ("MUL 0 DIV ONE POS", State::new(1.0, 0.0)),
+            ("MUL LN POS 0", State::new(1.0, -1.0)),
+            ("SUB LN POS LN POS", State::new(1.0, -1.0)),
+            ("SUB DIV ONE POS DIV ONE POS", State::new(1.0, 0.0)),
+        ];
+        for (text, state) in cases.iter() {
+            assert_eq!(simplified(text, &map), *text);
+            let expr = Expr::parse(text, &map).unwrap();
+            let simplified = Simplifier::new(&map).simplify(&expr);
+            assert!(expr.eval(state).is_nan());
+            assert!(simplified.eval(state).is_nan(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn rules_apply_children_first() {
+        assert_rules(&[
+            ("MUL ADD POS 0 SUB TIME 0", "MUL POS TIME"),
+            ("ADD MUL POS ONE DIV TIME ONE", "ADD POS TIME"),
+            ("SIN SUB MUL POS 1 SUB 2 2", "SIN POS"),
+        ]);
+    }
+
+    #[test]
+    fn rules_need_the_operators_in_the_map() {
+        // The rules find the operators by token, so SUB by another token is
+        // left alone.
+        let mut map = OperatorMap::new();
+        map.insert(operator::builtin("SUB").unwrap(), "MINUS");
+        assert_eq!(simplified("MINUS POS 0", &map), "MINUS POS 0");
+    }
+
+    #[test]
+    fn simplified_expressions_evaluate_the_same() {
+        let map = builtin_map();
+        let expr = Expr::parse(
+            "ADD MUL ONE SIN POS SUB DIV TIME ONE MUL 0 COS 0", &map)
+            .unwrap();
+        let simplified = Simplifier::new(&map).simplify(&expr);
+        assert!(simplified.size() < expr.size());
+        for i in 0..10 {
+            let state = State::new(i as f64 * 0.3, i as f64 - 4.5);
+            assert!((simplified.eval(&state) - expr.eval(&state)).abs() 
+                < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//_____________________________________________________________________________
+// Author: Garrett Tetrault
+// Tree view of expressions, for transformations that are awkward to do on the
+// flat prefix representation.
+//_____________________________________________________________________________
+
+// Internal imports.
+use crate::ode::Expr;
+use crate::operator::Operator;
+
+//_____________________________________________________________________________
+//                                                             Node Type & Impl
+
+#[derive(Clone, PartialEq)]
+pub struct Node {
+    pub operator: Operator,
+    // One node per argument of the operator, in order.
+    pub args: Vec<Node>,
+}
+
+impl Node {
+    /* leaf
+    * Create a node for an operator taking no arguments.
+    */
+    pub fn leaf(operator: Operator) -> Node {
+        return Node {operator, args: Vec::new()};
+    }
+
+    /* from_expr
+    * Build the tree of an expression.
+    */
+    pub fn from_expr(expr: &Expr) -> Node {
+        // Expressions are always valid, so each operator has its arguments.
+        let mut iter = expr.operators().iter();
+        return Node::build(&mut iter);
+    }
+
+    fn build<'a, I>(iter: &mut I) -> Node
+        where I: Iterator<Item = &'a Operator> {
+        let operator = *iter.next().unwrap();
+        let args = (0..operator.arity()).map(|_| Node::build(iter)).collect();
+        return Node {operator, args};
+    }
+
+    /* to_expr
+    * Flatten the tree back into an expression.
+    */
+    pub fn to_expr(&self) -> Expr {
+        let mut operators = Vec::new();
+        self.flatten(&mut operators);
+        return Expr::from_operators(operators).unwrap();
+    }
+
+    fn flatten(&self, operators: &mut Vec<Operator>) {
+        operators.push(self.operator);
+        for arg in self.args.iter() {
+            arg.flatten(operators);
+        }
+    }
+
+    /* size
+    * Get the number of nodes in the tree.
+    */
+    pub fn size(&self) -> usize {
+        return 1 + self.args.iter().map(Node::size).sum::<usize>();
+    }
+
+    /* depth
+    * Get the number of nodes on the longest path from the root to a leaf, 
+    * less one, so that a single leaf has depth zero.
+    */
+    pub fn depth(&self) -> usize {
+        return self.args.iter()
+            .map(|arg| 1 + arg.depth())
+            .max()
+            .unwrap_or(0);
+    }
+
+    /* is_constant
+    * Whether the tree contains no variables, so always evaluates the same.
+    */
+    pub fn is_constant(&self) -> bool {
+        return match self.operator {
+            Operator::Time | Operator::Position 
+            | Operator::Variable(_) | Operator::Velocity(_) => false,
+            _ => self.args.iter().all(Node::is_constant),
+        };
+    }
+}